@@ -10,7 +10,6 @@ pub struct EspoResponse<T> {
 
 #[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-
 pub struct EspoContact {
     pub account_id: Option<String>,
     pub name:       String,
@@ -38,7 +37,7 @@ pub async fn get_contacts(config: &Config, offset: Option<i64>) -> crate::Result
         .build();
 
     let params = Params::new()
-        .set_offset(offset.unwrap_or_else(|| 0i64))
+        .set_offset(offset.unwrap_or(0i64))
         .set_select("id,name,accounts")
         .build();
 
@@ -52,7 +51,7 @@ pub async fn get_contacts(config: &Config, offset: Option<i64>) -> crate::Result
     let mut results = Vec::new();
 
     if response_data.list.len() == 200 {
-        results.append(&mut get_contacts(config, Some(offset.unwrap_or_else(|| 0i64) + 200)).await?);
+        results.append(&mut get_contacts(config, Some(offset.unwrap_or(0i64) + 200)).await?);
     }
 
     results.append(&mut response_data.list);
@@ -73,7 +72,7 @@ pub async fn get_accounts(config: &Config, offset: Option<i64>) -> crate::Result
         .build();
 
     let params = Params::new()
-        .set_offset(offset.unwrap_or_else(|| 0i64))
+        .set_offset(offset.unwrap_or(0i64))
         .set_select("id,name,contacts,billingAddressCity,billingAddressCountry,billingAddressPostalCode,billingAddressStreet")
         .set_where(vec![
             Where {
@@ -128,11 +127,10 @@ pub async fn get_accounts(config: &Config, offset: Option<i64>) -> crate::Result
 
     let mut results = Vec::new();
     if response_data.list.len() == 200 {
-        results.append(&mut get_accounts(config, Some(offset.unwrap_or_else(|| 0i64) + 200i64)).await?);
+        results.append(&mut get_accounts(config, Some(offset.unwrap_or(0i64) + 200i64)).await?);
     }
 
     results.append(&mut response_data.list);
 
     Ok(results)
 }
-
@@ -2,23 +2,40 @@ use serde::{Serialize, Deserialize};
 use crate::appdata::Config;
 use hmac::{Hmac, NewMac, Mac};
 use sha2::Sha256;
+use mysql::Row;
+use crate::money::Money;
+use crate::calculation::{calculate, Totals};
 
 type HmacSha256 = Hmac<Sha256>;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PdfCommonPayload {
-    pub template_name:  String,
-    pub language:       String,
-    pub id:             i64,
-    pub attention_of:   Option<String>,
-    pub receiver:       String,
-    pub reference:      String,
-    pub notes:          Option<String>,
-    pub expiry_date:    i64,
-    pub creation_date:  i64,
-    pub rows:           Vec<ItemRow>,
-    pub address:        Address
+    pub template_name:          String,
+    pub language:               String,
+    /// Assigned by the server from the document's number sequence when the document is created
+    #[serde(default)]
+    pub id:                     i64,
+    pub attention_of:           Option<String>,
+    pub receiver:               String,
+    pub reference:              String,
+    pub notes:                  Option<String>,
+    pub expiry_date:            i64,
+    pub creation_date:          i64,
+    pub rows:                   Vec<ItemRow>,
+    pub address:                Address,
+    /// ISO 4217 code, the base currency when not set
+    #[serde(default)]
+    pub currency:               Option<String>,
+    /// Units of `currency` per unit of the base currency on the creation date. Assigned by the server
+    #[serde(default)]
+    pub exchange_rate:          Option<Money>,
+    /// Peppol participant identifier of the receiver, `scheme:identifier`
+    #[serde(default)]
+    pub electronic_address:     Option<String>,
+    /// Intra-EU supply to a business, invoiced at 0% with the VAT reverse charged. Assigned by the server
+    #[serde(default)]
+    pub reverse_charge:         bool
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -28,7 +45,19 @@ pub struct PdfQuotePayload {
     pub common:                 PdfCommonPayload,
     pub quote_topic:            String,
     pub quote_contact_person:   String,
-    pub debit_id:               String
+    pub debit_id:               String,
+    /// Quotes keep their number when revised, revision 0 is the original quote
+    #[serde(default)]
+    pub revision:               i32
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfCreditNotePayload {
+    #[serde(flatten)]
+    pub common:                 PdfCommonPayload,
+    /// The ID of the invoice this credit note corrects
+    pub invoice_id:             i64
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -38,19 +67,114 @@ pub struct ItemRow {
     pub id:             String,
     pub name:           String,
     pub description:    String,
-    pub discount_perc:  Option<f64>,
-    pub vat_perc:       f64,
-    pub price:          f64,
+    pub discount_perc:  Option<Money>,
+    pub vat_perc:       Money,
+    pub price:          Money,
     pub quantity:       i64
 }
 
+impl PdfCommonPayload {
+    /**
+    Build the common part of a document from a row of the invoices, quotes or credit_notes table
+    */
+    pub fn from_row(row: &Row, rows: Vec<ItemRow>) -> Self {
+        Self {
+            id: row.get("id").unwrap(),
+            template_name: row.get("template_name").unwrap(),
+            language: row.get("language").unwrap(),
+            attention_of: row.get::<Option<String>, &str>("attention_of").unwrap(),
+            receiver: row.get("receiver").unwrap(),
+            reference: row.get("reference").unwrap(),
+            notes: row.get::<Option<String>, &str>("notes").unwrap(),
+            expiry_date: row.get("expiry_date").unwrap(),
+            creation_date: row.get("creation_date").unwrap(),
+            address: Address {
+                city: row.get("city").unwrap(),
+                country: row.get("country").unwrap(),
+                postal_code: row.get("postal_code").unwrap(),
+                street: row.get("street").unwrap(),
+                vat_number: row.get::<Option<String>, &str>("vat_number").unwrap()
+            },
+            currency: row.get("currency").unwrap(),
+            exchange_rate: row.get("exchange_rate").unwrap(),
+            electronic_address: row.get::<Option<String>, &str>("electronic_address").unwrap(),
+            reverse_charge: row.get("reverse_charge").unwrap(),
+            rows
+        }
+    }
+}
+
+impl ItemRow {
+    /**
+    Build an ItemRow from a row of the itemrows table
+    */
+    pub fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get("product_id").unwrap(),
+            name: row.get("name").unwrap(),
+            comment: row.get::<Option<String>, &str>("comment").unwrap(),
+            description: row.get("description").unwrap(),
+            discount_perc: row.get::<Option<Money>, &str>("discount_perc").unwrap(),
+            vat_perc: row.get("vat_perc").unwrap(),
+            price: row.get("price").unwrap(),
+            quantity: row.get("quantity").unwrap()
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Address {
     pub city:           String,
     pub country:        String,
     pub postal_code:    String,
-    pub street:         String
+    pub street:         String,
+    /// VAT number of the receiver with its country prefix, e.g. `DE123456788`
+    #[serde(default)]
+    pub vat_number:     Option<String>
+}
+
+/**
+A document as sent to the PDF service, with the totals calculated by Invoicr so the PDF never computes its own.
+Amounts and percentages are decimal strings, e.g. `"12.50"`, and have to be printed as given rather than parsed to floats.
+The seller's VAT number and the legal note for reverse charge have to be printed on the document
+*/
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WithTotals<'a, T: Serialize> {
+    #[serde(flatten)]
+    payload:            &'a T,
+    totals:             Totals,
+    seller_vat_number:  &'a str,
+    legal_note:         Option<String>
+}
+
+impl<'a, T: Serialize> WithTotals<'a, T> {
+    fn new(config: &'a Config, payload: &'a T, common: &PdfCommonPayload) -> Self {
+        Self {
+            payload,
+            totals: calculate(&common.rows, config.rounding),
+            seller_vat_number: &config.seller.vat_number,
+            legal_note: legal_note(common)
+        }
+    }
+}
+
+/**
+The note a reverse charged invoice must carry, in the language of the document. Invoicr does not know whether
+a row is a supply of goods or of services, so the note does not name the article it falls under
+*/
+fn legal_note(payload: &PdfCommonPayload) -> Option<String> {
+    if !payload.reverse_charge {
+        return None;
+    }
+
+    let vat_number = payload.address.vat_number.as_deref().unwrap_or_default();
+    if payload.language.to_lowercase().starts_with("nl") {
+        Some(format!("BTW verlegd. Btw-nummer afnemer: {}", vat_number))
+    } else {
+        Some(format!("VAT reverse charged. Customer VAT number: {}", vat_number))
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -60,46 +184,39 @@ pub struct PdfGenerationResponse {
 }
 
 pub async fn generate_invoice(config: &Config, payload: &PdfCommonPayload) -> crate::Result<String> {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let _guard = rt.enter();
-    const PATH: &str = "generate/invoice";
-
-    let client = reqwest::Client::new();
-    let res = client.post(&format!("{}/{}", &config.invoicr_pdf_host, PATH))
-        .json(payload)
-        .header("X-Hmac-Authorization", get_hmac(config, "POST", PATH)?)
-        .send()
-        .await;
+    request_generation(config, "generate/invoice", &WithTotals::new(config, payload, payload)).await
+}
 
-    let result: reqwest::Result<PdfGenerationResponse>  = match res {
-        Ok(response) => response.json().await,
-        Err(err) => return Err(err.to_string())
-    };
+pub async fn generate_quote(config: &Config, payload: &PdfQuotePayload) -> crate::Result<String> {
+    request_generation(config, "generate/quote", &WithTotals::new(config, payload, &payload.common)).await
+}
 
-    let id = match result {
-        Ok(result) => {
-            if result.id.is_some() {
-                result.id.unwrap()
-            } else {
-                return Err(result.error.unwrap());
-            }
-        }
-        Err(err) => return Err(err.to_string())
-    };
+pub async fn generate_credit_note(config: &Config, payload: &PdfCreditNotePayload) -> crate::Result<String> {
+    request_generation(config, "generate/creditnote", &WithTotals::new(config, payload, &payload.common)).await
+}
 
-    rt.shutdown_background();
-    Ok(id)
+/**
+The ID of the generated PDF, or the error the PDF service gave instead
+*/
+fn pdf_id(response: PdfGenerationResponse) -> crate::Result<String> {
+    match (response.id, response.error) {
+        (Some(id), _) => Ok(id),
+        (None, Some(error)) => Err(error),
+        (None, None) => Err("The PDF service returned neither a PDF nor an error".to_string())
+    }
 }
 
-pub async fn generate_quote(config: &Config, payload: &PdfQuotePayload) -> crate::Result<String> {
+/**
+Send a signed generation request to the PDF service, returning the ID of the generated PDF
+*/
+async fn request_generation<T: Serialize>(config: &Config, path: &str, body: &T) -> crate::Result<String> {
     let rt = tokio::runtime::Runtime::new().unwrap();
     let _guard = rt.enter();
-    const PATH: &str = "generate/quote";
 
     let client = reqwest::Client::new();
-    let res = client.post(&format!("{}/{}", &config.invoicr_pdf_host, PATH))
-        .json(&payload)
-        .header("X-Hmac-Authorization", get_hmac(config, "POST", PATH)?)
+    let res = client.post(format!("{}/{}", &config.invoicr_pdf_host, path))
+        .json(body)
+        .header("X-Hmac-Authorization", get_hmac(config, "POST", path)?)
         .send()
         .await;
 
@@ -109,13 +226,7 @@ pub async fn generate_quote(config: &Config, payload: &PdfQuotePayload) -> crate
     };
 
     let id = match result {
-        Ok(result) => {
-            if result.id.is_some() {
-                result.id.unwrap()
-            } else {
-                return Err(result.error.unwrap());
-            }
-        },
+        Ok(result) => pdf_id(result)?,
         Err(err) => return Err(err.to_string())
     };
 
@@ -139,10 +250,33 @@ fn get_hmac<A, B>(config: &Config, method: A, path: B) -> crate::Result<String>
     let mac_result = mac.finalize().into_bytes();
 
     let hmac_string = format!("{}{}{}",
-        base64::encode(&config.invoicr_pdf_key.as_bytes()),
+        base64::encode(config.invoicr_pdf_key.as_bytes()),
         ":",
         base64::encode(mac_result)
     );
 
     Ok(hmac_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_generation_returns_the_error() {
+        assert_eq!(pdf_id(PdfGenerationResponse { id: Some("pdf".to_string()), error: None }), Ok("pdf".to_string()));
+        assert_eq!(pdf_id(PdfGenerationResponse { id: None, error: Some("Template not found".to_string()) }), Err("Template not found".to_string()));
+        assert!(pdf_id(PdfGenerationResponse { id: None, error: None }).is_err());
+    }
+
+    #[test]
+    fn unreachable_pdf_service_is_an_error() {
+        let config = Config { invoicr_pdf_host: "http://127.0.0.1:9".to_string(), invoicr_pdf_secret: "secret".to_string(), ..Config::default() };
+        let invoice: PdfCommonPayload = serde_json::from_value(serde_json::json!({
+            "templateName": "default", "language": "en", "id": 20240001, "attentionOf": null, "receiver": "Acme GmbH", "reference": "Stickers", "notes": null,
+            "expiryDate": 0, "creationDate": 0, "rows": [], "address": { "city": "Berlin", "country": "Germany", "postalCode": "10115", "street": "Main 1" }
+        })).unwrap();
+
+        assert!(futures::executor::block_on(generate_invoice(&config, &invoice)).is_err());
+    }
 }
\ No newline at end of file
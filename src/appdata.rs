@@ -1,10 +1,8 @@
 use std::path::PathBuf;
 use serde::{Serialize, Deserialize};
-use mysql::prelude::Queryable;
-use mysql::{Row, Params, params};
-use std::collections::HashMap;
 use std::sync::mpsc::Sender;
 use crate::threads::espocrm::Communication;
+use crate::money::Rounding;
 
 #[derive(Clone)]
 pub struct AppData {
@@ -15,16 +13,127 @@ pub struct AppData {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub mysql_host:         String,
-    pub mysql_database:     String,
-    pub mysql_username:     String,
-    pub mysql_password:     String,
-    pub espocrm_host:       String,
-    pub espocrm_api_key:    String,
-    pub espocrm_secret_key: String,
-    pub invoicr_pdf_host:   String,
-    pub invoicr_pdf_key:    String,
-    pub invoicr_pdf_secret: String
+    pub mysql_host:                String,
+    pub mysql_database:            String,
+    pub mysql_username:            String,
+    pub mysql_password:            String,
+    pub espocrm_host:              String,
+    pub espocrm_api_key:           String,
+    pub espocrm_secret_key:        String,
+    pub invoicr_pdf_host:          String,
+    pub invoicr_pdf_key:           String,
+    pub invoicr_pdf_secret:        String,
+    #[serde(default = "default_invoice_number_format")]
+    pub invoice_number_format:     String,
+    #[serde(default = "default_quote_number_format")]
+    pub quote_number_format:       String,
+    #[serde(default = "default_credit_note_number_format")]
+    pub credit_note_number_format: String,
+    #[serde(default)]
+    pub quote_token_secret:        String,
+    #[serde(default)]
+    pub quote_response_url:        String,
+    /// Round every line or only the total of a document
+    #[serde(default)]
+    pub rounding:                  Rounding,
+    /// Currency amounts are reported in, and exchange rates are relative to
+    #[serde(default = "default_base_currency")]
+    pub base_currency:             String,
+    /// Details of our own company, used in e-invoices
+    #[serde(default)]
+    pub seller:                    Seller,
+    /// General ledger accounts documents are booked on in the auditfile
+    #[serde(default)]
+    pub ledger_accounts:           LedgerAccounts
+}
+
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct Seller {
+    pub name:               String,
+    pub street:             String,
+    pub city:               String,
+    pub postal_code:        String,
+    /// ISO 3166-1 alpha-2 code
+    pub country:            String,
+    pub vat_number:         String,
+    /// Chamber of Commerce number
+    #[serde(default)]
+    pub company_id:         String,
+    #[serde(default)]
+    pub iban:               String,
+    #[serde(default)]
+    pub email:              String,
+    /// Peppol participant identifier, e.g. `0106:12345678` for a Dutch KvK number
+    #[serde(default)]
+    pub electronic_address: String
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LedgerAccounts {
+    pub receivables:        String,
+    pub revenue:            String,
+    pub vat_payable:        String,
+    pub bank:               String
+}
+
+impl Default for LedgerAccounts {
+    fn default() -> Self {
+        Self {
+            receivables: "1300".to_string(),
+            revenue: "8000".to_string(),
+            vat_payable: "1500".to_string(),
+            bank: "1100".to_string()
+        }
+    }
+}
+
+impl LedgerAccounts {
+    fn read_from_env() -> Self {
+        use std::env::var;
+
+        let default = Self::default();
+        Self {
+            receivables: var("LEDGER_RECEIVABLES").unwrap_or(default.receivables),
+            revenue: var("LEDGER_REVENUE").unwrap_or(default.revenue),
+            vat_payable: var("LEDGER_VAT_PAYABLE").unwrap_or(default.vat_payable),
+            bank: var("LEDGER_BANK").unwrap_or(default.bank)
+        }
+    }
+}
+
+impl Seller {
+    fn read_from_env() -> Self {
+        use std::env::var;
+
+        Self {
+            name: var("SELLER_NAME").unwrap_or_default(),
+            street: var("SELLER_STREET").unwrap_or_default(),
+            city: var("SELLER_CITY").unwrap_or_default(),
+            postal_code: var("SELLER_POSTAL_CODE").unwrap_or_default(),
+            country: var("SELLER_COUNTRY").unwrap_or_default(),
+            vat_number: var("SELLER_VAT_NUMBER").unwrap_or_default(),
+            company_id: var("SELLER_COMPANY_ID").unwrap_or_default(),
+            iban: var("SELLER_IBAN").unwrap_or_default(),
+            email: var("SELLER_EMAIL").unwrap_or_default(),
+            electronic_address: var("SELLER_ELECTRONIC_ADDRESS").unwrap_or_default()
+        }
+    }
+}
+
+fn default_invoice_number_format() -> String {
+    "{year}{seq:04}".to_string()
+}
+
+fn default_quote_number_format() -> String {
+    "{year}{seq:04}".to_string()
+}
+
+fn default_credit_note_number_format() -> String {
+    "9{year}{seq:04}".to_string()
+}
+
+fn default_base_currency() -> String {
+    "EUR".to_string()
 }
 
 impl Default for Config {
@@ -39,7 +148,27 @@ impl Default for Config {
             espocrm_secret_key: "espocrm_secret_key".to_string(),
             invoicr_pdf_host: "invoicr_pdf_host".to_string(),
             invoicr_pdf_key: "your_invoicr_pdf_key".to_string(),
-            invoicr_pdf_secret: "your_invoicr_pdf_secret".to_string()
+            invoicr_pdf_secret: "your_invoicr_pdf_secret".to_string(),
+            invoice_number_format: default_invoice_number_format(),
+            quote_number_format: default_quote_number_format(),
+            credit_note_number_format: default_credit_note_number_format(),
+            quote_token_secret: "your_quote_token_secret".to_string(),
+            quote_response_url: "https://invoicr.example.com/quote/respond".to_string(),
+            rounding: Rounding::default(),
+            base_currency: default_base_currency(),
+            seller: Seller {
+                name: "Example B.V.".to_string(),
+                street: "Examplestreet 1".to_string(),
+                city: "Amsterdam".to_string(),
+                postal_code: "1000 AA".to_string(),
+                country: "NL".to_string(),
+                vat_number: "NL000000000B01".to_string(),
+                company_id: "12345678".to_string(),
+                iban: "NL00BANK0123456789".to_string(),
+                email: "billing@example.com".to_string(),
+                electronic_address: "0106:12345678".to_string()
+            },
+            ledger_accounts: LedgerAccounts::default()
         }
     }
 }
@@ -143,6 +272,17 @@ impl Config {
             std::process::exit(1);
         }
 
+        let rounding = match var("ROUNDING") {
+            Ok(rounding) => match rounding.parse::<Rounding>() {
+                Ok(rounding) => rounding,
+                Err(err) => {
+                    eprintln!("Environmental variable 'ROUNDING' is invalid: {}. Exiting.", err);
+                    std::process::exit(1);
+                }
+            },
+            Err(_) => Rounding::default()
+        };
+
         Self {
             mysql_host: mysql_host.unwrap(),
             mysql_database: mysql_database.unwrap(),
@@ -153,7 +293,16 @@ impl Config {
             espocrm_secret_key: espocrm_secret_key.unwrap(),
             invoicr_pdf_host: invoicr_pdf_host.unwrap(),
             invoicr_pdf_key: invoicr_pdf_key.unwrap(),
-            invoicr_pdf_secret: invoicr_pdf_secret.unwrap()
+            invoicr_pdf_secret: invoicr_pdf_secret.unwrap(),
+            invoice_number_format: var("INVOICE_NUMBER_FORMAT").unwrap_or_else(|_| default_invoice_number_format()),
+            quote_number_format: var("QUOTE_NUMBER_FORMAT").unwrap_or_else(|_| default_quote_number_format()),
+            credit_note_number_format: var("CREDIT_NOTE_NUMBER_FORMAT").unwrap_or_else(|_| default_credit_note_number_format()),
+            quote_token_secret: var("QUOTE_TOKEN_SECRET").unwrap_or_default(),
+            quote_response_url: var("QUOTE_RESPONSE_URL").unwrap_or_default(),
+            rounding,
+            base_currency: var("BASE_CURRENCY").unwrap_or_else(|_| default_base_currency()),
+            seller: Seller::read_from_env(),
+            ledger_accounts: LedgerAccounts::read_from_env()
         }
     }
 }
@@ -179,44 +328,4 @@ impl AppData {
             espocrm_data: crate::threads::espocrm::start(config.clone()).unwrap()
         }
     }
-
-    pub fn check_db(&self) -> bool {
-        let mut conn = self.pool.get_conn().expect("Unable to create database connection");
-        let sql_get_tables = conn.exec::<Row, &str, Params>("SELECT table_name FROM INFORMATION_SCHEMA.TABLES WHERE TABLE_SCHEMA = :table_schema", params!{
-           "table_schema" => self.config.mysql_database.clone()
-        }).expect("Unable to fetch tables from the database");
-
-        let mut required_tables_map = HashMap::new();
-        required_tables_map.insert("products".to_string(), false);
-        required_tables_map.insert("invoices".to_string(), false);
-        required_tables_map.insert("quotes".to_string(), false);
-
-        for row in sql_get_tables {
-            let table_name = row.get::<String, &str>("table_name").expect("Unable to get table_name from row.");
-            required_tables_map.insert(table_name, true);
-        }
-
-        let mut table_missing = false;
-        for (k, v) in required_tables_map {
-            if v == false {
-                println!("Missing required table {}", k);
-                table_missing = true;
-            }
-        }
-
-        !table_missing
-    }
-
-    pub fn init_db(&self) {
-        let mut conn = self.pool.get_conn().expect("Unable to create database connection.");
-
-        conn.query::<usize, &str>("CREATE TABLE `products` (`id` varchar(32) NOT NULL, `name` varchar(255) NOT NULL, `description` text NOT NULL, `price` double NOT NULL, PRIMARY KEY (`id`), KEY `name` (`name`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4").expect("Unable to create table 'products'");
-        println!("Created table 'products'");
-
-        conn.query::<usize, &str>("invoices | CREATE TABLE `invoices` ( `id` bigint(64) NOT NULL, `template_name` varchar(255) NOT NULL, `language` varchar(255) NOT NULL, `attention_of` varchar(255) DEFAULT NULL, `receiver` varchar(255) NOT NULL, `reference` text NOT NULL, `notes` text DEFAULT NULL, `expiry_date` bigint(20) NOT NULL, `creation_date` bigint(20) NOT NULL, `city` varchar(255) NOT NULL, `country` varchar(255) NOT NULL, `postal_code` varchar(255) NOT NULL, `street` varchar(255) NOT NULL, PRIMARY KEY (`id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4").expect("Unable to create table 'invoices'");
-        println!("Created table 'invoices'");
-
-        conn.query::<usize, &str>("quotes | CREATE TABLE `quotes` (  `id` bigint(64) NOT NULL,  `template_name` varchar(255) NOT NULL,  `language` varchar(255) NOT NULL,  `attention_of` varchar(255) DEFAULT NULL,  `receiver` varchar(255) NOT NULL,  `reference` text NOT NULL,  `notes` text DEFAULT NULL,  `expiry_date` bigint(20) NOT NULL,  `creation_date` bigint(20) NOT NULL,  `city` varchar(255) NOT NULL,  `country` varchar(255) NOT NULL,  `postal_code` varchar(255) NOT NULL,  `street` varchar(255) NOT NULL,  `quote_topic` varchar(255) NOT NULL,  `quote_contact_person` varchar(255) NOT NULL,  `debit_id` varchar(255) NOT NULL,  PRIMARY KEY (`id`) ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4").expect("Unable to create table 'quotes'");
-        println!("Created table 'quotes'");
-    }
 }
\ No newline at end of file
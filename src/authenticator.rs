@@ -7,10 +7,12 @@ pub async fn check_permission(_req: &ServiceRequest) -> Result<Vec<String>, acti
     Ok(vec![
         "INVOICE_CREATE".to_string(),
         "INVOICE_READ".to_string(),
+        "INVOICE_UPDATE".to_string(),
         "QUOTE_CREATE".to_string(),
         "QUOTE_READ".to_string(),
         "PERSONS_READ".to_string(),
         "PRODUCTS_READ".to_string(),
-        "PRODUCTS_WRITE".to_string()
+        "PRODUCTS_WRITE".to_string(),
+        "EXCHANGE_RATES_WRITE".to_string()
     ])
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::apis::pdf::ItemRow;
+use crate::money::{self, Money, Rounding};
+
+/**
+Amounts of a single row
+*/
+#[derive(Serialize, Clone)]
+pub struct LineTotal {
+    /// The product ID of the row
+    pub id:         String,
+    /// Price times quantity
+    pub gross:      Money,
+    pub discount:   Money,
+    /// Gross minus discount, excluding VAT
+    pub net:        Money,
+    pub vat_perc:   Money,
+    pub vat:        Money,
+    pub total:      Money
+}
+
+/**
+Taxable amount and VAT of all rows sharing a VAT rate
+*/
+#[derive(Serialize, Clone)]
+pub struct VatTotal {
+    pub vat_perc:   Money,
+    pub base:       Money,
+    pub vat:        Money
+}
+
+/**
+Totals of a document. Line amounts are only rounded with `Rounding::Line`, the other amounts are always rounded to cents
+*/
+#[derive(Serialize, Clone)]
+pub struct Totals {
+    pub lines:      Vec<LineTotal>,
+    pub gross:      Money,
+    pub discount:   Money,
+    pub net:        Money,
+    /// Breakdown per VAT rate, lowest rate first
+    pub vat_rates:  Vec<VatTotal>,
+    pub vat:        Money,
+    pub total:      Money
+}
+
+/**
+Calculate the line totals, the VAT breakdown and the grand total of a set of rows.
+
+With `Rounding::Line` the net amount and VAT of every line are rounded and then summed.
+With `Rounding::Total` the unrounded net amounts are summed per VAT rate, and only the base and VAT of each rate are rounded
+*/
+pub fn calculate(rows: &[ItemRow], rounding: Rounding) -> Totals {
+    let line_round = |amount: Money| match rounding {
+        Rounding::Line => money::round(amount),
+        Rounding::Total => amount
+    };
+
+    let lines: Vec<LineTotal> = rows.iter()
+        .map(|row| {
+            let gross = row.price * Money::from(row.quantity);
+            let discount = line_round(money::percentage(gross, row.discount_perc.unwrap_or_default()));
+            let net = line_round(gross) - discount;
+            let vat = line_round(money::percentage(net, row.vat_perc));
+
+            LineTotal {
+                id: row.id.clone(),
+                gross: line_round(gross),
+                discount,
+                net,
+                vat_perc: row.vat_perc.normalize(),
+                vat,
+                total: net + vat
+            }
+        })
+        .collect();
+
+    //Keyed on the normalized rate, so 21 and 21.00 end up together
+    let mut per_rate: BTreeMap<Money, (Money, Money)> = BTreeMap::new();
+    for line in &lines {
+        let (base, vat) = per_rate.entry(line.vat_perc).or_default();
+        *base += line.net;
+        *vat += line.vat;
+    }
+
+    let vat_rates: Vec<VatTotal> = per_rate.into_iter()
+        .map(|(vat_perc, (base, vat))| match rounding {
+            Rounding::Line => VatTotal { vat_perc, base, vat },
+            Rounding::Total => VatTotal { vat_perc, base: money::round(base), vat: money::round(money::percentage(base, vat_perc)) }
+        })
+        .collect();
+
+    let net = vat_rates.iter().map(|rate| rate.base).sum();
+    let vat = vat_rates.iter().map(|rate| rate.vat).sum();
+
+    Totals {
+        gross: money::round(lines.iter().map(|line| line.gross).sum()),
+        discount: money::round(lines.iter().map(|line| line.discount).sum()),
+        net,
+        vat,
+        total: net + vat,
+        vat_rates,
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    fn row(price: &str, quantity: i64, discount_perc: Option<&str>, vat_perc: &str) -> ItemRow {
+        ItemRow {
+            comment: None,
+            id: "P1".to_string(),
+            name: "Product".to_string(),
+            description: String::new(),
+            discount_perc: discount_perc.map(money),
+            vat_perc: money(vat_perc),
+            price: money(price),
+            quantity
+        }
+    }
+
+    #[test]
+    fn line_rounding_rounds_every_line() {
+        let rows = vec![row("0.333", 1, None, "21"), row("0.333", 1, None, "21"), row("0.333", 1, None, "21")];
+        let totals = calculate(&rows, Rounding::Line);
+
+        assert_eq!(totals.lines[0].net, money("0.33"));
+        assert_eq!(totals.lines[0].vat, money("0.07"));
+        assert_eq!(totals.net, money("0.99"));
+        assert_eq!(totals.vat, money("0.21"));
+        assert_eq!(totals.total, money("1.20"));
+    }
+
+    #[test]
+    fn total_rounding_rounds_per_rate() {
+        let rows = vec![row("0.333", 1, None, "21"), row("0.333", 1, None, "21"), row("0.333", 1, None, "21")];
+        let totals = calculate(&rows, Rounding::Total);
+
+        assert_eq!(totals.lines[0].net, money("0.333"));
+        assert_eq!(totals.net, money("1.00"));
+        assert_eq!(totals.vat, money("0.21"));
+        assert_eq!(totals.total, money("1.21"));
+    }
+
+    #[test]
+    fn discount_is_taken_before_vat() {
+        let totals = calculate(&[row("10", 3, Some("15"), "21")], Rounding::Line);
+
+        assert_eq!(totals.gross, money("30"));
+        assert_eq!(totals.discount, money("4.50"));
+        assert_eq!(totals.net, money("25.50"));
+        assert_eq!(totals.vat, money("5.36"));
+        assert_eq!(totals.total, money("30.86"));
+    }
+
+    #[test]
+    fn vat_is_broken_down_per_rate() {
+        let rows = vec![row("100", 1, None, "21"), row("50", 2, None, "9"), row("10", 1, None, "21.00")];
+
+        for rounding in [Rounding::Line, Rounding::Total] {
+            let totals = calculate(&rows, rounding);
+            let rates: Vec<_> = totals.vat_rates.iter().map(|rate| (rate.vat_perc, rate.base, rate.vat)).collect();
+
+            assert_eq!(rates, [(money("9"), money("100"), money("9")), (money("21"), money("110"), money("23.10"))]);
+            assert_eq!(totals.total, money("242.10"));
+        }
+    }
+
+    #[test]
+    fn no_rows_add_up_to_zero() {
+        let totals = calculate(&[], Rounding::Line);
+
+        assert!(totals.vat_rates.is_empty());
+        assert_eq!(totals.total, Money::ZERO);
+    }
+}
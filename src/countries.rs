@@ -0,0 +1,61 @@
+/**
+Countries by ISO 3166-1 alpha-2 code, with the names they are commonly written as in addresses
+*/
+const COUNTRIES: &[(&str, &[&str])] = &[
+    ("AT", &["austria", "oostenrijk", "österreich", "osterreich", "autriche"]),
+    ("BE", &["belgium", "belgië", "belgie", "belgique", "belgien"]),
+    ("BG", &["bulgaria", "bulgarije", "bulgarien", "bulgarie"]),
+    ("HR", &["croatia", "kroatië", "kroatie", "kroatien", "croatie", "hrvatska"]),
+    ("CY", &["cyprus", "zypern", "chypre"]),
+    ("CZ", &["czech republic", "czechia", "tsjechië", "tsjechie", "tschechien", "république tchèque", "česko"]),
+    ("DK", &["denmark", "denemarken", "dänemark", "danemark", "danmark"]),
+    ("EE", &["estonia", "estland", "estonie", "eesti"]),
+    ("FI", &["finland", "finnland", "finlande", "suomi"]),
+    ("FR", &["france", "frankrijk", "frankreich"]),
+    ("DE", &["germany", "duitsland", "deutschland", "allemagne"]),
+    ("GR", &["greece", "griekenland", "griechenland", "grèce", "ελλάδα"]),
+    ("HU", &["hungary", "hongarije", "ungarn", "hongrie", "magyarország"]),
+    ("IE", &["ireland", "ierland", "irland", "irlande", "éire"]),
+    ("IT", &["italy", "italië", "italie", "italien", "italia"]),
+    ("LV", &["latvia", "letland", "lettland", "lettonie", "latvija"]),
+    ("LT", &["lithuania", "litouwen", "litauen", "lituanie", "lietuva"]),
+    ("LU", &["luxembourg", "luxemburg"]),
+    ("MT", &["malta", "malte"]),
+    ("NL", &["netherlands", "the netherlands", "nederland", "holland", "niederlande", "pays-bas"]),
+    ("PL", &["poland", "polen", "pologne", "polska"]),
+    ("PT", &["portugal"]),
+    ("RO", &["romania", "roemenië", "roemenie", "rumänien", "roumanie", "românia"]),
+    ("SK", &["slovakia", "slowakije", "slowakei", "slovaquie", "slovensko"]),
+    ("SI", &["slovenia", "slovenië", "slovenie", "slowenien", "slovénie", "slovenija"]),
+    ("ES", &["spain", "spanje", "spanien", "espagne", "españa", "espana"]),
+    ("SE", &["sweden", "zweden", "schweden", "suède", "sverige"]),
+    ("GB", &["united kingdom", "uk", "great britain", "verenigd koninkrijk", "england", "engeland", "großbritannien", "royaume-uni"]),
+    ("CH", &["switzerland", "zwitserland", "schweiz", "suisse", "svizzera"]),
+    ("NO", &["norway", "noorwegen", "norwegen", "norvège", "norge"]),
+    ("US", &["united states", "united states of america", "usa", "verenigde staten", "vereinigte staaten", "états-unis"])
+];
+
+/**
+Resolve a country as written in an address, either a name or an ISO code, to its ISO 3166-1 alpha-2 code
+*/
+pub fn iso_code(country: &str) -> Option<&'static str> {
+    let country = country.trim();
+    if let Some((code, _)) = COUNTRIES.iter().find(|(code, _)| code.eq_ignore_ascii_case(country)) {
+        return Some(code);
+    }
+
+    let country = country.to_lowercase();
+    COUNTRIES.iter()
+        .find(|(_, names)| names.contains(&country.as_str()))
+        .map(|(code, _)| *code)
+}
+
+/// Member states of the European Union
+const EU: &[&str] = &["AT", "BE", "BG", "HR", "CY", "CZ", "DK", "EE", "FI", "FR", "DE", "GR", "HU", "IE", "IT", "LV", "LT", "LU", "MT", "NL", "PL", "PT", "RO", "SK", "SI", "ES", "SE"];
+
+/**
+Whether an ISO 3166-1 alpha-2 code is an EU member state
+*/
+pub fn is_eu(code: &str) -> bool {
+    EU.contains(&code)
+}
@@ -0,0 +1,44 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Deserialize;
+use crate::AppData;
+
+#[derive(Deserialize)]
+pub struct Query {
+    /// First day of the range, `YYYY-MM-DD`
+    from:   String,
+    /// Last day of the range, inclusive
+    to:     String
+}
+
+/**
+Download an XAF 3.2 auditfile (Auditfile Financieel) for a date range
+*/
+#[get("/auditfile/xaf")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_xaf(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let (from, to) = match crate::xaf::parse_range(&query.from, &query.to) {
+        Ok(range) => range,
+        Err(err) => return HttpResponse::BadRequest().body(err)
+    };
+
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match crate::xaf::build(&mut conn, &data.config, from, to) {
+        Ok(auditfile) => HttpResponse::Ok()
+            .content_type("application/xml")
+            .header("Content-Disposition", format!("attachment; filename=\"auditfile-{}-{}.xaf\"", from, to))
+            .body(auditfile),
+        Err(err) => {
+            eprintln!("Failed to build auditfile: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_any_permission;
+use crate::AppData;
+use crate::apis::pdf::PdfCommonPayload;
+use crate::calculation::calculate;
+
+/**
+Preview the totals of a document without storing it
+*/
+#[post("/calculate")]
+#[has_any_permission("INVOICE_CREATE", "QUOTE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn calculate_totals(data: web::Data<AppData>, payload: web::Json<PdfCommonPayload>) -> HttpResponse {
+    HttpResponse::Ok().json(calculate(&payload.rows, data.config.rounding))
+}
@@ -0,0 +1,52 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use crate::AppData;
+use crate::money::Money;
+use crate::endpoints::exchange_rates::{ExchangeRate, normalize_currency, insert_rates};
+
+#[derive(Deserialize)]
+pub struct Request {
+    rates: Vec<ExchangeRate>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    error: Option<String>
+}
+
+#[post("/exchangerates/add")]
+#[has_permissions("EXCHANGE_RATES_WRITE")]
+#[allow(clippy::async_yields_async)]
+pub async fn add_exchange_rates(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let mut rates = request.into_inner().rates;
+    for rate in rates.iter_mut() {
+        rate.currency = match normalize_currency(&rate.currency) {
+            Ok(currency) => currency,
+            Err(err) => return HttpResponse::BadRequest().json(Response { error: Some(err) })
+        };
+
+        if rate.currency == data.config.base_currency {
+            return HttpResponse::BadRequest().json(Response { error: Some(format!("{} is the base currency, its rate is always 1", rate.currency)) });
+        }
+
+        if rate.rate <= Money::ZERO {
+            return HttpResponse::BadRequest().json(Response { error: Some(format!("Rate for {} must be positive", rate.currency)) });
+        }
+    }
+
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(err) = insert_rates(&mut conn, &rates) {
+        eprintln!("Failed to insert exchange rates into the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(Response { error: None })
+}
@@ -0,0 +1,55 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, params};
+use crate::AppData;
+use crate::endpoints::exchange_rates::ExchangeRate;
+
+#[derive(Deserialize)]
+pub struct Query {
+    currency:   Option<String>,
+    from:       Option<i64>,
+    to:         Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    base_currency:  String,
+    rates:          Vec<ExchangeRate>
+}
+
+#[get("/exchangerates/get")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_exchange_rates(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_rates = conn.exec::<Row, &str, Params>("SELECT currency, rate_date, rate FROM exchange_rates \
+        WHERE (:currency IS NULL OR currency = :currency) AND (:from IS NULL OR rate_date >= :from) AND (:to IS NULL OR rate_date <= :to) \
+        ORDER BY currency ASC, rate_date ASC", params! {
+        "currency" => query.currency.as_ref().map(|currency| currency.to_uppercase()),
+        "from" => query.from,
+        "to" => query.to
+    });
+
+    let rates = match sql_get_rates {
+        Ok(rows) => rows.into_iter().map(|row| ExchangeRate {
+            currency: row.get("currency").unwrap(),
+            date: row.get("rate_date").unwrap(),
+            rate: row.get("rate").unwrap()
+        }).collect(),
+        Err(err) => {
+            eprintln!("Failed to query exchange rates from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok().json(Response { base_currency: data.config.base_currency.clone(), rates })
+}
@@ -0,0 +1,44 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Serialize;
+use crate::AppData;
+use crate::endpoints::exchange_rates::{parse_ecb, insert_rates};
+
+#[derive(Serialize)]
+pub struct Response {
+    imported:   usize,
+    error:      Option<String>
+}
+
+/**
+Import rates from an ECB reference rates XML file, such as eurofxref-daily.xml or eurofxref-hist-90d.xml
+*/
+#[post("/exchangerates/import")]
+#[has_permissions("EXCHANGE_RATES_WRITE")]
+#[allow(clippy::async_yields_async)]
+pub async fn import_exchange_rates(data: web::Data<AppData>, body: web::Bytes) -> HttpResponse {
+    let xml = match std::str::from_utf8(&body) {
+        Ok(xml) => xml,
+        Err(_) => return HttpResponse::BadRequest().json(Response { imported: 0, error: Some("File is not valid UTF-8".to_string()) })
+    };
+
+    let rates = match parse_ecb(xml, &data.config.base_currency) {
+        Ok(rates) => rates,
+        Err(err) => return HttpResponse::BadRequest().json(Response { imported: 0, error: Some(err) })
+    };
+
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(err) = insert_rates(&mut conn, &rates) {
+        eprintln!("Failed to insert exchange rates into the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(Response { imported: rates.len(), error: None })
+}
@@ -0,0 +1,193 @@
+pub mod get;
+pub mod add;
+pub mod import;
+
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Params, params};
+use std::collections::HashMap;
+use crate::appdata::Config;
+use crate::apis::pdf::PdfCommonPayload;
+use crate::money::Money;
+
+/**
+Exchange rate of a currency on a date, in units of the currency per unit of the base currency
+*/
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExchangeRate {
+    pub currency:   String,
+    /// UNIX timestamp of the day the rate takes effect
+    pub date:       i64,
+    pub rate:       Money
+}
+
+/**
+Check that a currency code consists of three letters, and return it in upper case
+*/
+pub fn normalize_currency(currency: &str) -> crate::Result<String> {
+    let currency = currency.trim().to_uppercase();
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("'{}' is not a valid ISO 4217 currency code", currency));
+    }
+
+    Ok(currency)
+}
+
+/**
+Insert exchange rates, replacing any existing rate for the same currency and date
+*/
+pub fn insert_rates<Q: Queryable>(conn: &mut Q, rates: &[ExchangeRate]) -> mysql::Result<()> {
+    conn.exec_batch("INSERT INTO exchange_rates (currency, rate_date, rate) VALUES (:currency, :rate_date, :rate) \
+        ON DUPLICATE KEY UPDATE rate = VALUES(rate)", rates.iter().map(|rate| params! {
+        "currency" => &rate.currency,
+        "rate_date" => rate.date,
+        "rate" => rate.rate
+    }))
+}
+
+/**
+Find the rate of a currency in effect on a date: the most recent rate on or before it.
+The base currency always has rate 1. `None` if no rate is known
+*/
+pub fn get_rate<Q: Queryable>(conn: &mut Q, config: &Config, currency: &str, date: i64) -> mysql::Result<Option<Money>> {
+    if currency == config.base_currency {
+        return Ok(Some(Money::ONE));
+    }
+
+    conn.exec_first::<Money, &str, Params>("SELECT rate FROM exchange_rates WHERE currency = :currency AND rate_date <= :date ORDER BY rate_date DESC LIMIT 1", params! {
+        "currency" => currency,
+        "date" => date
+    })
+}
+
+/**
+Set the currency of a document, defaulting to the base currency, and store the exchange rate in effect on its creation date.
+The inner error is meant for the client: the currency code is invalid or no rate is known for it
+*/
+pub fn snapshot_rate<Q: Queryable>(conn: &mut Q, config: &Config, payload: &mut PdfCommonPayload) -> mysql::Result<crate::Result<()>> {
+    let currency = match normalize_currency(payload.currency.as_deref().unwrap_or(&config.base_currency)) {
+        Ok(currency) => currency,
+        Err(err) => return Ok(Err(err))
+    };
+
+    payload.exchange_rate = get_rate(conn, config, &currency, payload.creation_date)?;
+    if payload.exchange_rate.is_none() {
+        return Ok(Err(format!("No exchange rate for {} is known on or before the creation date.", currency)));
+    }
+
+    payload.currency = Some(currency);
+    Ok(Ok(()))
+}
+
+/**
+Parse the daily or historic reference rates published by the ECB. The ECB quotes against the euro,
+rates are converted to the configured base currency when that is not the euro
+*/
+pub fn parse_ecb(xml: &str, base_currency: &str) -> crate::Result<Vec<ExchangeRate>> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| format!("Invalid XML: {}", e))?;
+
+    let mut rates = Vec::new();
+    for day in document.descendants().filter(|node| node.has_tag_name("Cube") && node.has_attribute("time")) {
+        let time = day.attribute("time").unwrap();
+        let date = chrono::NaiveDate::parse_from_str(time, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}'", time))?;
+
+        let mut per_euro = HashMap::new();
+        per_euro.insert("EUR".to_string(), Money::ONE);
+        for cube in day.children().filter(|node| node.has_tag_name("Cube")) {
+            let (currency, rate) = match (cube.attribute("currency"), cube.attribute("rate")) {
+                (Some(currency), Some(rate)) => (currency, rate),
+                _ => continue
+            };
+
+            let rate = match rate.parse::<Money>() {
+                Ok(rate) if rate > Money::ZERO => rate,
+                _ => return Err(format!("Invalid rate '{}' for {} on {}", rate, currency, time))
+            };
+            per_euro.insert(normalize_currency(currency)?, rate);
+        }
+
+        let base = match per_euro.get(base_currency) {
+            Some(base) => *base,
+            None => return Err(format!("The file has no rate for the base currency {} on {}", base_currency, time))
+        };
+
+        for (currency, rate) in &per_euro {
+            if currency == base_currency {
+                continue;
+            }
+
+            rates.push(ExchangeRate {
+                currency: currency.clone(),
+                date: date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp(),
+                rate: (*rate / base).round_dp(10)
+            });
+        }
+    }
+
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECB: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+    <gesmes:subject>Reference rates</gesmes:subject>
+    <Cube>
+        <Cube time="2024-03-01">
+            <Cube currency="USD" rate="1.0830"/>
+            <Cube currency="GBP" rate="0.8556"/>
+        </Cube>
+    </Cube>
+</gesmes:Envelope>"#;
+
+    fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    fn rate(rates: &[ExchangeRate], currency: &str) -> Option<Money> {
+        rates.iter().find(|rate| rate.currency == currency).map(|rate| rate.rate)
+    }
+
+    #[test]
+    fn parse_ecb_keeps_euro_rates() {
+        let rates = parse_ecb(ECB, "EUR").unwrap();
+
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rate(&rates, "USD"), Some(money("1.0830")));
+        assert_eq!(rate(&rates, "GBP"), Some(money("0.8556")));
+        assert_eq!(rates[0].date, 1709251200);
+    }
+
+    #[test]
+    fn parse_ecb_converts_to_another_base() {
+        let rates = parse_ecb(ECB, "USD").unwrap();
+
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rate(&rates, "USD"), None);
+        assert_eq!(rate(&rates, "EUR"), Some((Money::ONE / money("1.0830")).round_dp(10)));
+        assert_eq!(rate(&rates, "GBP"), Some((money("0.8556") / money("1.0830")).round_dp(10)));
+    }
+
+    #[test]
+    fn parse_ecb_requires_the_base_currency() {
+        assert!(parse_ecb(ECB, "JPY").is_err());
+    }
+
+    #[test]
+    fn parse_ecb_rejects_invalid_rates() {
+        for invalid in ["0", "-1.2", "abc"] {
+            let xml = ECB.replace("1.0830", invalid);
+            assert!(parse_ecb(&xml, "EUR").is_err(), "rate {} was accepted", invalid);
+        }
+    }
+
+    #[test]
+    fn normalize_currency_checks_the_code() {
+        assert_eq!(normalize_currency(" usd "), Ok("USD".to_string()));
+        assert!(normalize_currency("US").is_err());
+        assert!(normalize_currency("US1").is_err());
+        assert!(normalize_currency("€UR").is_err());
+    }
+}
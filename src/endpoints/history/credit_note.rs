@@ -0,0 +1,70 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Serialize;
+use mysql::prelude::Queryable;
+use mysql::Row;
+use crate::appdata::AppData;
+use crate::apis::pdf::{PdfCommonPayload, PdfCreditNotePayload};
+use crate::endpoints::pdf::get_itemrows_batch;
+use crate::endpoints::invoice::InvoiceStatus;
+use crate::calculation::{calculate, Totals};
+
+#[derive(Serialize)]
+pub struct Response {
+    credit_notes: Vec<CreditNote>
+}
+
+#[derive(Serialize)]
+pub struct CreditNote {
+    #[serde(flatten)]
+    credit_note:    PdfCreditNotePayload,
+    /// Pending until the PDF of the credit note has been generated
+    status:         InvoiceStatus,
+    totals:         Totals
+}
+
+#[get("/history/creditnote")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_credit_note_history(data: web::Data<AppData>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_credit_notes = match conn.query::<Row, &str>("SELECT * FROM credit_notes") {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("Failed to query credit notes from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let parents: Vec<(i64, i32)> = sql_get_credit_notes.iter().map(|row| (row.get("id").unwrap(), 0)).collect();
+    let mut itemrows = match get_itemrows_batch(&mut conn, &parents, "credit_notes") {
+        Ok(itemrows) => itemrows,
+        Err(err) => {
+            eprintln!("Failed to query itemrows for credit notes: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut credit_notes = Vec::new();
+    for row in sql_get_credit_notes {
+        let id: i64 = row.get("id").unwrap();
+        let itemrows = itemrows.remove(&(id, 0)).unwrap_or_default();
+        credit_notes.push(CreditNote {
+            totals: calculate(&itemrows, data.config.rounding),
+            status: row.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap(),
+            credit_note: PdfCreditNotePayload {
+                invoice_id: row.get("invoice_id").unwrap(),
+                common: PdfCommonPayload::from_row(&row, itemrows)
+            }
+        });
+    }
+
+    HttpResponse::Ok().json(Response { credit_notes })
+}
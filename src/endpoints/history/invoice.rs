@@ -2,18 +2,42 @@ use actix_web::{get, web, HttpResponse};
 use actix_web_grants::proc_macro::has_permissions;
 use serde::Serialize;
 use mysql::prelude::Queryable;
-use mysql::{Row, Params, params};
+use mysql::{Row, Params, Value};
+use std::collections::HashMap;
 use crate::appdata::AppData;
-use crate::apis::pdf::{PdfCommonPayload, Address, ItemRow};
+use crate::apis::pdf::{PdfCommonPayload, ItemRow};
+use crate::endpoints::invoice::InvoiceStatus;
+use crate::endpoints::pdf::get_itemrows_batch;
+use crate::endpoints::history::Query;
+use crate::money::{self, Money};
+use crate::calculation::{calculate, Totals};
 
 #[derive(Serialize)]
 pub struct Response {
-    invoices: Vec<PdfCommonPayload>
+    invoices:       Vec<Invoice>,
+    /// Pass as `cursor` to fetch the next page, absent on the last page
+    next_cursor:    Option<String>
+}
+#[derive(Serialize)]
+pub struct Invoice {
+    #[serde(flatten)]
+    invoice:    PdfCommonPayload,
+    status:     InvoiceStatus,
+    overdue:    bool,
+    quote_id:   Option<i64>,
+    totals:     Totals,
+    total:      Money,
+    /// The total converted to the base currency
+    base_total: Money,
+    paid:       Money,
+    credited:   Money,
+    balance:    Money
 }
 
 #[get("/history/invoice")]
 #[has_permissions("INVOICE_READ")]
-pub async fn get_invoice_history(data: web::Data<AppData>) -> HttpResponse {
+#[allow(clippy::async_yields_async)]
+pub async fn get_invoice_history(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
     let mut conn = match data.pool.get_conn() {
         Ok(conn) => conn,
         Err(err) => {
@@ -22,7 +46,12 @@ pub async fn get_invoice_history(data: web::Data<AppData>) -> HttpResponse {
         }
     };
 
-    let sql_get_invoice = match conn.query::<Row, &str>("SELECT * FROM invoices") {
+    let (filter, filter_params) = match query.to_sql() {
+        Ok(filter) => filter,
+        Err(err) => return HttpResponse::BadRequest().body(err)
+    };
+
+    let mut sql_get_invoice = match conn.exec::<Row, String, Params>(format!("SELECT * FROM invoices WHERE {}", filter), filter_params) {
         Ok(result) => result,
         Err(err) => {
             eprintln!("Failed to query invoices from the database: {:?}", err);
@@ -30,57 +59,75 @@ pub async fn get_invoice_history(data: web::Data<AppData>) -> HttpResponse {
         }
     };
 
-    let mut invoices = Vec::new();
-    for row in sql_get_invoice {
-        let id = row.get("id").unwrap();
+    let next_cursor = query.paginate(&mut sql_get_invoice);
+    let ids: Vec<i64> = sql_get_invoice.iter().map(|row| row.get("id").unwrap()).collect();
 
-        let sql_get_rows = conn.exec::<Row, &str, Params>("SELECT * FROM itemrows WHERE parent_id = :parent_id AND parent_type = :parent_type", params! {
-            "parent_id" => &id,
-            "parent_type" => "invoices"
-        });
-        if sql_get_rows.is_err() {
-            eprintln!("Failed to query itemrows for invoice {}", &id);
+    let mut itemrows = match get_itemrows_batch(&mut conn, &ids.iter().map(|id| (*id, 0)).collect::<Vec<_>>(), "invoices") {
+        Ok(itemrows) => itemrows,
+        Err(err) => {
+            eprintln!("Failed to query itemrows for invoices: {:?}", err);
             return HttpResponse::InternalServerError().finish();
         }
+    };
 
-        let mut itemrows = Vec::new();
-        for itemrow in sql_get_rows.unwrap() {
-            let entry = ItemRow {
-                id: itemrow.get("product_id").unwrap(),
-                name: itemrow.get("name").unwrap(),
-                comment: itemrow.get("comment"),
-                description: itemrow.get("description").unwrap(),
-                discount_perc: itemrow.get("discount_perc"),
-                vat_perc: itemrow.get("vat_perc").unwrap(),
-                price: itemrow.get("price").unwrap(),
-                quantity: itemrow.get("quantity").unwrap()
-            };
+    let mut paid_per_invoice: HashMap<i64, Money> = HashMap::new();
+    let mut credited_per_invoice: HashMap<i64, Vec<ItemRow>> = HashMap::new();
+    if !ids.is_empty() {
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let id_params = Params::Positional(ids.iter().map(Value::from).collect());
 
-            itemrows.push(entry);
-        }
+        let sql_get_paid = match conn.exec::<Row, String, Params>(format!("SELECT invoice_id, SUM(amount) AS paid FROM payments WHERE invoice_id IN ({}) GROUP BY invoice_id", placeholders), id_params.clone()) {
+            Ok(result) => result,
+            Err(err) => {
+                eprintln!("Failed to query payments from the database: {:?}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
 
+        for row in sql_get_paid {
+            paid_per_invoice.insert(row.get("invoice_id").unwrap(), row.get("paid").unwrap());
+        }
 
-        let result = PdfCommonPayload {
-            id,
-            template_name: row.get("template_name").unwrap(),
-            language: row.get("language").unwrap(),
-            attention_of: row.get("attention_of"),
-            receiver: row.get("receiver").unwrap(),
-            reference: row.get("reference").unwrap(),
-            notes: row.get("notes"),
-            expiry_date: row.get("expiry_date").unwrap(),
-            creation_date: row.get("creation_date").unwrap(),
-            address: Address {
-                city: row.get("city").unwrap(),
-                country: row.get("country").unwrap(),
-                postal_code: row.get("postal_code").unwrap(),
-                street: row.get("street").unwrap()
-            },
-            rows: itemrows
+        let sql_get_credited = match conn.exec::<Row, String, Params>(format!("SELECT credit_notes.invoice_id, itemrows.* FROM itemrows INNER JOIN credit_notes ON credit_notes.id = itemrows.parent_id \
+            WHERE itemrows.parent_type = 'credit_notes' AND credit_notes.invoice_id IN ({})", placeholders), id_params) {
+            Ok(result) => result,
+            Err(err) => {
+                eprintln!("Failed to query credit notes from the database: {:?}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
         };
 
-        invoices.push(result);
+        for row in sql_get_credited {
+            credited_per_invoice.entry(row.get("invoice_id").unwrap()).or_default().push(ItemRow::from_row(&row));
+        }
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let mut invoices = Vec::new();
+    for row in sql_get_invoice {
+        let id: i64 = row.get("id").unwrap();
+
+        let result = PdfCommonPayload::from_row(&row, itemrows.remove(&(id, 0)).unwrap_or_default());
+
+        let status = row.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap();
+        let totals = calculate(&result.rows, data.config.rounding);
+        let total = totals.total;
+        let paid = paid_per_invoice.get(&result.id).copied().unwrap_or_default();
+        let credited = calculate(&credited_per_invoice.remove(&result.id).unwrap_or_default(), data.config.rounding).total;
+        let balance = total - paid - credited;
+        invoices.push(Invoice {
+            base_total: money::to_base(total, result.exchange_rate),
+            overdue: balance > Money::ZERO && status.is_overdue(result.expiry_date, now),
+            invoice: result,
+            status,
+            quote_id: row.get::<Option<i64>, &str>("quote_id").unwrap(),
+            totals,
+            total,
+            paid,
+            credited,
+            balance
+        });
     }
 
-    HttpResponse::Ok().json(Response { invoices })
+    HttpResponse::Ok().json(Response { invoices, next_cursor })
 }
\ No newline at end of file
@@ -1,2 +1,210 @@
 pub mod quote;
-pub mod invoice;
\ No newline at end of file
+pub mod invoice;
+pub mod credit_note;
+
+use serde::{Serialize, Deserialize};
+use mysql::{Params, Value, params};
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Sort {
+    Id,
+    CreationDate,
+    ExpiryDate,
+    Receiver
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    Desc
+}
+
+/**
+Filters and pagination shared by the invoice and quote history
+*/
+#[derive(Deserialize)]
+pub struct Query {
+    receiver:       Option<String>,
+    created_from:   Option<i64>,
+    created_to:     Option<i64>,
+    expires_from:   Option<i64>,
+    expires_to:     Option<i64>,
+    status:         Option<String>,
+    /// Free-text search on reference and notes
+    q:              Option<String>,
+    template:       Option<String>,
+    sort:           Option<Sort>,
+    order:          Option<Order>,
+    limit:          Option<u64>,
+    /// The `next_cursor` of the previous page
+    cursor:         Option<String>
+}
+
+/**
+Position of the last document of a page: the value of the sort column and the id as tiebreaker
+*/
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    value:  CursorValue,
+    id:     i64
+}
+
+impl Cursor {
+    fn encode(&self) -> Option<String> {
+        let cursor = serde_json::to_vec(self).ok()?;
+        Some(base64::encode_config(cursor, base64::URL_SAFE_NO_PAD))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CursorValue {
+    Int(i64),
+    Text(String)
+}
+
+const FILTER: &str = "(:receiver IS NULL OR receiver = :receiver) \
+    AND (:created_from IS NULL OR creation_date >= :created_from) \
+    AND (:created_to IS NULL OR creation_date <= :created_to) \
+    AND (:expires_from IS NULL OR expiry_date >= :expires_from) \
+    AND (:expires_to IS NULL OR expiry_date <= :expires_to) \
+    AND (:status IS NULL OR status = :status) \
+    AND (:search IS NULL OR reference LIKE :search OR notes LIKE :search) \
+    AND (:template IS NULL OR template_name = :template)";
+
+impl Query {
+    fn sort_column(&self) -> &'static str {
+        match self.sort.unwrap_or(Sort::Id) {
+            Sort::Id => "id",
+            Sort::CreationDate => "creation_date",
+            Sort::ExpiryDate => "expiry_date",
+            Sort::Receiver => "receiver"
+        }
+    }
+
+    /**
+    Build the filter, ordering and limit for a page of documents, to be appended after `WHERE`.
+    Returns an error if the cursor could not be decoded
+    */
+    pub fn to_sql(&self) -> Result<(String, Params), String> {
+        let cursor = match &self.cursor {
+            Some(cursor) => {
+                let decoded = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).map_err(|_| "Invalid cursor".to_string())?;
+                Some(serde_json::from_slice::<Cursor>(&decoded).map_err(|_| "Invalid cursor".to_string())?)
+            },
+            None => None
+        };
+
+        let (cursor_value, cursor_id) = match cursor {
+            Some(Cursor { value: CursorValue::Int(value), id }) => (Value::from(value), Value::from(id)),
+            Some(Cursor { value: CursorValue::Text(value), id }) => (Value::from(value), Value::from(id)),
+            None => (Value::NULL, Value::NULL)
+        };
+
+        let search = self.q.as_ref()
+            .filter(|q| !q.trim().is_empty())
+            .map(|q| format!("%{}%", q.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")));
+
+        let (order, comparison) = match self.order.unwrap_or(Order::Asc) {
+            Order::Asc => ("ASC", ">"),
+            Order::Desc => ("DESC", "<")
+        };
+
+        let sort = self.sort_column();
+
+        //The id is used as a tiebreaker so pages are stable
+        let mut sql = format!("{} AND (:cursor_id IS NULL OR ({}, id) {} (:cursor_value, :cursor_id)) ORDER BY {} {}, id {}", FILTER, sort, comparison, sort, order, order);
+
+        //One extra document is fetched to know whether there is a next page
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" LIMIT {}", limit.saturating_add(1)));
+        }
+
+        Ok((sql, params! {
+            "receiver" => &self.receiver,
+            "created_from" => self.created_from,
+            "created_to" => self.created_to,
+            "expires_from" => self.expires_from,
+            "expires_to" => self.expires_to,
+            "status" => &self.status,
+            "search" => search,
+            "template" => &self.template,
+            "cursor_value" => cursor_value,
+            "cursor_id" => cursor_id
+        }))
+    }
+
+    /**
+    Cut the extra document off a page and build the cursor for the next page, if there is one
+    */
+    pub fn paginate(&self, rows: &mut Vec<mysql::Row>) -> Option<String> {
+        let limit = self.limit? as usize;
+        if rows.len() <= limit {
+            return None;
+        }
+
+        rows.truncate(limit);
+        let last = rows.last()?;
+        let value = match self.sort.unwrap_or(Sort::Id) {
+            Sort::Receiver => CursorValue::Text(last.get("receiver").unwrap()),
+            _ => CursorValue::Int(last.get(self.sort_column()).unwrap())
+        };
+
+        Cursor { value, id: last.get("id").unwrap() }.encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(json: serde_json::Value) -> Query {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn param(params: &Params, name: &str) -> Value {
+        match params {
+            Params::Named(params) => params[name].clone(),
+            _ => panic!("Named parameters expected")
+        }
+    }
+
+    #[test]
+    fn pages_are_ordered_by_the_sort_column_and_id() {
+        let (sql, _) = query(serde_json::json!({})).to_sql().unwrap();
+        assert!(sql.ends_with("(id, id) > (:cursor_value, :cursor_id)) ORDER BY id ASC, id ASC"));
+
+        let (sql, _) = query(serde_json::json!({"sort": "receiver", "order": "desc", "limit": 20})).to_sql().unwrap();
+        assert!(sql.contains("(receiver, id) < (:cursor_value, :cursor_id)"));
+        assert!(sql.ends_with("ORDER BY receiver DESC, id DESC LIMIT 21"));
+    }
+
+    #[test]
+    fn cursors_continue_after_the_last_document() {
+        let cursor = Cursor { value: CursorValue::Text("Acme GmbH".to_string()), id: 42 }.encode().unwrap();
+        let (_, params) = query(serde_json::json!({"sort": "receiver", "cursor": cursor})).to_sql().unwrap();
+
+        assert_eq!(param(&params, "cursor_value"), Value::from("Acme GmbH"));
+        assert_eq!(param(&params, "cursor_id"), Value::from(42));
+
+        let (_, params) = query(serde_json::json!({})).to_sql().unwrap();
+        assert_eq!(param(&params, "cursor_id"), Value::NULL);
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        assert!(query(serde_json::json!({"cursor": "not a cursor"})).to_sql().is_err());
+        assert!(query(serde_json::json!({"cursor": base64::encode_config("{}", base64::URL_SAFE_NO_PAD)})).to_sql().is_err());
+    }
+
+    #[test]
+    fn search_wildcards_are_escaped() {
+        let (_, params) = query(serde_json::json!({"q": " 50%_off "})).to_sql().unwrap();
+        assert_eq!(param(&params, "search"), Value::from("%50\\%\\_off%"));
+
+        let (_, params) = query(serde_json::json!({"q": " "})).to_sql().unwrap();
+        assert_eq!(param(&params, "search"), Value::NULL);
+    }
+}
\ No newline at end of file
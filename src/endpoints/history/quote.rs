@@ -2,18 +2,41 @@ use actix_web::{get, web, HttpResponse};
 use actix_web_grants::proc_macro::has_permissions;
 use serde::Serialize;
 use mysql::prelude::Queryable;
-use mysql::{Row, Params, params};
+use mysql::{Row, Params, Value};
+use std::collections::HashMap;
+use crate::endpoints::quote::{QuoteStatus, revision_number};
+use crate::endpoints::pdf::get_itemrows_batch;
+use crate::endpoints::history::Query;
+use crate::calculation::{calculate, Totals};
 use crate::appdata::AppData;
-use crate::apis::pdf::{ItemRow, PdfQuotePayload, PdfCommonPayload, Address};
+use crate::apis::pdf::{PdfQuotePayload, PdfCommonPayload};
 
 #[derive(Serialize)]
 pub struct Response {
-    quotes: Vec<PdfQuotePayload>
+    quotes:         Vec<Quote>,
+    /// Pass as `cursor` to fetch the next page, absent on the last page
+    next_cursor:    Option<String>
+}
+
+#[derive(Serialize)]
+pub struct Quote {
+    #[serde(flatten)]
+    quote:          PdfQuotePayload,
+    /// Quote number including the revision suffix
+    number:         String,
+    totals:         Totals,
+    status:         QuoteStatus,
+    /// When the customer accepted or declined the quote, and the name they entered
+    decided_at:     Option<i64>,
+    decided_by:     Option<String>,
+    /// Invoices created from this quote
+    invoice_ids:    Vec<i64>
 }
 
 #[get("/history/quote")]
 #[has_permissions("QUOTE_READ")]
-pub async fn get_quote_history(data: web::Data<AppData>) -> HttpResponse {
+#[allow(clippy::async_yields_async)]
+pub async fn get_quote_history(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
     let mut conn = match data.pool.get_conn() {
         Ok(conn) => conn,
         Err(err) => {
@@ -22,70 +45,73 @@ pub async fn get_quote_history(data: web::Data<AppData>) -> HttpResponse {
         }
     };
 
-    let sql_get_quotes = match conn.query::<Row, &str>("SELECT * FROM quotes") {
+    let (filter, filter_params) = match query.to_sql() {
+        Ok(filter) => filter,
+        Err(err) => return HttpResponse::BadRequest().body(err)
+    };
+
+    let mut sql_get_quotes = match conn.exec::<Row, String, Params>(format!("SELECT * FROM quotes WHERE revision = (SELECT MAX(revision) FROM quotes AS revisions WHERE revisions.id = quotes.id) AND {}", filter), filter_params) {
         Ok(result) => result,
         Err(err) => {
-            eprintln!("Failed to query invoices from the database: {:?}", err);
+            eprintln!("Failed to query quotes from the database: {:?}", err);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    let mut quotes = Vec::new();
-    for row in sql_get_quotes {
-        let id = row.get("id").unwrap();
+    let next_cursor = query.paginate(&mut sql_get_quotes);
+    let parents: Vec<(i64, i32)> = sql_get_quotes.iter().map(|row| (row.get("id").unwrap(), row.get("revision").unwrap())).collect();
 
-        let sql_get_rows = conn.exec::<Row, &str, Params>("SELECT * FROM itemrows WHERE parent_id = :parent_id AND parent_type = :parent_type", params! {
-            "parent_id" => &id,
-            "parent_type" => "quotes"
-        });
-        if sql_get_rows.is_err() {
-            eprintln!("Failed to query itemrows for invoice {}", &id);
+    let mut itemrows = match get_itemrows_batch(&mut conn, &parents, "quotes") {
+        Ok(itemrows) => itemrows,
+        Err(err) => {
+            eprintln!("Failed to query itemrows for quotes: {:?}", err);
             return HttpResponse::InternalServerError().finish();
         }
+    };
 
-        let mut itemrows = Vec::new();
-        for itemrow in sql_get_rows.unwrap() {
-            let entry = ItemRow {
-                id: itemrow.get("product_id").unwrap(),
-                name: itemrow.get("name").unwrap(),
-                comment: itemrow.get("comment"),
-                description: itemrow.get("description").unwrap(),
-                discount_perc: itemrow.get("discount_perc"),
-                vat_perc: itemrow.get("vat_perc").unwrap(),
-                price: itemrow.get("price").unwrap(),
-                quantity: itemrow.get("quantity").unwrap()
-            };
-
-            itemrows.push(entry);
+    let sql_get_invoice_ids = match parents.is_empty() {
+        true => Vec::new(),
+        false => {
+            let placeholders = vec!["?"; parents.len()].join(", ");
+            let id_params = Params::Positional(parents.iter().map(|(id, _)| Value::from(id)).collect());
+            match conn.exec::<Row, String, Params>(format!("SELECT id, quote_id FROM invoices WHERE quote_id IN ({}) ORDER BY id ASC", placeholders), id_params) {
+                Ok(result) => result,
+                Err(err) => {
+                    eprintln!("Failed to query invoices from the database: {:?}", err);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
         }
+    };
 
+    let mut invoice_ids: HashMap<i64, Vec<i64>> = HashMap::new();
+    for row in sql_get_invoice_ids {
+        invoice_ids.entry(row.get("quote_id").unwrap()).or_default().push(row.get("id").unwrap());
+    }
+
+    let mut quotes = Vec::new();
+    for row in sql_get_quotes {
+        let id: i64 = row.get("id").unwrap();
+        let revision: i32 = row.get("revision").unwrap();
 
         let result = PdfQuotePayload {
             quote_topic: row.get("quote_topic").unwrap(),
             quote_contact_person: row.get("quote_contact_person").unwrap(),
             debit_id: row.get("debit_id").unwrap(),
-            common: PdfCommonPayload {
-                id,
-                template_name: row.get("template_name").unwrap(),
-                language: row.get("language").unwrap(),
-                attention_of: row.get("attention_of"),
-                receiver: row.get("receiver").unwrap(),
-                reference: row.get("reference").unwrap(),
-                notes: row.get("notes"),
-                expiry_date: row.get("expiry_date").unwrap(),
-                creation_date: row.get("creation_date").unwrap(),
-                address: Address {
-                    city: row.get("city").unwrap(),
-                    country: row.get("country").unwrap(),
-                    postal_code: row.get("postal_code").unwrap(),
-                    street: row.get("street").unwrap()
-                },
-                rows: itemrows,
-            }
+            revision,
+            common: PdfCommonPayload::from_row(&row, itemrows.remove(&(id, revision)).unwrap_or_default())
         };
 
-        quotes.push(result);
+        quotes.push(Quote {
+            number: revision_number(id, revision),
+            totals: calculate(&result.common.rows, data.config.rounding),
+            invoice_ids: invoice_ids.remove(&id).unwrap_or_default(),
+            status: row.get::<String, &str>("status").unwrap().parse::<QuoteStatus>().unwrap(),
+            decided_at: row.get::<Option<i64>, &str>("decided_at").unwrap(),
+            decided_by: row.get::<Option<String>, &str>("decided_by").unwrap(),
+            quote: result
+        });
     }
 
-    HttpResponse::Ok().json(Response { quotes })
+    HttpResponse::Ok().json(Response { quotes, next_cursor })
 }
\ No newline at end of file
@@ -1 +1,2 @@
-pub mod quote;
\ No newline at end of file
+pub mod quote;
+pub mod invoice;
\ No newline at end of file
@@ -12,6 +12,7 @@ pub struct Response {
 
 #[get("/id/quote")]
 #[has_permissions("QUOTE_READ")]
+#[allow(clippy::async_yields_async)]
 pub async fn get_quite_id(data: web::Data<AppData>) -> HttpResponse {
     let mut conn = match data.pool.get_conn() {
         Ok(conn) => conn,
@@ -0,0 +1,64 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use crate::AppData;
+use serde::Serialize;
+use crate::apis::pdf::PdfCommonPayload;
+use crate::calculation::{calculate, Totals};
+use crate::endpoints::invoice::get_invoice;
+
+#[derive(Serialize)]
+pub struct Response {
+    #[serde(flatten)]
+    invoice:    PdfCommonPayload,
+    totals:     Totals
+}
+
+#[get("/invoice/{id:-?\\d+}")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_invoice_by_id(data: web::Data<AppData>, web::Path(invoice_id): web::Path<i64>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match get_invoice(&mut conn, invoice_id) {
+        Ok(Some(invoice)) => HttpResponse::Ok().json(Response { totals: calculate(&invoice.rows, data.config.rounding), invoice }),
+        Ok(None) => HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", invoice_id)),
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Rounding;
+    use serde_json::json;
+
+    #[test]
+    fn invoices_are_returned_with_their_totals_next_to_the_fields() {
+        let invoice: PdfCommonPayload = serde_json::from_value(json!({
+            "templateName": "default", "language": "en", "id": 20240001, "attentionOf": null, "receiver": "Acme GmbH", "reference": "Stickers", "notes": null,
+            "expiryDate": 0, "creationDate": 0, "address": { "city": "Berlin", "country": "Germany", "postalCode": "10115", "street": "Main 1" },
+            "rows": [{ "comment": null, "id": "P1", "name": "Sticker", "description": "", "discountPerc": null, "vatPerc": "21", "price": "1.50", "quantity": 10 }]
+        })).unwrap();
+
+        let totals = calculate(&invoice.rows, Rounding::Total);
+        let json = serde_json::to_value(Response { totals, invoice: invoice.clone() }).unwrap();
+
+        assert_eq!(json["id"], json!(20240001));
+        assert_eq!(json["templateName"], json!("default"));
+        assert_eq!(json["totals"]["total"], json!("18.15"));
+
+        //What is returned can be sent back to create a new invoice
+        let returned: PdfCommonPayload = serde_json::from_value(json).unwrap();
+        assert_eq!(returned.rows.len(), invoice.rows.len());
+        assert_eq!(returned.rows[0].price, invoice.rows[0].price);
+    }
+}
@@ -0,0 +1,123 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use mysql::prelude::Queryable;
+use mysql::{TxOpts, params};
+use serde::{Serialize, Deserialize};
+use crate::AppData;
+use crate::apis::pdf::PdfCommonPayload;
+use crate::calculation::Totals;
+use crate::endpoints::exchange_rates::snapshot_rate;
+use crate::endpoints::invoice::{InvoiceStatus, insert_invoice};
+use crate::sequences::next_draft_id;
+use crate::ubl::import::{prepare, Import, LineReport, UnmappedField};
+
+#[derive(Deserialize)]
+pub struct Query {
+    template_name:  String,
+    language:       String,
+    dry_run:        Option<bool>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    dry_run:        bool,
+    /// Not set for a dry run
+    invoice_id:     Option<i64>,
+    source_id:      Option<String>,
+    invoice:        PdfCommonPayload,
+    totals:         Totals,
+    lines:          Vec<LineReport>,
+    unmapped:       Vec<UnmappedField>,
+    warnings:       Vec<String>
+}
+
+/**
+Import a UBL Invoice as a draft invoice. The draft gets a provisional negative ID and its invoice number when it is finalized.
+The number in the document is stored and returned as `source_id`
+*/
+#[post("/invoice/import")]
+#[has_permissions("INVOICE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn import_invoice(data: web::Data<AppData>, query: web::Query<Query>, body: web::Bytes) -> HttpResponse {
+    let dry_run = query.dry_run.unwrap_or(false);
+
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let Import { mut document, lines, totals } = match prepare(&mut conn, &data.config, &body, &query.template_name, &query.language) {
+        Ok(Ok(import)) => import,
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to fetch products from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match snapshot_rate(&mut tx, &data.config, &mut document.payload) {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to query exchange rate from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut invoice_id = None;
+    if !dry_run {
+        //The draft gets its invoice number when it is finalized, so the gapless invoice sequence is not used up by drafts
+        document.payload.id = match next_draft_id(&mut tx) {
+            Ok(id) => id,
+            Err(err) => {
+                eprintln!("Failed to allocate draft ID: {:?}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+
+        //No PDF is generated, the invoice stays a draft
+        if let Err(err) = insert_invoice(&mut tx, &document.payload, None, InvoiceStatus::Draft) {
+            eprintln!("Failed to create imported invoice in database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        let sql_set_source = tx.exec_drop("UPDATE invoices SET source_id = :source_id WHERE id = :id", params! {
+            "id" => document.payload.id,
+            "source_id" => &document.source_id
+        });
+
+        if let Err(err) = sql_set_source {
+            eprintln!("Failed to store the source ID of the imported invoice: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        if let Err(err) = tx.commit() {
+            eprintln!("Failed to commit imported invoice to the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        invoice_id = Some(document.payload.id);
+    }
+
+    HttpResponse::Ok().json(Response {
+        dry_run,
+        invoice_id,
+        source_id: document.source_id,
+        invoice: document.payload,
+        totals,
+        lines,
+        unmapped: document.unmapped,
+        warnings: document.warnings
+    })
+}
@@ -0,0 +1,328 @@
+pub mod status;
+pub mod payments;
+pub mod get;
+pub mod ubl;
+pub mod import;
+
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, params};
+use crate::apis::pdf::{PdfCommonPayload, PdfCreditNotePayload, ItemRow};
+use crate::endpoints::pdf::{insert_itemrows, get_itemrows, get_itemrows_batch};
+use rand::Rng;
+use std::str::FromStr;
+use crate::money::{Money, Rounding};
+use crate::calculation::calculate;
+use crate::appdata::Config;
+use crate::sequences::{next_number, Sequence};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceStatus {
+    Draft,
+    /// Numbered, but its PDF could not be generated yet. Generating the PDF again finalizes it
+    PendingGeneration,
+    Finalized,
+    Sent,
+    PartiallyPaid,
+    Paid,
+    /// Credited in full by one or more credit notes
+    Credited,
+    Cancelled
+}
+
+impl InvoiceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::PendingGeneration => "pending_generation",
+            Self::Finalized => "finalized",
+            Self::Sent => "sent",
+            Self::PartiallyPaid => "partially_paid",
+            Self::Paid => "paid",
+            Self::Credited => "credited",
+            Self::Cancelled => "cancelled"
+        }
+    }
+
+    /**
+    Whether an invoice in this status may be moved to `next`.
+    A finalized invoice can be paid before it is marked as sent, e.g. when it was handed over in person.
+    A pending invoice only becomes finalized by generating its PDF and an invoice is only credited by credit notes,
+    see [`InvoiceStatus::is_set_by_server`]. Credit notes are also numbered invoices, they are either pending or finalized
+    */
+    pub fn can_transition_to(&self, next: Self) -> bool {
+        use InvoiceStatus::*;
+
+        matches!((self, next),
+            (Draft, Finalized)
+            | (PendingGeneration, Finalized)
+            | (Finalized, Sent)
+            | (Finalized, PartiallyPaid)
+            | (Finalized, Paid)
+            | (Sent, PartiallyPaid)
+            | (Sent, Paid)
+            | (PartiallyPaid, Paid)
+            | (Finalized, Credited)
+            | (Sent, Credited)
+            | (PartiallyPaid, Credited)
+            | (Paid, Credited)
+            | (Draft, Cancelled)
+            | (PendingGeneration, Cancelled)
+            | (Finalized, Cancelled)
+            | (Sent, Cancelled)
+        )
+    }
+
+    /**
+    Whether moving from this status to `next` follows from generating a PDF or creating a credit note,
+    so it can not be requested through the status endpoint
+    */
+    pub fn is_set_by_server(&self, next: Self) -> bool {
+        matches!(next, Self::PendingGeneration | Self::Credited) || (*self == Self::PendingGeneration && next == Self::Finalized)
+    }
+
+    /**
+    An invoice is overdue when it has been sent, is not paid in full and its expiry date has passed
+    */
+    pub fn is_overdue(&self, expiry_date: i64, now: i64) -> bool {
+        matches!(self, Self::Sent | Self::PartiallyPaid) && expiry_date < now
+    }
+}
+
+impl FromStr for InvoiceStatus {
+    type Err = String;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "draft" => Ok(Self::Draft),
+            "pending_generation" => Ok(Self::PendingGeneration),
+            "finalized" => Ok(Self::Finalized),
+            "sent" => Ok(Self::Sent),
+            "partially_paid" => Ok(Self::PartiallyPaid),
+            "paid" => Ok(Self::Paid),
+            "credited" => Ok(Self::Credited),
+            "cancelled" => Ok(Self::Cancelled),
+            _ => Err(format!("Unknown invoice status '{}'", status))
+        }
+    }
+}
+
+/**
+Set the status of an invoice and record the change in the invoice_status_history table.
+This does not check if the transition is allowed
+*/
+pub fn set_status<Q: Queryable>(conn: &mut Q, invoice_id: i64, status: InvoiceStatus) -> mysql::Result<()> {
+    conn.exec_drop("UPDATE invoices SET status = :status WHERE id = :id", params! {
+        "id" => invoice_id,
+        "status" => status.as_str()
+    })?;
+
+    let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+    conn.exec_drop("INSERT INTO invoice_status_history (id, invoice_id, status, changed_at) VALUES (:id, :invoice_id, :status, :changed_at)", params! {
+        "id" => id,
+        "invoice_id" => invoice_id,
+        "status" => status.as_str(),
+        "changed_at" => chrono::Utc::now().timestamp()
+    })
+}
+
+/**
+Insert an invoice and its rows with its first status, a draft or a numbered invoice pending generation.
+`quote_id` links the invoice to the quote it was converted from
+*/
+pub fn insert_invoice<Q: Queryable>(conn: &mut Q, payload: &PdfCommonPayload, quote_id: Option<i64>, status: InvoiceStatus) -> mysql::Result<()> {
+    conn.exec_drop("INSERT INTO invoices \
+        (id, template_name, language, attention_of, receiver, reference, notes, expiry_date, creation_date, city, country, postal_code, street, currency, exchange_rate, electronic_address, vat_number, reverse_charge, quote_id) \
+        VALUES (:id, :template_name, :language, :attention_of, :receiver, :reference, :notes, :expiry_date, :creation_date, :city, :country, :postal_code, :street, :currency, :exchange_rate, :electronic_address, :vat_number, :reverse_charge, :quote_id)", params! {
+
+        "id" => &payload.id,
+        "template_name" => &payload.template_name,
+        "language" => &payload.language,
+        "attention_of" => &payload.attention_of,
+        "receiver" => &payload.receiver,
+        "reference" => &payload.reference,
+        "notes" => &payload.notes,
+        "expiry_date" => &payload.expiry_date,
+        "creation_date" => &payload.creation_date,
+        "city" => &payload.address.city,
+        "country" => &payload.address.country,
+        "postal_code" => &payload.address.postal_code,
+        "street" => &payload.address.street,
+        "currency" => &payload.currency,
+        "exchange_rate" => payload.exchange_rate,
+        "electronic_address" => &payload.electronic_address,
+        "vat_number" => &payload.address.vat_number,
+        "reverse_charge" => payload.reverse_charge,
+        "quote_id" => quote_id
+    })?;
+
+    set_status(conn, payload.id, status)?;
+    insert_itemrows(conn, payload.id, 0, "invoices", &payload.rows)
+}
+
+/**
+Give a draft with a provisional ID its invoice number, moving its rows and status history along.
+Invoices that already have their number keep it. Returns the invoice number
+*/
+pub fn assign_number<Q: Queryable>(conn: &mut Q, config: &Config, id: i64) -> crate::Result<i64> {
+    if id > 0 {
+        return Ok(id);
+    }
+
+    let number = next_number(conn, config, Sequence::Invoice)?;
+    for statement in [
+        "UPDATE invoices SET id = :number WHERE id = :id",
+        "UPDATE itemrows SET parent_id = :number WHERE parent_type = 'invoices' AND parent_id = :id",
+        "UPDATE invoice_status_history SET invoice_id = :number WHERE invoice_id = :id"
+    ] {
+        conn.exec_drop(statement, params! {
+            "number" => number,
+            "id" => id
+        }).map_err(|err| err.to_string())?;
+    }
+
+    Ok(number)
+}
+
+/**
+Fetch an invoice and its rows, `None` if the invoice does not exist
+*/
+pub fn get_invoice<Q: Queryable>(conn: &mut Q, id: i64) -> mysql::Result<Option<PdfCommonPayload>> {
+    match conn.exec_first::<Row, &str, Params>("SELECT * FROM invoices WHERE id = :id", params! { "id" => id })? {
+        Some(row) => {
+            let itemrows = get_itemrows(conn, id, 0, "invoices")?;
+            Ok(Some(PdfCommonPayload::from_row(&row, itemrows)))
+        },
+        None => Ok(None)
+    }
+}
+
+/**
+Fetch the rows of all credit notes issued against an invoice
+*/
+pub fn get_credited_rows<Q: Queryable>(conn: &mut Q, invoice_id: i64) -> mysql::Result<Vec<ItemRow>> {
+    let rows = conn.exec::<Row, &str, Params>("SELECT itemrows.* FROM itemrows INNER JOIN credit_notes ON credit_notes.id = itemrows.parent_id \
+        WHERE itemrows.parent_type = 'credit_notes' AND credit_notes.invoice_id = :invoice_id", params! {
+        "invoice_id" => invoice_id
+    })?;
+
+    Ok(rows.iter().map(ItemRow::from_row).collect())
+}
+
+/**
+Calculate the open balance of an invoice: its total minus everything paid and credited on it
+*/
+pub fn get_balance<Q: Queryable>(conn: &mut Q, invoice_id: i64, rounding: Rounding) -> mysql::Result<Money> {
+    let total = calculate(&get_itemrows(conn, invoice_id, 0, "invoices")?, rounding).total;
+    let credited = calculate(&get_credited_rows(conn, invoice_id)?, rounding).total;
+
+    let paid = conn.exec_first::<Option<Money>, &str, Params>("SELECT SUM(amount) FROM payments WHERE invoice_id = :invoice_id", params! {
+        "invoice_id" => invoice_id
+    })?.flatten().unwrap_or_default();
+
+    Ok(total - paid - credited)
+}
+
+/**
+Fetch all invoices created in `[from, to)` that are neither drafts nor cancelled, with their rows, oldest first
+*/
+pub fn get_issued_invoices<Q: Queryable>(conn: &mut Q, from: i64, to: i64) -> mysql::Result<Vec<PdfCommonPayload>> {
+    let rows = conn.exec::<Row, &str, Params>("SELECT * FROM invoices WHERE status NOT IN ('draft', 'cancelled') AND creation_date >= :from AND creation_date < :to ORDER BY creation_date, id", params! {
+        "from" => from,
+        "to" => to
+    })?;
+
+    let parents: Vec<(i64, i32)> = rows.iter().map(|row| (row.get("id").unwrap(), 0)).collect();
+    let mut itemrows = get_itemrows_batch(conn, &parents, "invoices")?;
+    Ok(rows.iter().map(|row| PdfCommonPayload::from_row(row, itemrows.remove(&(row.get("id").unwrap(), 0)).unwrap_or_default())).collect())
+}
+
+/**
+Fetch all credit notes created in `[from, to)`, with their rows, oldest first
+*/
+pub fn get_credit_notes<Q: Queryable>(conn: &mut Q, from: i64, to: i64) -> mysql::Result<Vec<PdfCreditNotePayload>> {
+    let rows = conn.exec::<Row, &str, Params>("SELECT * FROM credit_notes WHERE creation_date >= :from AND creation_date < :to ORDER BY creation_date, id", params! {
+        "from" => from,
+        "to" => to
+    })?;
+
+    let parents: Vec<(i64, i32)> = rows.iter().map(|row| (row.get("id").unwrap(), 0)).collect();
+    let mut itemrows = get_itemrows_batch(conn, &parents, "credit_notes")?;
+    Ok(rows.iter().map(|row| PdfCreditNotePayload {
+        invoice_id: row.get("invoice_id").unwrap(),
+        common: PdfCommonPayload::from_row(row, itemrows.remove(&(row.get("id").unwrap(), 0)).unwrap_or_default())
+    }).collect())
+}
+
+/**
+Fetch a credit note with its rows and status, `None` if the credit note does not exist
+*/
+pub fn get_credit_note<Q: Queryable>(conn: &mut Q, id: i64) -> mysql::Result<Option<(PdfCreditNotePayload, InvoiceStatus)>> {
+    match conn.exec_first::<Row, &str, Params>("SELECT * FROM credit_notes WHERE id = :id", params! { "id" => id })? {
+        Some(row) => {
+            let itemrows = get_itemrows(conn, id, 0, "credit_notes")?;
+            let status = row.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap();
+            Ok(Some((PdfCreditNotePayload { invoice_id: row.get("invoice_id").unwrap(), common: PdfCommonPayload::from_row(&row, itemrows) }, status)))
+        },
+        None => Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InvoiceStatus::*;
+
+    const ALL: [InvoiceStatus; 8] = [Draft, PendingGeneration, Finalized, Sent, PartiallyPaid, Paid, Credited, Cancelled];
+
+    #[test]
+    fn invoices_move_forward_only() {
+        assert!(Draft.can_transition_to(Finalized));
+        assert!(Finalized.can_transition_to(Sent));
+        assert!(Finalized.can_transition_to(Paid));
+        assert!(Sent.can_transition_to(PartiallyPaid));
+        assert!(PartiallyPaid.can_transition_to(Paid));
+
+        assert!(!Sent.can_transition_to(Finalized));
+        assert!(!Paid.can_transition_to(PartiallyPaid));
+        assert!(!Draft.can_transition_to(Sent));
+        assert!(ALL.iter().all(|status| !status.can_transition_to(*status)));
+    }
+
+    #[test]
+    fn paid_credited_and_cancelled_invoices_can_not_be_cancelled() {
+        assert!(Sent.can_transition_to(Cancelled));
+        assert!(!PartiallyPaid.can_transition_to(Cancelled));
+        assert!(!Paid.can_transition_to(Cancelled));
+        assert!(ALL.iter().all(|status| !Cancelled.can_transition_to(*status) && !Credited.can_transition_to(*status)));
+    }
+
+    #[test]
+    fn generation_and_crediting_are_left_to_the_server() {
+        assert!(PendingGeneration.is_set_by_server(Finalized));
+        assert!(Paid.is_set_by_server(Credited));
+        assert!(ALL.iter().all(|status| status.is_set_by_server(PendingGeneration)));
+        assert!(!Draft.is_set_by_server(Finalized));
+        assert!(!Sent.is_set_by_server(Paid));
+    }
+
+    #[test]
+    fn only_sent_and_unpaid_invoices_are_overdue() {
+        assert!(Sent.is_overdue(100, 200));
+        assert!(PartiallyPaid.is_overdue(100, 200));
+        assert!(!Sent.is_overdue(200, 200));
+        assert!(!Finalized.is_overdue(100, 200));
+        assert!(!Paid.is_overdue(100, 200));
+    }
+
+    #[test]
+    fn statuses_round_trip_through_their_names() {
+        for status in ALL {
+            assert_eq!(status.as_str().parse::<InvoiceStatus>(), Ok(status));
+            assert_eq!(serde_json::to_value(status).unwrap(), serde_json::json!(status.as_str()));
+        }
+
+        assert!("open".parse::<InvoiceStatus>().is_err());
+    }
+}
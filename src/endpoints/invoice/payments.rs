@@ -0,0 +1,186 @@
+use actix_web::{get, post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, TxOpts, params};
+use rand::Rng;
+use crate::AppData;
+use crate::endpoints::invoice::{InvoiceStatus, set_status, get_balance};
+use crate::money::Money;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Payment {
+    pub id:             Option<String>,
+    pub invoice_id:     i64,
+    pub payment_date:   i64,
+    pub amount:         Money,
+    pub method:         String,
+    pub reference:      Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct Query {
+    invoice_id: i64
+}
+
+#[derive(Serialize)]
+pub struct GetResponse {
+    payments: Vec<Payment>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    id:         Option<String>,
+    status:     Option<InvoiceStatus>,
+    balance:    Option<Money>,
+    error:      Option<String>
+}
+
+#[get("/invoice/payments")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_payments(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_payments = conn.exec::<Row, &str, Params>("SELECT * FROM payments WHERE invoice_id = :invoice_id ORDER BY payment_date ASC", params! {
+        "invoice_id" => query.invoice_id
+    });
+
+    if sql_get_payments.is_err() {
+        eprintln!("Failed to query payments from the database: {:?}", sql_get_payments.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut payments = Vec::new();
+    for row in sql_get_payments.unwrap() {
+        payments.push(Payment {
+            id: row.get("id").unwrap(),
+            invoice_id: row.get("invoice_id").unwrap(),
+            payment_date: row.get("payment_date").unwrap(),
+            amount: row.get("amount").unwrap(),
+            method: row.get("method").unwrap(),
+            reference: row.get("reference").unwrap()
+        });
+    }
+
+    HttpResponse::Ok().json(GetResponse { payments })
+}
+
+#[post("/invoice/payments/add")]
+#[has_permissions("INVOICE_UPDATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn add_payment(data: web::Data<AppData>, payload: web::Json<Payment>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if payload.amount <= Money::ZERO {
+        return HttpResponse::BadRequest().json(Response { id: None, status: None, balance: None, error: Some("Payment amount must be positive".to_string()) });
+    }
+
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    //The invoice stays locked until the payment is committed, so concurrent payments see each other's amounts in the balance
+    let sql_get_invoice = tx.exec_first::<Row, &str, Params>("SELECT status FROM invoices WHERE id = :id FOR UPDATE", params! {
+        "id" => payload.invoice_id
+    });
+
+    let status = match sql_get_invoice {
+        Ok(Some(row)) => row.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap(),
+        Ok(None) => return HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", payload.invoice_id)),
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if matches!(status, InvoiceStatus::Draft | InvoiceStatus::Credited | InvoiceStatus::Cancelled) {
+        return HttpResponse::BadRequest().json(Response { id: None, status: Some(status), balance: None, error: Some(format!("Payments can not be registered on an invoice with status '{}'", status.as_str())) });
+    }
+
+    let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+    let sql_insert_payment = tx.exec_drop("INSERT INTO payments (id, invoice_id, payment_date, amount, method, reference) \
+        VALUES (:id, :invoice_id, :payment_date, :amount, :method, :reference)", params! {
+        "id" => &id,
+        "invoice_id" => payload.invoice_id,
+        "payment_date" => payload.payment_date,
+        "amount" => payload.amount,
+        "method" => &payload.method,
+        "reference" => &payload.reference
+    });
+
+    if let Err(err) = sql_insert_payment {
+        eprintln!("Failed to insert payment into the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let balance = match get_balance(&mut tx, payload.invoice_id, data.config.rounding) {
+        Ok(balance) => balance,
+        Err(err) => {
+            eprintln!("Failed to calculate the balance of invoice {}: {:?}", payload.invoice_id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let next = status_after_payment(status, balance);
+    if next != status {
+        if let Err(err) = set_status(&mut tx, payload.invoice_id, next) {
+            eprintln!("Failed to update invoice status in the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit payment to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(Response { id: Some(id), status: Some(next), balance: Some(balance), error: None })
+}
+
+/**
+The status of an invoice after a payment left it with `balance` open. Statuses that can not be moved to paid are kept
+*/
+fn status_after_payment(status: InvoiceStatus, balance: Money) -> InvoiceStatus {
+    let next = if balance <= Money::ZERO { InvoiceStatus::Paid } else { InvoiceStatus::PartiallyPaid };
+    if status != next && status.can_transition_to(next) {
+        next
+    } else {
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payments_move_the_invoice_towards_paid() {
+        assert_eq!(status_after_payment(InvoiceStatus::Sent, Money::ONE), InvoiceStatus::PartiallyPaid);
+        assert_eq!(status_after_payment(InvoiceStatus::Sent, Money::ZERO), InvoiceStatus::Paid);
+        assert_eq!(status_after_payment(InvoiceStatus::Finalized, Money::NEGATIVE_ONE), InvoiceStatus::Paid);
+        assert_eq!(status_after_payment(InvoiceStatus::PartiallyPaid, Money::ONE), InvoiceStatus::PartiallyPaid);
+        assert_eq!(status_after_payment(InvoiceStatus::PartiallyPaid, Money::ZERO), InvoiceStatus::Paid);
+    }
+
+    #[test]
+    fn paid_invoices_stay_paid() {
+        assert_eq!(status_after_payment(InvoiceStatus::Paid, Money::ONE), InvoiceStatus::Paid);
+    }
+}
@@ -0,0 +1,159 @@
+use actix_web::{get, post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, TxOpts, params};
+use crate::AppData;
+use crate::endpoints::invoice::{InvoiceStatus, set_status, assign_number};
+
+#[derive(Deserialize)]
+pub struct Query {
+    id: i64
+}
+
+#[derive(Serialize)]
+pub struct StatusChange {
+    status:     InvoiceStatus,
+    changed_at: i64
+}
+
+#[derive(Serialize)]
+pub struct GetResponse {
+    id:         i64,
+    status:     InvoiceStatus,
+    overdue:    bool,
+    changes:    Vec<StatusChange>
+}
+
+#[derive(Deserialize)]
+pub struct Request {
+    id:     i64,
+    status: InvoiceStatus
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    error:  Option<String>,
+    /// The invoice number, which differs from the requested ID when a draft got its number
+    id:     Option<i64>
+}
+
+#[get("/invoice/status")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_invoice_status(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_invoice = conn.exec::<Row, &str, Params>("SELECT status, expiry_date FROM invoices WHERE id = :id", params! {
+        "id" => query.id
+    });
+
+    let (status, expiry_date) = match sql_get_invoice {
+        Ok(rows) => match rows.into_iter().next() {
+            Some(row) => (row.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap(), row.get::<i64, &str>("expiry_date").unwrap()),
+            None => return HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", query.id))
+        },
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_changes = conn.exec::<Row, &str, Params>("SELECT status, changed_at FROM invoice_status_history WHERE invoice_id = :id ORDER BY changed_at ASC", params! {
+        "id" => query.id
+    });
+
+    if sql_get_changes.is_err() {
+        eprintln!("Failed to query invoice status history from the database: {:?}", sql_get_changes.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut changes = Vec::new();
+    for row in sql_get_changes.unwrap() {
+        changes.push(StatusChange {
+            status: row.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap(),
+            changed_at: row.get("changed_at").unwrap()
+        });
+    }
+
+    HttpResponse::Ok().json(GetResponse {
+        id: query.id,
+        status,
+        overdue: status.is_overdue(expiry_date, chrono::Utc::now().timestamp()),
+        changes
+    })
+}
+
+#[post("/invoice/status")]
+#[has_permissions("INVOICE_UPDATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn set_invoice_status(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    //The invoice stays locked until the new status is committed, so concurrent changes can not both pass the transition check
+    let sql_get_invoice = tx.exec_first::<Row, &str, Params>("SELECT status FROM invoices WHERE id = :id FOR UPDATE", params! {
+        "id" => request.id
+    });
+
+    let current = match sql_get_invoice {
+        Ok(Some(row)) => row.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap(),
+        Ok(None) => return HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", request.id)),
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if !current.can_transition_to(request.status) {
+        return HttpResponse::BadRequest().json(Response { error: Some(format!("Invoice {} can not go from '{}' to '{}'", request.id, current.as_str(), request.status.as_str())), id: None });
+    }
+
+    if current.is_set_by_server(request.status) {
+        return HttpResponse::BadRequest().json(Response { error: Some(format!("Invoice {} goes from '{}' to '{}' by generating its PDF or crediting it", request.id, current.as_str(), request.status.as_str())), id: None });
+    }
+
+    //Imported drafts only get their invoice number when they are finalized
+    let id = if request.status == InvoiceStatus::Finalized {
+        match assign_number(&mut tx, &data.config, request.id) {
+            Ok(id) => id,
+            Err(err) => {
+                eprintln!("Failed to allocate invoice number: {:?}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    } else {
+        request.id
+    };
+
+    if let Err(err) = set_status(&mut tx, id, request.status) {
+        eprintln!("Failed to update invoice status in the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit invoice status to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(Response { error: None, id: Some(id) })
+}
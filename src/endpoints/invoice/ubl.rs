@@ -0,0 +1,112 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use crate::AppData;
+use serde::Serialize;
+use mysql::prelude::Queryable;
+use mysql::{Params, params};
+use crate::endpoints::invoice::{InvoiceStatus, get_invoice};
+use crate::ubl::{UblInvoice, export, validation::{self, RuleViolation}};
+
+#[derive(Serialize)]
+pub struct Response {
+    valid:      bool,
+    errors:     Vec<RuleViolation>
+}
+
+/**
+Only issued invoices can be sent: a draft has no invoice number yet and a cancelled invoice must not be sent at all
+*/
+fn check_issued(invoice_id: i64, status: InvoiceStatus) -> Vec<RuleViolation> {
+    let mut errors = Vec::new();
+    if invoice_id <= 0 || status == InvoiceStatus::Draft {
+        errors.push(RuleViolation { rule: "BR-02", message: format!("Invoice {} is a draft without an invoice number, finalize it first.", invoice_id) });
+    }
+
+    if status == InvoiceStatus::Cancelled {
+        errors.push(RuleViolation { rule: "INVOICR-01", message: format!("Invoice {} is cancelled and can not be sent.", invoice_id) });
+    }
+
+    errors
+}
+
+/**
+Load the invoice with the violations that follow from its status, not from its contents
+*/
+fn load(data: &AppData, invoice_id: i64) -> Result<(UblInvoice, Vec<RuleViolation>), HttpResponse> {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+    };
+
+    let invoice = match get_invoice(&mut conn, invoice_id) {
+        Ok(Some(invoice)) => UblInvoice::from_invoice(&invoice, &data.config.seller, &data.config.base_currency, data.config.rounding),
+        Ok(None) => return Err(HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", invoice_id))),
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err);
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+    };
+
+    match conn.exec_first::<String, &str, Params>("SELECT status FROM invoices WHERE id = :id", params! { "id" => invoice_id }) {
+        Ok(Some(status)) => Ok((invoice, check_issued(invoice_id, status.parse::<InvoiceStatus>().unwrap()))),
+        Ok(None) => Err(HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", invoice_id))),
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err);
+            Err(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+/**
+The invoice as a Peppol BIS Billing 3.0 UBL document. Drafts, cancelled invoices and invoices that break a mandatory
+business rule are not exported, the rules they break are returned instead
+*/
+#[get("/invoice/{id:-?\\d+}/ubl")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_invoice_ubl(data: web::Data<AppData>, web::Path(invoice_id): web::Path<i64>) -> HttpResponse {
+    let (invoice, mut errors) = match load(&data, invoice_id) {
+        Ok(loaded) => loaded,
+        Err(response) => return response
+    };
+
+    errors.extend(validation::validate(&invoice));
+    if !errors.is_empty() {
+        return HttpResponse::UnprocessableEntity().json(Response { valid: false, errors });
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/xml")
+        .header("Content-Disposition", format!("attachment; filename=\"invoice-{}.xml\"", invoice_id))
+        .body(export::to_xml(&invoice))
+}
+
+#[get("/invoice/{id:-?\\d+}/ubl/validate")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn validate_invoice_ubl(data: web::Data<AppData>, web::Path(invoice_id): web::Path<i64>) -> HttpResponse {
+    let (invoice, mut errors) = match load(&data, invoice_id) {
+        Ok(loaded) => loaded,
+        Err(response) => return response
+    };
+
+    errors.extend(validation::validate(&invoice));
+    HttpResponse::Ok().json(Response { valid: errors.is_empty(), errors })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_issued_invoices_can_be_exported() {
+        assert!(check_issued(20240001, InvoiceStatus::Finalized).is_empty());
+        assert!(check_issued(20240001, InvoiceStatus::Paid).is_empty());
+        assert_eq!(check_issued(-4, InvoiceStatus::Draft).len(), 1);
+        assert_eq!(check_issued(20240001, InvoiceStatus::Cancelled)[0].rule, "INVOICR-01");
+        assert_eq!(check_issued(0, InvoiceStatus::Finalized)[0].rule, "BR-02");
+    }
+}
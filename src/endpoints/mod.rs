@@ -2,4 +2,10 @@ pub mod products;
 pub mod persons;
 pub mod pdf;
 pub mod history;
-pub mod ids;
\ No newline at end of file
+pub mod ids;
+pub mod pricelists;
+pub mod invoice;
+pub mod quote;
+pub mod calculate;
+pub mod exchange_rates;
+pub mod auditfile;pub mod vat;
\ No newline at end of file
@@ -0,0 +1,345 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::{TxOpts, PooledConn};
+use crate::AppData;
+use crate::apis::pdf::{PdfCommonPayload, PdfCreditNotePayload, ItemRow, generate_credit_note, PdfGenerationResponse};
+use crate::endpoints::invoice::{InvoiceStatus, get_credited_rows, get_credit_note, set_status};
+use mysql::prelude::Queryable;
+use mysql::{Params, params, Row};
+use crate::endpoints::pdf::{insert_itemrows, get_itemrows};
+use crate::sequences::{next_number, Sequence};
+use crate::calculation::{calculate, Totals};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    invoice_id:     i64,
+    template_name:  String,
+    language:       String,
+    reference:      String,
+    notes:          Option<String>,
+    creation_date:  i64,
+    /// Positions of the invoice rows to credit, counting from 0 in the order of the invoice. The whole invoice is credited when omitted
+    rows:           Option<Vec<usize>>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    #[serde(flatten)]
+    pdf:            PdfGenerationResponse,
+    credit_note_id: i64,
+    totals:         Totals,
+    /// Set when the PDF was generated, but the credit note could not be finalized
+    warning:        Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct RegenerateRequest {
+    id: i64
+}
+
+/**
+Rows that only differ in quantity can stand in for each other when crediting
+*/
+fn same_row(a: &ItemRow, b: &ItemRow) -> bool {
+    a.id == b.id && a.name == b.name && a.description == b.description && a.comment == b.comment
+        && a.price == b.price && a.discount_perc == b.discount_perc && a.vat_perc == b.vat_perc
+}
+
+/**
+The quantity of every invoice row that has not been credited yet. Each credited row counts against the first invoice rows it can stand in for
+*/
+fn remaining_quantities(invoice_rows: &[ItemRow], credited_rows: &[ItemRow]) -> Vec<i64> {
+    let mut remaining: Vec<i64> = invoice_rows.iter().map(|row| row.quantity).collect();
+    for credited in credited_rows {
+        let mut quantity = credited.quantity;
+        for (row, left) in invoice_rows.iter().zip(remaining.iter_mut()) {
+            if quantity <= 0 {
+                break;
+            }
+
+            if same_row(row, credited) {
+                let taken = quantity.min(*left);
+                *left -= taken;
+                quantity -= taken;
+            }
+        }
+    }
+
+    remaining
+}
+
+fn set_credit_note_status<Q: Queryable>(conn: &mut Q, id: i64, status: InvoiceStatus) -> mysql::Result<()> {
+    conn.exec_drop("UPDATE credit_notes SET status = :status WHERE id = :id", params! {
+        "id" => id,
+        "status" => status.as_str()
+    })
+}
+
+/**
+Generate the PDF of a numbered credit note and finalize it. When generation fails the credit note keeps its number and stays
+pending until its PDF is generated again through /pdf/creditnote/regenerate
+*/
+async fn generate_and_finalize(data: &AppData, conn: &mut PooledConn, payload: &PdfCreditNotePayload) -> HttpResponse {
+    let totals = calculate(&payload.common.rows, data.config.rounding);
+    let pdf_id = match generate_credit_note(&data.config, payload).await {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Failed to send Credit note generation request: {:?}", err);
+            return HttpResponse::InternalServerError().json(Response {
+                pdf: PdfGenerationResponse { id: None, error: Some(format!("The PDF could not be generated, credit note {} is pending until it is regenerated", payload.common.id)) },
+                credit_note_id: payload.common.id,
+                totals,
+                warning: None
+            });
+        }
+    };
+
+    let warning = match set_credit_note_status(conn, payload.common.id, InvoiceStatus::Finalized) {
+        Ok(()) => None,
+        Err(err) => {
+            eprintln!("Failed to set credit note status in database: {:?}", err);
+            Some(format!("The PDF was generated, but credit note {} could not be finalized and is still pending", payload.common.id))
+        }
+    };
+
+    HttpResponse::Ok().json(Response { pdf: PdfGenerationResponse { id: Some(pdf_id), error: None }, credit_note_id: payload.common.id, totals, warning })
+}
+
+#[post("/pdf/creditnote")]
+#[has_permissions("INVOICE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn create_credit_note(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err.to_string());
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    //The invoice row stays locked until the credit note is committed, so concurrent requests can not credit the same quantities twice
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let invoice = match tx.exec::<Row, &str, Params>("SELECT * FROM invoices WHERE id = :id FOR UPDATE", params! { "id" => request.invoice_id }) {
+        Ok(rows) => match rows.into_iter().next() {
+            Some(row) => row,
+            None => return HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", request.invoice_id))
+        },
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err.to_string());
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let status = invoice.get::<String, &str>("status").unwrap().parse::<InvoiceStatus>().unwrap();
+    if matches!(status, InvoiceStatus::Draft | InvoiceStatus::PendingGeneration | InvoiceStatus::Cancelled) {
+        return HttpResponse::BadRequest().body(format!("Invoice with status '{}' can not be credited.", status.as_str()));
+    }
+
+    let invoice_rows = match get_itemrows(&mut tx, request.invoice_id, 0, "invoices") {
+        Ok(rows) => rows,
+        Err(err) => {
+            eprintln!("Failed to query itemrows for invoice {}: {:?}", request.invoice_id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Some(position) = request.rows.iter().flatten().find(|position| **position >= invoice_rows.len()) {
+        return HttpResponse::BadRequest().body(format!("Invoice {} has no row at position {}.", request.invoice_id, position));
+    }
+
+    //Quantities that were credited earlier can not be credited again
+    let mut remaining = match get_credited_rows(&mut tx, request.invoice_id) {
+        Ok(credited_rows) => remaining_quantities(&invoice_rows, &credited_rows),
+        Err(err) => {
+            eprintln!("Failed to query credit notes for invoice {}: {:?}", request.invoice_id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut rows = Vec::new();
+    for (position, mut row) in invoice_rows.into_iter().enumerate() {
+        let selected = match &request.rows {
+            Some(selected) => selected.contains(&position),
+            None => true
+        };
+
+        if selected && remaining[position] > 0 {
+            row.quantity = remaining[position];
+            remaining[position] = 0;
+            rows.push(row);
+        }
+    }
+
+    if rows.is_empty() {
+        return HttpResponse::BadRequest().body(format!("Nothing left to credit on invoice {}.", request.invoice_id));
+    }
+
+    let id = match next_number(&mut tx, &data.config, Sequence::CreditNote) {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Failed to allocate credit note number: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let original = PdfCommonPayload::from_row(&invoice, Vec::new());
+    let payload = PdfCreditNotePayload {
+        invoice_id: request.invoice_id,
+        common: PdfCommonPayload {
+            id,
+            template_name: request.template_name.clone(),
+            language: request.language.clone(),
+            reference: request.reference.clone(),
+            notes: request.notes.clone(),
+            expiry_date: request.creation_date,
+            creation_date: request.creation_date,
+            rows,
+            ..original
+        }
+    };
+
+    let sql_create_credit_note = tx.exec::<usize, &str, Params>("INSERT INTO credit_notes \
+        (id, invoice_id, status, template_name, language, attention_of, receiver, reference, notes, expiry_date, creation_date, city, country, postal_code, street, currency, exchange_rate, electronic_address, vat_number, reverse_charge) \
+        VALUES (:id, :invoice_id, :status, :template_name, :language, :attention_of, :receiver, :reference, :notes, :expiry_date, :creation_date, :city, :country, :postal_code, :street, :currency, :exchange_rate, :electronic_address, :vat_number, :reverse_charge)", params! {
+
+        "id" => &payload.common.id,
+        "invoice_id" => &payload.invoice_id,
+        "status" => InvoiceStatus::PendingGeneration.as_str(),
+        "template_name" => &payload.common.template_name,
+        "language" => &payload.common.language,
+        "attention_of" => &payload.common.attention_of,
+        "receiver" => &payload.common.receiver,
+        "reference" => &payload.common.reference,
+        "notes" => &payload.common.notes,
+        "expiry_date" => &payload.common.expiry_date,
+        "creation_date" => &payload.common.creation_date,
+        "city" => &payload.common.address.city,
+        "country" => &payload.common.address.country,
+        "postal_code" => &payload.common.address.postal_code,
+        "street" => &payload.common.address.street,
+        "currency" => &payload.common.currency,
+        "exchange_rate" => payload.common.exchange_rate,
+        "electronic_address" => &payload.common.electronic_address,
+        "vat_number" => &payload.common.address.vat_number,
+        "reverse_charge" => payload.common.reverse_charge
+    });
+
+    if sql_create_credit_note.is_err() {
+        eprintln!("Failed to create new credit note in database: {:?}", sql_create_credit_note.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = insert_itemrows(&mut tx, payload.common.id, 0, "credit_notes", &payload.common.rows) {
+        eprintln!("Failed to insert ItemRows into database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    //Crediting what was left of the invoice settles it
+    if remaining.iter().all(|quantity| *quantity == 0) && status.can_transition_to(InvoiceStatus::Credited) {
+        if let Err(err) = set_status(&mut tx, request.invoice_id, InvoiceStatus::Credited) {
+            eprintln!("Failed to update invoice status in the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    //The credit note is committed before its PDF is generated, so the number sequence is not locked while the PDF service is called.
+    //Credit notes are invoices too, their number is never handed out again and the credit note is pending until its PDF has been generated
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit credit note to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    generate_and_finalize(&data, &mut conn, &payload).await
+}
+
+/**
+Generate the PDF of a credit note again. A pending credit note is finalized once its PDF has been generated
+*/
+#[post("/pdf/creditnote/regenerate")]
+#[has_permissions("INVOICE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn regenerate_credit_note(data: web::Data<AppData>, request: web::Json<RegenerateRequest>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err.to_string());
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let (payload, status) = match get_credit_note(&mut conn, request.id) {
+        Ok(Some(credit_note)) => credit_note,
+        Ok(None) => return HttpResponse::NotFound().body(format!("Credit note with ID {} does not exist.", request.id)),
+        Err(err) => {
+            eprintln!("Failed to query credit note from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if status == InvoiceStatus::PendingGeneration {
+        return generate_and_finalize(&data, &mut conn, &payload).await;
+    }
+
+    match generate_credit_note(&data.config, &payload).await {
+        Ok(id) => HttpResponse::Ok().json(Response { pdf: PdfGenerationResponse { id: Some(id), error: None }, credit_note_id: payload.common.id, totals: calculate(&payload.common.rows, data.config.rounding), warning: None }),
+        Err(err) => {
+            eprintln!("Failed to send Credit note generation request: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: &str, price: &str, quantity: i64) -> ItemRow {
+        ItemRow {
+            comment: None,
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            discount_perc: None,
+            vat_perc: "21".parse().unwrap(),
+            price: price.parse().unwrap(),
+            quantity
+        }
+    }
+
+    #[test]
+    fn nothing_credited_leaves_every_quantity() {
+        let invoice = [row("A", "10", 2), row("B", "5", 1)];
+        assert_eq!(remaining_quantities(&invoice, &[]), [2, 1]);
+    }
+
+    #[test]
+    fn credits_count_against_the_matching_row() {
+        let invoice = [row("A", "10", 1), row("A", "20", 1)];
+        assert_eq!(remaining_quantities(&invoice, &[row("A", "20", 1)]), [1, 0]);
+        assert_eq!(remaining_quantities(&invoice, &[row("A", "10", 1)]), [0, 1]);
+    }
+
+    #[test]
+    fn identical_rows_are_credited_in_order() {
+        let invoice = [row("A", "10", 2), row("A", "10", 3)];
+        assert_eq!(remaining_quantities(&invoice, &[row("A", "10", 3)]), [0, 2]);
+        assert_eq!(remaining_quantities(&invoice, &[row("A", "10", 5), row("A", "10", 1)]), [0, 0]);
+    }
+
+    #[test]
+    fn rows_that_differ_are_not_the_same() {
+        let mut discounted = row("A", "10", 1);
+        discounted.discount_perc = Some("10".parse().unwrap());
+        assert!(!same_row(&row("A", "10", 1), &discounted));
+        assert!(same_row(&row("A", "10", 1), &row("A", "10.000000", 4)));
+    }
+}
@@ -1,13 +1,66 @@
 use actix_web::{post, web, HttpResponse};
 use actix_web_grants::proc_macro::has_permissions;
+use mysql::{TxOpts, PooledConn};
 use crate::AppData;
 use crate::apis::pdf::{PdfCommonPayload, generate_invoice, PdfGenerationResponse};
+use crate::endpoints::invoice::{InvoiceStatus, set_status, insert_invoice, get_invoice};
+use serde::{Serialize, Deserialize};
+use crate::sequences::{next_number, Sequence};
+use crate::calculation::{calculate, Totals};
+use crate::endpoints::exchange_rates::snapshot_rate;
+use crate::endpoints::vat::apply_reverse_charge;
 use mysql::prelude::Queryable;
-use mysql::{Params, params, Row};
-use rand::Rng;
+use mysql::{Params, params};
+
+#[derive(Serialize)]
+pub struct Response {
+    #[serde(flatten)]
+    pdf:            PdfGenerationResponse,
+    invoice_id:     i64,
+    totals:         Totals,
+    /// Set when the PDF was generated, but the invoice could not be finalized
+    warning:        Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct RegenerateRequest {
+    id: i64
+}
+
+/**
+Generate the PDF of a numbered invoice and finalize it. When generation fails the invoice keeps its number and stays
+pending until its PDF is generated again through /pdf/invoice/regenerate
+*/
+pub async fn generate_and_finalize(data: &AppData, conn: &mut PooledConn, payload: &PdfCommonPayload) -> HttpResponse {
+    let totals = calculate(&payload.rows, data.config.rounding);
+    let pdf_id = match generate_invoice(&data.config, payload).await {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Failed to send Invoice generation request: {:?}", err);
+            return HttpResponse::InternalServerError().json(Response {
+                pdf: PdfGenerationResponse { id: None, error: Some(format!("The PDF could not be generated, invoice {} is pending until it is regenerated", payload.id)) },
+                invoice_id: payload.id,
+                totals,
+                warning: None
+            });
+        }
+    };
+
+    //The PDF exists, so the invoice has been issued even when its status can not be updated
+    let warning = match set_status(conn, payload.id, InvoiceStatus::Finalized) {
+        Ok(()) => None,
+        Err(err) => {
+            eprintln!("Failed to set invoice status in database: {:?}", err);
+            Some(format!("The PDF was generated, but invoice {} could not be finalized and is still pending", payload.id))
+        }
+    };
+
+    HttpResponse::Ok().json(Response { pdf: PdfGenerationResponse { id: Some(pdf_id), error: None }, invoice_id: payload.id, totals, warning })
+}
 
 #[post("/pdf/invoice")]
 #[has_permissions("INVOICE_CREATE")]
+#[allow(clippy::async_yields_async)]
 pub async fn create_invoice(data: web::Data<AppData>, payload: web::Json<PdfCommonPayload>) -> HttpResponse {
     let mut conn = match data.pool.get_conn() {
         Ok(conn) => conn,
@@ -17,86 +70,94 @@ pub async fn create_invoice(data: web::Data<AppData>, payload: web::Json<PdfComm
         }
     };
 
-    //Fetch all invoice IDs to make sure we don't have double IDs
-    let sql_get_invoice_ids = match conn.query::<Row, &str>("SELECT id FROM invoices") {
-        Ok(row) => row,
+    //The invoice is committed before its PDF is generated, so the number sequence is not locked while the PDF service is called.
+    //Its number is never handed out again, the invoice is pending until its PDF has been generated
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
         Err(err) => {
-            eprintln!("Failed to query invoice IDs from the database: {:?}", err.to_string());
+            eprintln!("Failed to start database transaction: {:?}", err);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    let invoice_ids = {
-        let mut invoice_ids = Vec::with_capacity(sql_get_invoice_ids.len());
-        for row in sql_get_invoice_ids {
-            let id = row.get::<i64, &str>("id").unwrap();
-            invoice_ids.push(id);
+    //Document numbers are allocated by the server, the ID sent by the client is ignored
+    let mut payload = payload.into_inner();
+    payload.id = match next_number(&mut tx, &data.config, Sequence::Invoice) {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Failed to allocate invoice number: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
         }
-
-        invoice_ids
     };
 
-    if invoice_ids.contains(&payload.id) {
-        return HttpResponse::Conflict().body(format!("Invoice with ID {} already exists.", &payload.id));
+    match snapshot_rate(&mut tx, &data.config, &mut payload) {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to query exchange rate from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    if let Err(err) = apply_reverse_charge(&data.config, &mut payload) {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    if let Err(err) = insert_invoice(&mut tx, &payload, None, InvoiceStatus::PendingGeneration) {
+        eprintln!("Failed to create new invoice in database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
     }
 
-    let sql_create_invoice = conn.exec::<usize, &str, Params>("INSERT INTO invoices \
-        (id, template_name, language, attention_of, receiver, reference, notes, expiry_date, creation_date, city, country, postal_code, street) \
-        VALUES (:id, :template_name, :language, :attention_of, :receiver, :reference, :notes, :expiry_date, :creation_date, :city, :country, :postal_code, :street)", params! {
-
-        "id" => &payload.id,
-        "template_name" => &payload.template_name,
-        "language" => &payload.language,
-        "attention_of" => &payload.attention_of,
-        "receiver" => &payload.receiver,
-        "reference" => &payload.reference,
-        "notes" => &payload.notes,
-        "expiry_date" => &payload.expiry_date,
-        "creation_date" => &payload.creation_date,
-        "city" => &payload.address.city,
-        "country" => &payload.address.country,
-        "postal_code" => &payload.address.postal_code,
-        "street" => &payload.address.street
-    });
-
-    if sql_create_invoice.is_err() {
-        eprintln!("Failed to create new invoice in database: {:?}", sql_create_invoice.err().unwrap());
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit invoice to the database: {:?}", err);
         return HttpResponse::InternalServerError().finish();
     }
 
-    for row in payload.rows.iter() {
-        let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
-        let sql_create_item_row = conn.exec::<usize, &str, Params>("INSERT INTO itemrows \
-            (id, product_id, parent_id, parent_type, comment, name, description, discount_perc, vat_perc, price, quantity) \
-            VALUES (:id, :product_id, :parent_id, :parent_type, :comment, :name, :description, :discount_perc, :vat_perc, :price, :quantity)", params! {
-
-            "id" => id,
-            "product_id" => &row.id,
-            "parent_id" => &payload.id,
-            "parent_type" => "invoices",
-            "comment" => &row.comment,
-            "name" => &row.name,
-            "description" => &row.description,
-            "discount_perc" => &row.discount_perc,
-            "vat_perc" => &row.vat_perc,
-            "price" => &row.price,
-            "quantity" => &row.quantity
-        });
-
-        if sql_create_item_row.is_err() {
-            eprintln!("Failed to insert ItemRow into database: {:?}", sql_create_item_row.err().unwrap());
+    generate_and_finalize(&data, &mut conn, &payload).await
+}
+
+/**
+Generate the PDF of an invoice again. A pending invoice is finalized once its PDF has been generated
+*/
+#[post("/pdf/invoice/regenerate")]
+#[has_permissions("INVOICE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn regenerate_invoice(data: web::Data<AppData>, request: web::Json<RegenerateRequest>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err.to_string());
             return HttpResponse::InternalServerError().finish();
         }
-    }
+    };
 
-    //Generate the PDF
-    let id = match generate_invoice(&data.config, &*payload).await {
-        Ok(id) => id,
+    let payload = match get_invoice(&mut conn, request.id) {
+        Ok(Some(payload)) => payload,
+        Ok(None) => return HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", request.id)),
         Err(err) => {
-            eprintln!("Failed to send Invoice generation request: {:?}", err);
+            eprintln!("Failed to query invoice from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let status = match conn.exec_first::<String, &str, Params>("SELECT status FROM invoices WHERE id = :id", params! { "id" => request.id }) {
+        Ok(Some(status)) => status.parse::<InvoiceStatus>().unwrap(),
+        Ok(None) => return HttpResponse::NotFound().body(format!("Invoice with ID {} does not exist.", request.id)),
+        Err(err) => {
+            eprintln!("Failed to query invoice from the database: {:?}", err);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    HttpResponse::Ok().json(PdfGenerationResponse { id: Some(id), error: None })
+    match status {
+        InvoiceStatus::PendingGeneration => generate_and_finalize(&data, &mut conn, &payload).await,
+        InvoiceStatus::Draft | InvoiceStatus::Cancelled => HttpResponse::BadRequest().body(format!("Invoice with status '{}' has no PDF.", status.as_str())),
+        _ => match generate_invoice(&data.config, &payload).await {
+            Ok(id) => HttpResponse::Ok().json(Response { pdf: PdfGenerationResponse { id: Some(id), error: None }, invoice_id: payload.id, totals: calculate(&payload.rows, data.config.rounding), warning: None }),
+            Err(err) => {
+                eprintln!("Failed to send Invoice generation request: {:?}", err);
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -1,2 +1,76 @@
 pub mod quote;
-pub mod invoice;
\ No newline at end of file
+pub mod invoice;
+pub mod credit_note;
+
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, Value, params};
+use rand::Rng;
+use std::collections::HashMap;
+use crate::apis::pdf::ItemRow;
+
+/**
+Insert the rows of a document into the itemrows table, keeping their order. `parent_revision` is only used by quotes, other documents have revision 0
+*/
+pub fn insert_itemrows<Q: Queryable>(conn: &mut Q, parent_id: i64, parent_revision: i32, parent_type: &str, rows: &[ItemRow]) -> mysql::Result<()> {
+    for (position, row) in rows.iter().enumerate() {
+        let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+        conn.exec_drop("INSERT INTO itemrows \
+            (id, product_id, parent_id, parent_revision, parent_type, position, comment, name, description, discount_perc, vat_perc, price, quantity) \
+            VALUES (:id, :product_id, :parent_id, :parent_revision, :parent_type, :position, :comment, :name, :description, :discount_perc, :vat_perc, :price, :quantity)", params! {
+
+            "id" => id,
+            "product_id" => &row.id,
+            "parent_id" => parent_id,
+            "parent_revision" => parent_revision,
+            "parent_type" => parent_type,
+            "position" => position,
+            "comment" => &row.comment,
+            "name" => &row.name,
+            "description" => &row.description,
+            "discount_perc" => &row.discount_perc,
+            "vat_perc" => &row.vat_perc,
+            "price" => &row.price,
+            "quantity" => &row.quantity
+        })?;
+    }
+
+    Ok(())
+}
+
+/**
+Fetch the rows of a document from the itemrows table, in their order on the document
+*/
+pub fn get_itemrows<Q: Queryable>(conn: &mut Q, parent_id: i64, parent_revision: i32, parent_type: &str) -> mysql::Result<Vec<ItemRow>> {
+    let rows = conn.exec::<Row, &str, Params>("SELECT * FROM itemrows WHERE parent_id = :parent_id AND parent_revision = :parent_revision AND parent_type = :parent_type ORDER BY position, id", params! {
+        "parent_id" => parent_id,
+        "parent_revision" => parent_revision,
+        "parent_type" => parent_type
+    })?;
+
+    Ok(rows.iter().map(ItemRow::from_row).collect())
+}
+
+/**
+Fetch the rows of several documents of the same type in a single query, keyed on parent id and revision
+*/
+pub fn get_itemrows_batch<Q: Queryable>(conn: &mut Q, parents: &[(i64, i32)], parent_type: &str) -> mysql::Result<HashMap<(i64, i32), Vec<ItemRow>>> {
+    let mut itemrows: HashMap<(i64, i32), Vec<ItemRow>> = parents.iter().map(|parent| (*parent, Vec::new())).collect();
+    if parents.is_empty() {
+        return Ok(itemrows);
+    }
+
+    let placeholders = vec!["?"; parents.len()].join(", ");
+    let mut values = vec![Value::from(parent_type)];
+    values.extend(parents.iter().map(|(id, _)| Value::from(id)));
+
+    let rows = conn.exec::<Row, String, Params>(format!("SELECT * FROM itemrows WHERE parent_type = ? AND parent_id IN ({}) ORDER BY position, id", placeholders), Params::Positional(values))?;
+    for row in rows {
+        let parent = (row.get("parent_id").unwrap(), row.get("parent_revision").unwrap());
+        //Rows of other quote revisions are skipped
+        if let Some(parent_rows) = itemrows.get_mut(&parent) {
+            parent_rows.push(ItemRow::from_row(&row));
+        }
+    }
+
+    Ok(itemrows)
+}
\ No newline at end of file
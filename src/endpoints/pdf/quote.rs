@@ -1,13 +1,26 @@
 use actix_web::{post, web, HttpResponse};
 use actix_web_grants::proc_macro::has_permissions;
+use mysql::TxOpts;
 use crate::AppData;
 use crate::apis::pdf::{generate_quote, PdfGenerationResponse, PdfQuotePayload};
-use mysql::prelude::Queryable;
-use mysql::{Params, params, Row};
-use rand::Rng;
+use crate::endpoints::quote::{QuoteStatus, insert_quote, set_revision_status, delete_revision};
+use serde::Serialize;
+use crate::sequences::{next_number, Sequence};
+use crate::calculation::{calculate, Totals};
+use crate::endpoints::exchange_rates::snapshot_rate;
+use crate::endpoints::vat::apply_reverse_charge;
+
+#[derive(Serialize)]
+pub struct Response {
+    #[serde(flatten)]
+    pdf:            PdfGenerationResponse,
+    quote_id:       i64,
+    totals:         Totals
+}
 
 #[post("/pdf/quote")]
 #[has_permissions("QUOTE_CREATE")]
+#[allow(clippy::async_yields_async)]
 pub async fn create_quote(data: web::Data<AppData>, payload: web::Json<PdfQuotePayload>) -> HttpResponse {
     let mut conn = match data.pool.get_conn() {
         Ok(conn) => conn,
@@ -17,89 +30,66 @@ pub async fn create_quote(data: web::Data<AppData>, payload: web::Json<PdfQuoteP
         }
     };
 
-    //Fetch all quote IDs to make sure we don't have double IDs
-    let sql_get_quote_ids = match conn.query::<Row, &str>("SELECT id FROM quotes") {
-        Ok(row) => row,
+    //The quote is committed as a draft before its PDF is generated, so the number sequence is not locked while the PDF service is called.
+    //When generation fails the quote is deleted again. Quotes are not bookkept, so the gap this leaves in their numbering is harmless
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
         Err(err) => {
-            eprintln!("Failed to query quote IDs from the database: {:?}", err.to_string());
+            eprintln!("Failed to start database transaction: {:?}", err);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    let quote_ids = {
-        let mut quote_ids = Vec::with_capacity(sql_get_quote_ids.len());
-        for row in sql_get_quote_ids {
-            let id = row.get::<i64, &str>("id").unwrap();
-            quote_ids.push(id);
+    //Document numbers are allocated by the server, the ID sent by the client is ignored
+    let mut payload = payload.into_inner();
+    payload.common.id = match next_number(&mut tx, &data.config, Sequence::Quote) {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Failed to allocate quote number: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
         }
-
-        quote_ids
     };
 
-    if quote_ids.contains(&payload.common.id) {
-        return HttpResponse::Conflict().body(format!("Quote with ID {} already exists.", &payload.common.id));
+    match snapshot_rate(&mut tx, &data.config, &mut payload.common) {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to query exchange rate from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
     }
 
-    let sql_create_quote = conn.exec::<usize, &str, Params>("INSERT INTO quotes \
-        (id, template_name, language, attention_of, receiver, reference, notes, expiry_date, creation_date, city, country, postal_code, street, quote_topic, quote_contact_person, debit_id) \
-        VALUES (:id, :template_name, :language, :attention_of, :receiver, :reference, :notes, :expiry_date, :creation_date, :city, :country, :postal_code, :street, :quote_topic, :quote_contact_person, :debit_id)", params! {
-
-        "id" => &payload.common.id,
-        "template_name" => &payload.common.template_name,
-        "language" => &payload.common.language,
-        "attention_of" => &payload.common.attention_of,
-        "receiver" => &payload.common.receiver,
-        "reference" => &payload.common.reference,
-        "notes" => &payload.common.notes,
-        "expiry_date" => &payload.common.expiry_date,
-        "creation_date" => &payload.common.creation_date,
-        "city" => &payload.common.address.city,
-        "country" => &payload.common.address.country,
-        "postal_code" => &payload.common.address.postal_code,
-        "street" => &payload.common.address.street,
-        "quote_topic" => &payload.quote_topic,
-        "quote_contact_person" => &payload.quote_contact_person,
-        "debit_id" => &payload.debit_id
-    });
+    if let Err(err) = apply_reverse_charge(&data.config, &mut payload.common) {
+        return HttpResponse::BadRequest().body(err);
+    }
 
-    if sql_create_quote.is_err() {
-        eprintln!("Failed to create new quote in database: {:?}", sql_create_quote.err().unwrap());
+    payload.revision = 0;
+    if let Err(err) = insert_quote(&mut tx, &payload, QuoteStatus::Draft) {
+        eprintln!("Failed to create new quote in database: {:?}", err);
         return HttpResponse::InternalServerError().finish();
     }
 
-    for row in payload.common.rows.iter() {
-        let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
-        let sql_create_item_row = conn.exec::<usize, &str, Params>("INSERT INTO itemrows \
-            (id, product_id, parent_id, parent_type, comment, name, description, discount_perc, vat_perc, price, quantity) \
-            VALUES (:id, :product_id, :parent_id, :parent_type,:comment, :name, :description, :discount_perc, :vat_perc, :price, :quantity)", params! {
-
-            "id" => id,
-            "product_id" => &row.id,
-            "parent_id" => &payload.common.id,
-            "parent_type" => "quotes",
-            "comment" => &row.comment,
-            "name" => &row.name,
-            "description" => &row.description,
-            "discount_perc" => &row.discount_perc,
-            "vat_perc" => &row.vat_perc,
-            "price" => &row.price,
-            "quantity" => &row.quantity
-        });
-
-        if sql_create_item_row.is_err() {
-            eprintln!("Failed to insert ItemRow into database: {:?}", sql_create_item_row.err().unwrap());
-            return HttpResponse::InternalServerError().finish();
-        }
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit quote to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
     }
 
     //Generate the PDF
-    let id = match generate_quote(&data.config, &*payload).await {
+    let id = match generate_quote(&data.config, &payload).await {
         Ok(id) => id,
         Err(err) => {
             eprintln!("Failed to send Quote generation request: {:?}", err);
+            if let Err(err) = delete_revision(&mut conn, payload.common.id, payload.revision) {
+                eprintln!("Failed to remove quote {} after its PDF could not be generated: {:?}", payload.common.id, err);
+            }
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    HttpResponse::Ok().json(PdfGenerationResponse { id: Some(id), error: None })
+    if let Err(err) = set_revision_status(&mut conn, payload.common.id, payload.revision, QuoteStatus::Open) {
+        eprintln!("Failed to set quote status in database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(Response { pdf: PdfGenerationResponse { id: Some(id), error: None }, quote_id: payload.common.id, totals: calculate(&payload.common.rows, data.config.rounding) })
 }
\ No newline at end of file
@@ -22,6 +22,7 @@ pub struct Contact {
 
 #[get("/persons/get")]
 #[has_permissions("PERSONS_READ")]
+#[allow(clippy::async_yields_async)]
 pub async fn get_contacts(data: web::Data<AppData>) -> HttpResponse {
     let contacts = Communication::query_contact(&data.espocrm_data);
     let accounts = Communication::query_account(&data.espocrm_data);
@@ -0,0 +1,90 @@
+use actix_web::{HttpResponse, web, post};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Serialize;
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, TxOpts, params};
+use rand::Rng;
+use crate::AppData;
+use crate::endpoints::pricelists::{PriceList, validate_entries, insert_entries, get_product_ids};
+
+#[derive(Serialize)]
+pub struct Response {
+    id:     Option<String>,
+    error:  Option<String>
+}
+
+#[post("/pricelists/add")]
+#[has_permissions("PRODUCTS_WRITE")]
+#[allow(clippy::async_yields_async)]
+pub async fn add_price_list(data: web::Data<AppData>, request: web::Json<PriceList>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Unable to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    //There is one default price list, and at most one price list per Account
+    let sql_get_existing = tx.exec::<Row, &str, Params>("SELECT id FROM price_lists WHERE account_id <=> :account_id", params! {
+        "account_id" => &request.account_id
+    });
+
+    match sql_get_existing {
+        Ok(rows) => if !rows.is_empty() {
+            let error = match &request.account_id {
+                Some(account_id) => format!("A price list for account '{}' already exists!", account_id),
+                None => "A default price list already exists!".to_string()
+            };
+
+            return HttpResponse::BadRequest().json(Response { id: None, error: Some(error) });
+        },
+        Err(err) => {
+            eprintln!("Unable to fetch price lists from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let product_ids = match get_product_ids(&mut tx) {
+        Ok(ids) => ids,
+        Err(err) => {
+            eprintln!("Unable to fetch products from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(err) = validate_entries(&request.entries, &product_ids) {
+        return HttpResponse::BadRequest().json(Response { id: None, error: Some(err) });
+    }
+
+    let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+    let sql_insert_price_list = tx.exec::<usize, &str, Params>("INSERT INTO price_lists (id, name, account_id) VALUES (:id, :name, :account_id)", params! {
+        "id" => &id,
+        "name" => &request.name,
+        "account_id" => &request.account_id
+    });
+
+    if sql_insert_price_list.is_err() {
+        eprintln!("Unable to add price list to the database: {:?}", sql_insert_price_list.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = insert_entries(&mut tx, &id, &request.entries) {
+        eprintln!("Unable to add price list entries to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Unable to commit price list to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(Response { id: Some(id), error: None })
+}
@@ -0,0 +1,66 @@
+use actix_web::{post, HttpResponse, web};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, params};
+use crate::AppData;
+
+#[derive(Deserialize)]
+pub struct Request {
+    price_lists: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    error: Option<String>
+}
+
+#[post("/pricelists/del")]
+#[has_permissions("PRODUCTS_WRITE")]
+#[allow(clippy::async_yields_async)]
+pub async fn del_price_list(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+    let sql_get_price_lists = conn.query::<Row, &str>("SELECT id FROM price_lists");
+    if sql_get_price_lists.is_err() {
+        eprintln!("Unable to fetch price lists from the database: {:?}", sql_get_price_lists.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut price_list_ids: Vec<String> = Vec::new();
+    for row in sql_get_price_lists.unwrap() {
+        let id = row.get("id").unwrap();
+        price_list_ids.push(id);
+    }
+
+    for price_list_id in request.price_lists.clone() {
+        if !price_list_ids.contains(&price_list_id) {
+            return HttpResponse::BadRequest().json(Response { error: Some(format!("Price list with id '{}' does not exist!", price_list_id))});
+        }
+
+        let sql_delete_entries = conn.exec::<usize, &str, Params>("DELETE FROM price_list_entries WHERE price_list_id = :id", params! {
+            "id" => &price_list_id
+        });
+
+        if sql_delete_entries.is_err() {
+            eprintln!("Unable to delete price list entries from database: {:?}", sql_delete_entries.err().unwrap());
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        let sql_delete_price_list = conn.exec::<usize, &str, Params>("DELETE FROM price_lists WHERE id = :id", params! {
+            "id" => &price_list_id
+        });
+
+        if sql_delete_price_list.is_err() {
+            eprintln!("Unable to delete price list from database: {:?}", sql_delete_price_list.err().unwrap());
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    HttpResponse::Ok().finish()
+}
@@ -0,0 +1,88 @@
+use actix_web::{post, HttpResponse, web};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, TxOpts, params};
+use crate::AppData;
+use crate::endpoints::pricelists::{PriceListEntry, validate_entries, insert_entries, get_product_ids};
+
+#[derive(Deserialize)]
+pub struct Request {
+    price_list_id:  String,
+    entries:        Vec<PriceListEntry>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    error: Option<String>
+}
+
+/**
+Replace all entries of a price list. The old entries are only removed when all new entries could be stored
+*/
+#[post("/pricelists/entries/set")]
+#[has_permissions("PRODUCTS_WRITE")]
+#[allow(clippy::async_yields_async)]
+pub async fn set_price_list_entries(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Unable to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_price_list = tx.exec::<Row, &str, Params>("SELECT id FROM price_lists WHERE id = :id FOR UPDATE", params! {
+        "id" => &request.price_list_id
+    });
+
+    match sql_get_price_list {
+        Ok(rows) => if rows.is_empty() {
+            return HttpResponse::BadRequest().json(Response { error: Some(format!("Price list with id '{}' does not exist!", &request.price_list_id))});
+        },
+        Err(err) => {
+            eprintln!("Unable to fetch price list from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let product_ids = match get_product_ids(&mut tx) {
+        Ok(ids) => ids,
+        Err(err) => {
+            eprintln!("Unable to fetch products from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(err) = validate_entries(&request.entries, &product_ids) {
+        return HttpResponse::BadRequest().json(Response { error: Some(err) });
+    }
+
+    let sql_delete_entries = tx.exec::<usize, &str, Params>("DELETE FROM price_list_entries WHERE price_list_id = :id", params! {
+        "id" => &request.price_list_id
+    });
+
+    if sql_delete_entries.is_err() {
+        eprintln!("Unable to delete price list entries from database: {:?}", sql_delete_entries.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = insert_entries(&mut tx, &request.price_list_id, &request.entries) {
+        eprintln!("Unable to add price list entries to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Unable to commit price list entries to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().finish()
+}
@@ -0,0 +1,60 @@
+use actix_web::{HttpResponse, web, get};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Serialize;
+use mysql::prelude::Queryable;
+use mysql::Row;
+use std::collections::HashMap;
+use crate::appdata::AppData;
+use crate::endpoints::pricelists::{PriceList, PriceListEntry};
+
+#[derive(Serialize)]
+pub struct Response {
+    price_lists: Vec<PriceList>
+}
+
+#[get("/pricelists/get")]
+#[has_permissions("PRODUCTS_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_price_lists(data: web::Data<AppData>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create a database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+
+    let sql_price_lists = conn.query::<Row, &str>("SELECT id,name,account_id FROM price_lists ORDER BY name ASC");
+    if sql_price_lists.is_err() {
+        eprintln!("Unable to fetch price lists from the database: {:?}", sql_price_lists.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let sql_entries = conn.query::<Row, &str>("SELECT price_list_id,product_id,min_quantity,price FROM price_list_entries ORDER BY product_id ASC, min_quantity ASC");
+    if sql_entries.is_err() {
+        eprintln!("Unable to fetch price list entries from the database: {:?}", sql_entries.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut entries: HashMap<String, Vec<PriceListEntry>> = HashMap::new();
+    for row in sql_entries.unwrap() {
+        entries.entry(row.get("price_list_id").unwrap()).or_default().push(PriceListEntry {
+            product_id: row.get("product_id").unwrap(),
+            min_quantity: row.get("min_quantity").unwrap(),
+            price: row.get("price").unwrap()
+        });
+    }
+
+    let mut price_lists = Vec::new();
+    for row in sql_price_lists.unwrap() {
+        let id: String = row.get("id").unwrap();
+        price_lists.push(PriceList {
+            entries: entries.remove(&id).unwrap_or_default(),
+            id: Some(id),
+            name: row.get("name").unwrap(),
+            account_id: row.get("account_id").unwrap()
+        });
+    }
+
+    HttpResponse::Ok().json(Response { price_lists })
+}
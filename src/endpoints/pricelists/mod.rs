@@ -0,0 +1,103 @@
+pub mod get;
+pub mod add;
+pub mod del;
+pub mod entries;
+pub mod price;
+
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::params;
+use rand::Rng;
+use crate::money::Money;
+
+/**
+A price list either belongs to an EspoCRM Account, or is the default price list when `account_id` is empty
+*/
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PriceList {
+    pub id:             Option<String>,
+    pub name:           String,
+    pub account_id:     Option<String>,
+    #[serde(default)]
+    pub entries:        Vec<PriceListEntry>
+}
+
+/**
+The price of a Product in a price list, applicable from `min_quantity` units upwards
+*/
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PriceListEntry {
+    pub product_id:     String,
+    #[serde(default = "default_min_quantity")]
+    pub min_quantity:   i64,
+    pub price:          Money
+}
+
+fn default_min_quantity() -> i64 {
+    1
+}
+
+/**
+Pick the entry that applies to `quantity` units. Entries of the Account's own price list take precedence over
+the default price list, within a list the entry with the highest applicable quantity break wins.
+
+`entries` holds the price list id, whether that list is the Account's own list, and the entry itself
+*/
+pub fn resolve_price(entries: &[(String, bool, PriceListEntry)], quantity: i64) -> Option<&(String, bool, PriceListEntry)> {
+    entries.iter()
+        .filter(|(_, _, entry)| entry.min_quantity <= quantity)
+        .max_by_key(|(_, is_account, entry)| (*is_account, entry.min_quantity))
+}
+
+/**
+Check the entries of a price list for unknown products, invalid quantities or prices and duplicate quantity breaks
+*/
+pub fn validate_entries(entries: &[PriceListEntry], product_ids: &[String]) -> Result<(), String> {
+    let mut seen = Vec::new();
+    for entry in entries {
+        if !product_ids.contains(&entry.product_id) {
+            return Err(format!("Product with id '{}' does not exist!", &entry.product_id));
+        }
+
+        if entry.min_quantity < 1 {
+            return Err(format!("Minimum quantity for product '{}' must be at least 1", &entry.product_id));
+        }
+
+        if entry.price < Money::ZERO {
+            return Err(format!("Price for product '{}' may not be negative", &entry.product_id));
+        }
+
+        let key = (entry.product_id.clone(), entry.min_quantity);
+        if seen.contains(&key) {
+            return Err(format!("Product '{}' has more than one price for quantity {}", &entry.product_id, entry.min_quantity));
+        }
+        seen.push(key);
+    }
+
+    Ok(())
+}
+
+/**
+Insert the entries of a price list
+*/
+pub fn insert_entries<Q: Queryable>(conn: &mut Q, price_list_id: &str, entries: &[PriceListEntry]) -> mysql::Result<()> {
+    for entry in entries {
+        let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+        conn.exec_drop("INSERT INTO price_list_entries (id, price_list_id, product_id, min_quantity, price) VALUES (:id, :price_list_id, :product_id, :min_quantity, :price)", params! {
+            "id" => id,
+            "price_list_id" => price_list_id,
+            "product_id" => &entry.product_id,
+            "min_quantity" => entry.min_quantity,
+            "price" => entry.price
+        })?;
+    }
+
+    Ok(())
+}
+
+/**
+Fetch the ids of all products
+*/
+pub fn get_product_ids<Q: Queryable>(conn: &mut Q) -> mysql::Result<Vec<String>> {
+    conn.query::<String, &str>("SELECT id FROM products")
+}
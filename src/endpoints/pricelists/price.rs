@@ -0,0 +1,128 @@
+use actix_web::{HttpResponse, web, get};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, params};
+use crate::appdata::AppData;
+use crate::endpoints::pricelists::{PriceListEntry, resolve_price};
+use rust_decimal::RoundingStrategy;
+use crate::money::Money;
+
+#[derive(Deserialize)]
+pub struct Query {
+    product_id:     String,
+    account_id:     Option<String>,
+    quantity:       Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    product_id:     String,
+    quantity:       i64,
+    base_price:     Money,
+    price:          Money,
+    /// The discount relative to the base price of the product, suitable for `ItemRow.discount_perc`. Never negative
+    discount_perc:  Money,
+    /// The price list the price was taken from, empty if the base price of the product applies
+    price_list_id:  Option<String>
+}
+
+/**
+The discount of `price` relative to `base_price` in percent, rounded to the six decimals a discount is stored with.
+A price above the base price is no discount, that gives 0
+*/
+fn discount_perc(base_price: Money, price: Money) -> Money {
+    if base_price <= Money::ZERO || price >= base_price {
+        return Money::ZERO;
+    }
+
+    ((Money::ONE - price / base_price) * Money::ONE_HUNDRED).round_dp_with_strategy(6, RoundingStrategy::MidpointAwayFromZero)
+}
+
+#[get("/pricelists/price")]
+#[has_permissions("PRODUCTS_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_price(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create a database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+    let quantity = query.quantity.unwrap_or(1);
+
+    let sql_get_product = conn.exec::<Row, &str, Params>("SELECT price FROM products WHERE id = :id AND archived = 0", params! {
+        "id" => &query.product_id
+    });
+
+    let base_price: Money = match sql_get_product {
+        Ok(rows) => match rows.into_iter().next() {
+            Some(row) => row.get("price").unwrap(),
+            None => return HttpResponse::NotFound().body(format!("Product with id '{}' does not exist.", &query.product_id))
+        },
+        Err(err) => {
+            eprintln!("Unable to fetch product from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_entries = conn.exec::<Row, &str, Params>("SELECT price_lists.id AS price_list_id, price_lists.account_id, price_list_entries.min_quantity, price_list_entries.price \
+        FROM price_list_entries INNER JOIN price_lists ON price_lists.id = price_list_entries.price_list_id \
+        WHERE price_list_entries.product_id = :product_id AND (price_lists.account_id IS NULL OR price_lists.account_id = :account_id)", params! {
+        "product_id" => &query.product_id,
+        "account_id" => &query.account_id
+    });
+
+    if sql_get_entries.is_err() {
+        eprintln!("Unable to fetch price list entries from the database: {:?}", sql_get_entries.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut entries = Vec::new();
+    for row in sql_get_entries.unwrap() {
+        let is_account = row.get::<Option<String>, &str>("account_id").unwrap().is_some();
+        entries.push((row.get("price_list_id").unwrap(), is_account, PriceListEntry {
+            product_id: query.product_id.clone(),
+            min_quantity: row.get("min_quantity").unwrap(),
+            price: row.get("price").unwrap()
+        }));
+    }
+
+    let (price, price_list_id) = match resolve_price(&entries, quantity) {
+        Some((price_list_id, _, entry)) => (entry.price, Some(price_list_id.clone())),
+        None => (base_price, None)
+    };
+
+    HttpResponse::Ok().json(Response {
+        product_id: query.product_id.clone(),
+        quantity,
+        base_price,
+        price,
+        discount_perc: discount_perc(base_price, price),
+        price_list_id
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn discounts_are_rounded_to_the_stored_scale() {
+        assert_eq!(discount_perc(money("100"), money("90")), money("10"));
+        assert_eq!(discount_perc(money("3"), money("2")), money("33.333333"));
+        assert_eq!(discount_perc(money("3"), money("1")), money("66.666667"));
+    }
+
+    #[test]
+    fn discounts_are_never_negative() {
+        assert_eq!(discount_perc(money("10"), money("12")), Money::ZERO);
+        assert_eq!(discount_perc(money("10"), money("10")), Money::ZERO);
+        assert_eq!(discount_perc(Money::ZERO, money("5")), Money::ZERO);
+    }
+}
@@ -2,9 +2,9 @@ use actix_web::{HttpResponse, web, post};
 use actix_web_grants::proc_macro::has_permissions;
 use serde::{Serialize, Deserialize};
 use mysql::prelude::Queryable;
-use mysql::{Row, Params, params};
+use mysql::Row;
 use crate::AppData;
-use crate::endpoints::products::Product;
+use crate::endpoints::products::{Product, insert_product};
 use rand::Rng;
 use std::future::Future;
 use std::pin::Pin;
@@ -21,6 +21,7 @@ pub struct Response {
 
 #[post("/products/add")]
 #[has_permissions("PRODUCTS_WRITE")]
+#[allow(clippy::async_yields_async)]
 pub async fn add_product(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
     let conn = data.pool.get_conn();
     if conn.is_err() {
@@ -30,16 +31,21 @@ pub async fn add_product(data: web::Data<AppData>, request: web::Json<Request>)
 
     let mut conn = conn.unwrap();
 
-    let sql_get_products = conn.query::<Row, &str>("SELECT name FROM products");
+    let sql_get_products = conn.query::<Row, &str>("SELECT name,sku FROM products");
     if sql_get_products.is_err() {
         eprintln!("Unable to fetch products from the database: {:?}", sql_get_products.err().unwrap());
         return HttpResponse::InternalServerError().finish();
     }
 
     let mut product_names: Vec<String> = Vec::new();
+    let mut product_skus: Vec<String> = Vec::new();
     for row in sql_get_products.unwrap() {
         let name = row.get("name").unwrap();
         product_names.push(name);
+
+        if let Some(sku) = row.get::<Option<String>, &str>("sku").unwrap() {
+            product_skus.push(sku);
+        }
     }
 
     let mut processors: Vec<Box<dyn Future<Output=(bool, Option<mysql::Error>)>>> = Vec::new();
@@ -48,19 +54,19 @@ pub async fn add_product(data: web::Data<AppData>, request: web::Json<Request>)
             return HttpResponse::BadRequest().json(Response { error: Some(format!("Product with name '{}' already exists!", &product.name))});
         }
 
+        if let Some(sku) = &product.sku {
+            if product_skus.contains(sku) {
+                return HttpResponse::BadRequest().json(Response { error: Some(format!("Product with SKU '{}' already exists!", sku))});
+            }
+        }
+
         let product_clone = product.clone();
         let pool = data.pool.clone();
 
         let processor = async move {
             let mut conn = pool.get_conn().unwrap();
             let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
-            let sql_insert_product=  conn.exec::<usize, &str, Params>("INSERT INTO products (id, name, description, price) VALUES (:id, :name, :description, :price)", params! {
-                "id" => id,
-                "name" => product_clone.name.clone(),
-                "description" => product_clone.description.clone(),
-                "price" => product_clone.price
-            });
-
+            let sql_insert_product = insert_product(&mut conn, &id, &product_clone);
             (sql_insert_product.is_ok(), sql_insert_product.err())
         };
 
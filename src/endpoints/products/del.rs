@@ -17,6 +17,7 @@ pub struct Response {
 
 #[post("/products/del")]
 #[has_permissions("PRODUCTS_WRITE")]
+#[allow(clippy::async_yields_async)]
 pub async fn del_product(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
     let conn = data.pool.get_conn();
     if conn.is_err() {
@@ -42,12 +43,13 @@ pub async fn del_product(data: web::Data<AppData>, request: web::Json<Request>)
             return HttpResponse::BadRequest().json(Response { error: Some(format!("Product with id '{}' does not exist!", product_id))});
         }
 
-        let sql_delete_product = conn.exec::<usize, &str, Params>("DELETE FROM products WHERE id = :id", params! {
+        //Products are archived rather than deleted, as itemrows of existing invoices and quotes still refer to them
+        let sql_archive_product = conn.exec::<usize, &str, Params>("UPDATE products SET archived = 1 WHERE id = :id", params! {
             "id" => product_id
         });
 
-        if sql_delete_product.is_err() {
-            eprintln!("Unable to delete product from database: {:?}", sql_delete_product.err().unwrap());
+        if sql_archive_product.is_err() {
+            eprintln!("Unable to archive product in database: {:?}", sql_archive_product.err().unwrap());
             return HttpResponse::InternalServerError().finish();
         }
     }
@@ -0,0 +1,126 @@
+use actix_web::{HttpResponse, web, get};
+use actix_web::web::Bytes;
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Deserialize;
+use mysql::prelude::Queryable;
+use mysql::{PooledConn, Params, params};
+use futures::channel::{mpsc, oneshot};
+use futures::executor::block_on;
+use futures::SinkExt;
+use crate::appdata::AppData;
+use crate::endpoints::products::{Product, Format};
+
+/// Number of serialized products buffered ahead of a slow client
+const BUFFERED_PRODUCTS: usize = 64;
+
+#[derive(Deserialize)]
+pub struct Query {
+    format:             Format,
+    include_archived:   Option<bool>
+}
+
+type Chunk = Result<Bytes, std::io::Error>;
+
+#[get("/products/export")]
+#[has_permissions("PRODUCTS_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn export_products(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create a database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    //The catalogue is streamed to the client while it is read, rather than being collected in memory first.
+    //The query runs on a thread of its own, which reports whether it could be started before the first product is sent
+    let conn = conn.unwrap();
+    let (started_tx, started_rx) = oneshot::channel();
+    let (chunks_tx, chunks_rx) = mpsc::channel::<Chunk>(BUFFERED_PRODUCTS);
+    let format = query.format;
+    let include_archived = query.include_archived.unwrap_or(false);
+    std::thread::spawn(move || write_products(conn, format, include_archived, started_tx, chunks_tx));
+
+    match started_rx.await {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => {
+            eprintln!("Unable to fetch products from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+
+    match format {
+        Format::Json => HttpResponse::Ok()
+            .content_type("application/json")
+            .header("Content-Disposition", "attachment; filename=\"products.json\"")
+            .streaming(chunks_rx),
+        Format::Csv => HttpResponse::Ok()
+            .content_type("text/csv")
+            .header("Content-Disposition", "attachment; filename=\"products.csv\"")
+            .streaming(chunks_rx)
+    }
+}
+
+/**
+Read the products and send them in the requested format, one product per chunk.
+Stops early when the client disconnects. An error halfway aborts the response, as its status has already been sent
+*/
+fn write_products(mut conn: PooledConn, format: Format, include_archived: bool, started: oneshot::Sender<mysql::Result<()>>, mut chunks: mpsc::Sender<Chunk>) {
+    let sql_products = conn.exec_iter::<&str, Params>("SELECT id,name,description,price,sku,unit,vat_perc,category,archived FROM products \
+        WHERE archived = 0 OR :include_archived ORDER BY name ASC", params! {
+        "include_archived" => include_archived
+    });
+
+    let sql_products = match sql_products {
+        Ok(result) => {
+            let _ = started.send(Ok(()));
+            result
+        },
+        Err(err) => {
+            let _ = started.send(Err(err));
+            return;
+        }
+    };
+
+    let mut first = true;
+    for row in sql_products {
+        let chunk = row.map_err(|err| err.to_string()).and_then(|row| {
+            let product = Product::from_row(&row);
+            match format {
+                Format::Json => serde_json::to_vec(&product)
+                    .map(|json| [if first { &b"["[..] } else { &b","[..] }, &json].concat())
+                    .map_err(|err| err.to_string()),
+                Format::Csv => {
+                    let mut csv = csv::WriterBuilder::new().has_headers(first).from_writer(Vec::new());
+                    csv.serialize(&product).map_err(|err| err.to_string())?;
+                    csv.into_inner().map_err(|err| err.to_string())
+                }
+            }
+        });
+
+        let chunk = match chunk {
+            Ok(chunk) => Ok(Bytes::from(chunk)),
+            Err(err) => {
+                eprintln!("Unable to export products: {:?}", err);
+                Err(std::io::Error::other(err))
+            }
+        };
+
+        let failed = chunk.is_err();
+        if block_on(chunks.send(chunk)).is_err() || failed {
+            return;
+        }
+
+        first = false;
+    }
+
+    let end = match (format, first) {
+        (Format::Json, true) => &b"[]"[..],
+        (Format::Json, false) => &b"]"[..],
+        (Format::Csv, _) => &b""[..]
+    };
+
+    if !end.is_empty() {
+        let _ = block_on(chunks.send(Ok(Bytes::from_static(end))));
+    }
+}
@@ -1,19 +1,67 @@
 use actix_web::{HttpResponse, web, get};
 use actix_web_grants::proc_macro::has_permissions;
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
 use mysql::prelude::Queryable;
-use mysql::Row;
+use mysql::{Row, Params, params};
 use crate::appdata::AppData;
 use crate::endpoints::products::Product;
+use crate::money::Money;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    Name,
+    Price,
+    Sku,
+    Category
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    Desc
+}
+
+#[derive(Deserialize)]
+pub struct Query {
+    include_archived:   Option<bool>,
+    category:           Option<String>,
+    /// Free-text search on name and description
+    q:                  Option<String>,
+    min_price:          Option<Money>,
+    max_price:          Option<Money>,
+    sort:               Option<Sort>,
+    order:              Option<Order>,
+    limit:              Option<u64>,
+    offset:             Option<u64>
+}
 
 #[derive(Serialize)]
 pub struct Response {
-    products: Vec<Product>
+    products:   Vec<Product>,
+    total:      i64
+}
+
+const FILTER: &str = "WHERE (archived = 0 OR :include_archived) \
+    AND (:category IS NULL OR category = :category) \
+    AND (:search IS NULL OR name LIKE :search OR description LIKE :search) \
+    AND (:min_price IS NULL OR price >= :min_price) \
+    AND (:max_price IS NULL OR price <= :max_price)";
+
+/**
+The LIKE pattern for a free-text search, `None` for an empty search. Wildcards in the search are matched literally
+*/
+fn search_pattern(q: Option<&str>) -> Option<String> {
+    q.map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(|q| format!("%{}%", q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")))
 }
 
 #[get("/products/get")]
 #[has_permissions("PRODUCTS_READ")]
-pub async fn get_products(data: web::Data<AppData>) -> HttpResponse {
+#[allow(clippy::async_yields_async)]
+pub async fn get_products(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
     let conn = data.pool.get_conn();
     if conn.is_err() {
         eprintln!("Unable to create a database connection: {:?}", conn.err().unwrap());
@@ -22,7 +70,46 @@ pub async fn get_products(data: web::Data<AppData>) -> HttpResponse {
 
     let mut conn = conn.unwrap();
 
-    let sql_products = conn.query::<Row, &str>("SELECT id,name,description,price FROM products");
+    let search = search_pattern(query.q.as_deref());
+
+    let filter_params = params! {
+        "include_archived" => query.include_archived.unwrap_or(false),
+        "category" => &query.category,
+        "search" => &search,
+        "min_price" => query.min_price,
+        "max_price" => query.max_price
+    };
+
+    let sql_total = conn.exec::<Row, String, Params>(format!("SELECT COUNT(*) AS total FROM products {}", FILTER), filter_params.clone());
+    if sql_total.is_err() {
+        eprintln!("Unable to count products in the database: {:?}", sql_total.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let total = match sql_total.unwrap().into_iter().next() {
+        Some(row) => row.get("total").unwrap(),
+        None => 0
+    };
+
+    let sort = match query.sort.unwrap_or(Sort::Name) {
+        Sort::Name => "name",
+        Sort::Price => "price",
+        Sort::Sku => "sku",
+        Sort::Category => "category"
+    };
+
+    let order = match query.order.unwrap_or(Order::Asc) {
+        Order::Asc => "ASC",
+        Order::Desc => "DESC"
+    };
+
+    //The id is used as a tiebreaker so pages are stable
+    let mut sql = format!("SELECT id,name,description,price,sku,unit,vat_perc,category,archived FROM products {} ORDER BY {} {}, id ASC", FILTER, sort, order);
+    if query.limit.is_some() || query.offset.is_some() {
+        sql.push_str(&format!(" LIMIT {} OFFSET {}", query.limit.unwrap_or(u64::MAX), query.offset.unwrap_or(0)));
+    }
+
+    let sql_products = conn.exec::<Row, String, Params>(sql, filter_params);
     if sql_products.is_err() {
         eprintln!("Unable to fetch products from the database: {:?}", sql_products.err().unwrap());
         return HttpResponse::InternalServerError().finish();
@@ -30,18 +117,27 @@ pub async fn get_products(data: web::Data<AppData>) -> HttpResponse {
 
     let mut products = Vec::new();
     for row in sql_products.unwrap() {
-        let id = row.get("id").unwrap();
-        let name = row.get("name").unwrap();
-        let description = row.get("description").unwrap();
-        let price = row.get("price").unwrap();
-
-        products.push(Product {
-            id: Some(id),
-            name,
-            description,
-            price
-        });
+        products.push(Product::from_row(&row));
     }
 
-    HttpResponse::Ok().json(Response { products })
+    HttpResponse::Ok().json(Response { products, total })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_matches_anywhere_in_the_text() {
+        assert_eq!(search_pattern(Some(" widget ")), Some("%widget%".to_string()));
+        assert_eq!(search_pattern(Some("  ")), None);
+        assert_eq!(search_pattern(None), None);
+    }
+
+    #[test]
+    fn search_wildcards_are_escaped() {
+        assert_eq!(search_pattern(Some("100%")), Some("%100\\%%".to_string()));
+        assert_eq!(search_pattern(Some("a_b")), Some("%a\\_b%".to_string()));
+        assert_eq!(search_pattern(Some("c:\\dir")), Some("%c:\\\\dir%".to_string()));
+    }
 }
\ No newline at end of file
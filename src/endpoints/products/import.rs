@@ -0,0 +1,254 @@
+use actix_web::{post, HttpResponse, web};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::Row;
+use crate::AppData;
+use crate::endpoints::products::{Product, Format, insert_product, overwrite_product, normalize_name};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Only create new products, rows matching an existing product by name or SKU are skipped
+    Insert,
+    /// Update the product with the same name, or create it if it doesn't exist
+    UpsertName,
+    /// Update the product with the same SKU, or create it if it doesn't exist
+    UpsertSku
+}
+
+#[derive(Deserialize)]
+pub struct Query {
+    format:     Format,
+    mode:       Option<Mode>,
+    dry_run:    Option<bool>
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RowStatus {
+    Created,
+    Updated,
+    Skipped,
+    Error
+}
+
+#[derive(Serialize)]
+pub struct RowReport {
+    row:        usize,
+    name:       Option<String>,
+    id:         Option<String>,
+    status:     RowStatus,
+    error:      Option<String>,
+    /// Set when an archived product was updated, it stays archived
+    warning:    Option<String>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    dry_run:    bool,
+    rows:       Vec<RowReport>,
+    error:      Option<String>
+}
+
+/**
+The products in the database, names are matched the way imported documents are matched to products, see [`normalize_name`]
+*/
+struct Existing {
+    by_name:    HashMap<String, String>,
+    by_sku:     HashMap<String, String>,
+    archived:   HashSet<String>
+}
+
+impl Existing {
+    fn forget(&mut self, id: &str) {
+        self.by_name.retain(|_, v| v != id);
+        self.by_sku.retain(|_, v| v != id);
+    }
+
+    fn remember(&mut self, id: &str, product: &Product) {
+        self.by_name.insert(normalize_name(&product.name), id.to_string());
+        if let Some(sku) = &product.sku {
+            self.by_sku.insert(sku.clone(), id.to_string());
+        }
+    }
+
+    /**
+    Returns the id of a product other than `own_id` which already uses the name or SKU of `product`
+    */
+    fn conflict(&self, own_id: Option<&str>, product: &Product) -> Option<String> {
+        if let Some(id) = self.by_name.get(&normalize_name(&product.name)) {
+            if Some(id.as_str()) != own_id {
+                return Some(format!("Product with name '{}' already exists!", &product.name));
+            }
+        }
+
+        if let Some(sku) = &product.sku {
+            if let Some(id) = self.by_sku.get(sku) {
+                if Some(id.as_str()) != own_id {
+                    return Some(format!("Product with SKU '{}' already exists!", sku));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[post("/products/import")]
+#[has_permissions("PRODUCTS_WRITE")]
+#[allow(clippy::async_yields_async)]
+pub async fn import_products(data: web::Data<AppData>, query: web::Query<Query>, body: web::Bytes) -> HttpResponse {
+    let mode = query.mode.unwrap_or(Mode::Insert);
+    let dry_run = query.dry_run.unwrap_or(false);
+
+    let parsed: Vec<Result<Product, String>> = match query.format {
+        Format::Json => match serde_json::from_slice::<Vec<Product>>(&body) {
+            Ok(products) => products.into_iter().map(Ok).collect(),
+            Err(err) => return HttpResponse::BadRequest().json(Response { dry_run, rows: Vec::new(), error: Some(format!("Invalid JSON: {}", err)) })
+        },
+        Format::Csv => csv::Reader::from_reader(body.as_ref())
+            .deserialize::<Product>()
+            .map(|record| record.map_err(|err| err.to_string()))
+            .collect()
+    };
+
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+
+    let sql_get_products = conn.query::<Row, &str>("SELECT id,name,sku,archived FROM products");
+    if sql_get_products.is_err() {
+        eprintln!("Unable to fetch products from the database: {:?}", sql_get_products.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut existing = Existing { by_name: HashMap::new(), by_sku: HashMap::new(), archived: HashSet::new() };
+    for row in sql_get_products.unwrap() {
+        let id: String = row.get("id").unwrap();
+        if row.get::<bool, &str>("archived").unwrap() {
+            existing.archived.insert(id.clone());
+        }
+
+        existing.by_name.insert(normalize_name(&row.get::<String, &str>("name").unwrap()), id.clone());
+        if let Some(sku) = row.get::<Option<String>, &str>("sku").unwrap() {
+            existing.by_sku.insert(sku, id);
+        }
+    }
+
+    let mut reports = Vec::with_capacity(parsed.len());
+    for (index, product) in parsed.into_iter().enumerate() {
+        //Row numbers are 1-based, for CSV the header is row 1
+        let row = if query.format == Format::Csv { index + 2 } else { index + 1 };
+
+        let product = match product {
+            Ok(product) => product,
+            Err(err) => {
+                reports.push(RowReport { row, name: None, id: None, status: RowStatus::Error, error: Some(err), warning: None });
+                continue;
+            }
+        };
+
+        if product.name.trim().is_empty() {
+            reports.push(RowReport { row, name: None, id: None, status: RowStatus::Error, error: Some("Product name may not be empty".to_string()), warning: None });
+            continue;
+        }
+
+        let matched = match mode {
+            Mode::Insert => None,
+            Mode::UpsertName => existing.by_name.get(&normalize_name(&product.name)).cloned(),
+            Mode::UpsertSku => match &product.sku {
+                Some(sku) => existing.by_sku.get(sku).cloned(),
+                None => {
+                    reports.push(RowReport { row, name: Some(product.name.clone()), id: None, status: RowStatus::Error, error: Some("A SKU is required when upserting by SKU".to_string()), warning: None });
+                    continue;
+                }
+            }
+        };
+
+        if let Some(conflict) = existing.conflict(matched.as_deref(), &product) {
+            let status = if mode == Mode::Insert { RowStatus::Skipped } else { RowStatus::Error };
+            reports.push(RowReport { row, name: Some(product.name.clone()), id: None, status, error: Some(conflict), warning: None });
+            continue;
+        }
+
+        let (id, status) = match matched {
+            Some(id) => {
+                if !dry_run {
+                    if let Err(err) = overwrite_product(&mut conn, &id, &product) {
+                        eprintln!("Unable to update product in the database: {:?}", err);
+                        reports.push(RowReport { row, name: Some(product.name.clone()), id: Some(id), status: RowStatus::Error, error: Some("Unable to update product in the database".to_string()), warning: None });
+                        continue;
+                    }
+                }
+
+                (id, RowStatus::Updated)
+            },
+            None => {
+                let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+                if !dry_run {
+                    if let Err(err) = insert_product(&mut conn, &id, &product) {
+                        eprintln!("Unable to add product to the database: {:?}", err);
+                        reports.push(RowReport { row, name: Some(product.name.clone()), id: None, status: RowStatus::Error, error: Some("Unable to add product to the database".to_string()), warning: None });
+                        continue;
+                    }
+                }
+
+                (id, RowStatus::Created)
+            }
+        };
+
+        let warning = if existing.archived.contains(&id) {
+            Some("The product is archived, it is updated but stays archived".to_string())
+        } else {
+            None
+        };
+
+        existing.forget(&id);
+        existing.remember(&id, &product);
+
+        reports.push(RowReport { row, name: Some(product.name), id: if dry_run { None } else { Some(id) }, status, error: None, warning });
+    }
+
+    HttpResponse::Ok().json(Response { dry_run, rows: reports, error: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    fn product(name: &str, sku: Option<&str>) -> Product {
+        Product {
+            id: None,
+            name: name.to_string(),
+            description: String::new(),
+            price: Money::ONE,
+            sku: sku.map(str::to_string),
+            unit: None,
+            vat_perc: None,
+            category: None,
+            archived: false
+        }
+    }
+
+    #[test]
+    fn names_conflict_the_way_imported_documents_match_them() {
+        let mut existing = Existing { by_name: HashMap::new(), by_sku: HashMap::new(), archived: HashSet::new() };
+        existing.remember("A", &product("Widget", Some("W-1")));
+
+        assert!(existing.conflict(None, &product(" widget ", None)).is_some());
+        assert!(existing.conflict(Some("A"), &product("WIDGET", None)).is_none());
+        assert!(existing.conflict(None, &product("Gadget", Some("W-1"))).is_some());
+        assert!(existing.conflict(None, &product("Gadget", Some("G-1"))).is_none());
+
+        existing.forget("A");
+        assert!(existing.conflict(None, &product("Widget", Some("W-1"))).is_none());
+    }
+}
@@ -1,13 +1,211 @@
 pub mod get;
 pub mod add;
 pub mod del;
+pub mod update;
+pub mod revisions;
+pub mod restore;
+pub mod import;
+pub mod export;
 
 use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, params};
+use rand::Rng;
+use crate::money::Money;
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Product {
     pub id:             Option<String>,
     pub name:           String,
     pub description:    String,
-    pub price:          f64
+    pub price:          Money,
+    #[serde(default)]
+    pub sku:            Option<String>,
+    #[serde(default)]
+    pub unit:           Option<String>,
+    #[serde(default)]
+    pub vat_perc:       Option<Money>,
+    #[serde(default)]
+    pub category:       Option<String>,
+    #[serde(default)]
+    pub archived:       bool
+}
+
+impl Product {
+    /**
+    Build a Product from a row of the products table
+    */
+    pub fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name").unwrap(),
+            description: row.get("description").unwrap(),
+            price: row.get("price").unwrap(),
+            sku: row.get("sku").unwrap(),
+            unit: row.get("unit").unwrap(),
+            vat_perc: row.get("vat_perc").unwrap(),
+            category: row.get("category").unwrap(),
+            archived: row.get("archived").unwrap()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProductRevision {
+    pub id:             String,
+    pub product_id:     String,
+    pub name:           String,
+    pub description:    String,
+    pub price:          Money,
+    pub sku:            Option<String>,
+    pub unit:           Option<String>,
+    pub vat_perc:       Option<Money>,
+    pub category:       Option<String>,
+    pub revision_date:  i64
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    Json
+}
+
+/**
+Insert a new Product with the given id, and record its first revision
+*/
+pub fn insert_product<Q: Queryable>(conn: &mut Q, id: &str, product: &Product) -> mysql::Result<()> {
+    conn.exec_drop("INSERT INTO products (id, name, description, price, sku, unit, vat_perc, category) \
+        VALUES (:id, :name, :description, :price, :sku, :unit, :vat_perc, :category)", params! {
+        "id" => id,
+        "name" => &product.name,
+        "description" => &product.description,
+        "price" => product.price,
+        "sku" => &product.sku,
+        "unit" => &product.unit,
+        "vat_perc" => product.vat_perc,
+        "category" => &product.category
+    })?;
+
+    insert_revision(conn, id, product)
+}
+
+/**
+Overwrite the editable fields of an existing Product, and record the change as a revision
+*/
+pub fn overwrite_product<Q: Queryable>(conn: &mut Q, id: &str, product: &Product) -> mysql::Result<()> {
+    conn.exec_drop("UPDATE products SET \
+        name = :name, description = :description, price = :price, sku = :sku, unit = :unit, vat_perc = :vat_perc, category = :category \
+        WHERE id = :id", params! {
+        "id" => id,
+        "name" => &product.name,
+        "description" => &product.description,
+        "price" => product.price,
+        "sku" => &product.sku,
+        "unit" => &product.unit,
+        "vat_perc" => product.vat_perc,
+        "category" => &product.category
+    })?;
+
+    insert_revision(conn, id, product)
+}
+
+/**
+Record the current state of a Product in the product_revisions table
+*/
+pub fn insert_revision<Q: Queryable>(conn: &mut Q, product_id: &str, product: &Product) -> mysql::Result<()> {
+    let id: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+    conn.exec_drop("INSERT INTO product_revisions (id, product_id, name, description, price, sku, unit, vat_perc, category, revision_date) \
+        VALUES (:id, :product_id, :name, :description, :price, :sku, :unit, :vat_perc, :category, :revision_date)", params! {
+        "id" => id,
+        "product_id" => product_id,
+        "name" => &product.name,
+        "description" => &product.description,
+        "price" => product.price,
+        "sku" => &product.sku,
+        "unit" => &product.unit,
+        "vat_perc" => product.vat_perc,
+        "category" => &product.category,
+        "revision_date" => chrono::Utc::now().timestamp()
+    })
+}
+
+/**
+The form of a product name that is compared when looking for a product by name, so `Widget ` and `widget` are the same product
+*/
+pub fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchedBy {
+    Sku,
+    Name
+}
+
+/**
+The active products by SKU and by name, for linking imported lines to the catalogue
+*/
+pub struct ProductLookup {
+    by_sku:     HashMap<String, String>,
+    /// Keyed on the normalized name, see [`normalize_name`]
+    by_name:    HashMap<String, String>
+}
+
+impl ProductLookup {
+    /**
+    Load all products that are not archived
+    */
+    pub fn load<Q: Queryable>(conn: &mut Q) -> mysql::Result<Self> {
+        let mut lookup = Self { by_sku: HashMap::new(), by_name: HashMap::new() };
+        for row in conn.query::<Row, &str>("SELECT id, name, sku FROM products WHERE archived = 0")? {
+            let id: String = row.get("id").unwrap();
+            lookup.by_name.insert(normalize_name(&row.get::<String, &str>("name").unwrap()), id.clone());
+            if let Some(sku) = row.get::<Option<String>, &str>("sku").unwrap() {
+                lookup.by_sku.insert(sku, id);
+            }
+        }
+
+        Ok(lookup)
+    }
+
+    /**
+    Find a product by its SKU, or by its name when there is no product with the SKU
+    */
+    pub fn find(&self, sku: Option<&str>, name: &str) -> Option<(String, MatchedBy)> {
+        if let Some(id) = sku.and_then(|sku| self.by_sku.get(sku.trim())) {
+            return Some((id.clone(), MatchedBy::Sku));
+        }
+
+        self.by_name.get(&normalize_name(name)).map(|id| (id.clone(), MatchedBy::Name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn products_are_active_unless_archived() {
+        let product: Product = serde_json::from_str(r#"{"name": "Widget", "description": "", "price": "1.50"}"#).unwrap();
+        assert!(!product.archived);
+
+        let product: Product = serde_json::from_str(r#"{"name": "Widget", "description": "", "price": "1.50", "archived": true}"#).unwrap();
+        assert!(product.archived);
+        assert_eq!(serde_json::to_value(&product).unwrap()["archived"], serde_json::json!(true));
+    }
+
+    #[test]
+    fn catalogue_fields_are_optional() {
+        let product: Product = serde_json::from_str(r#"{"name": "Widget", "description": "", "price": "1.50"}"#).unwrap();
+        assert_eq!((product.sku, product.unit, product.vat_perc, product.category), (None, None, None, None));
+
+        let product: Product = serde_json::from_str(r#"{"name": "Widget", "description": "", "price": "1.50", "sku": "W-1", "unit": "pcs", "vat_perc": "21", "category": "Parts"}"#).unwrap();
+        assert_eq!(product.sku.as_deref(), Some("W-1"));
+        assert_eq!(product.unit.as_deref(), Some("pcs"));
+        assert_eq!(product.vat_perc, Some(Money::from(21)));
+        assert_eq!(product.category.as_deref(), Some("Parts"));
+    }
 }
\ No newline at end of file
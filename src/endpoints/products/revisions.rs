@@ -0,0 +1,65 @@
+use actix_web::{HttpResponse, web, get};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, params};
+use crate::appdata::AppData;
+use crate::endpoints::products::ProductRevision;
+
+#[derive(Deserialize)]
+pub struct Query {
+    id:     String,
+    /// When set, only the revision that was in effect at this UNIX timestamp is returned
+    date:   Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    revisions: Vec<ProductRevision>
+}
+
+#[get("/products/revisions")]
+#[has_permissions("PRODUCTS_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_product_revisions(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create a database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+
+    let sql_revisions = match query.date {
+        Some(date) => conn.exec::<Row, &str, Params>("SELECT * FROM product_revisions WHERE product_id = :product_id AND revision_date <= :date ORDER BY revision_date DESC LIMIT 1", params! {
+            "product_id" => &query.id,
+            "date" => date
+        }),
+        None => conn.exec::<Row, &str, Params>("SELECT * FROM product_revisions WHERE product_id = :product_id ORDER BY revision_date ASC", params! {
+            "product_id" => &query.id
+        })
+    };
+
+    if sql_revisions.is_err() {
+        eprintln!("Unable to fetch product revisions from the database: {:?}", sql_revisions.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut revisions = Vec::new();
+    for row in sql_revisions.unwrap() {
+        revisions.push(ProductRevision {
+            id: row.get("id").unwrap(),
+            product_id: row.get("product_id").unwrap(),
+            name: row.get("name").unwrap(),
+            description: row.get("description").unwrap(),
+            price: row.get("price").unwrap(),
+            sku: row.get("sku").unwrap(),
+            unit: row.get("unit").unwrap(),
+            vat_perc: row.get("vat_perc").unwrap(),
+            category: row.get("category").unwrap(),
+            revision_date: row.get("revision_date").unwrap()
+        });
+    }
+
+    HttpResponse::Ok().json(Response { revisions })
+}
@@ -0,0 +1,149 @@
+use actix_web::{post, HttpResponse, web};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize, Deserializer};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, TxOpts, params};
+use crate::AppData;
+use crate::endpoints::products::{Product, overwrite_product};
+use crate::money::Money;
+
+/**
+Fields that are left out keep their current value. The optional fields are cleared by setting them to null
+*/
+#[derive(Deserialize)]
+pub struct Request {
+    id:             String,
+    name:           Option<String>,
+    description:    Option<String>,
+    price:          Option<Money>,
+    #[serde(default, deserialize_with = "nullable")]
+    sku:            Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    unit:           Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    vat_perc:       Option<Option<Money>>,
+    #[serde(default, deserialize_with = "nullable")]
+    category:       Option<Option<String>>
+}
+
+/**
+Tell a field that is null apart from one that is left out, which serde would both turn into `None`
+*/
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error> where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> {
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    error: Option<String>
+}
+
+#[post("/products/update")]
+#[has_permissions("PRODUCTS_WRITE")]
+#[allow(clippy::async_yields_async)]
+pub async fn update_product(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let conn = data.pool.get_conn();
+    if conn.is_err() {
+        eprintln!("Unable to create database connection: {:?}", conn.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let mut conn = conn.unwrap();
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Unable to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    //The product stays locked until its revision is recorded, so concurrent updates can not overwrite each other's changes
+    let sql_get_product = tx.exec::<Row, &str, Params>("SELECT id,name,description,price,sku,unit,vat_perc,category,archived FROM products WHERE id = :id FOR UPDATE", params! {
+        "id" => &request.id
+    });
+
+    if sql_get_product.is_err() {
+        eprintln!("Unable to fetch product from the database: {:?}", sql_get_product.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let row = match sql_get_product.unwrap().into_iter().next() {
+        Some(row) => row,
+        None => return HttpResponse::BadRequest().json(Response { error: Some(format!("Product with id '{}' does not exist!", &request.id))})
+    };
+
+    let current = Product::from_row(&row);
+    let product = Product {
+        id: Some(request.id.clone()),
+        name: request.name.clone().unwrap_or_else(|| current.name.clone()),
+        description: request.description.clone().unwrap_or_else(|| current.description.clone()),
+        price: request.price.unwrap_or(current.price),
+        sku: request.sku.clone().unwrap_or_else(|| current.sku.clone()),
+        unit: request.unit.clone().unwrap_or_else(|| current.unit.clone()),
+        vat_perc: request.vat_perc.unwrap_or(current.vat_perc),
+        category: request.category.clone().unwrap_or_else(|| current.category.clone()),
+        archived: current.archived
+    };
+
+    if product.sku.is_some() && product.sku != current.sku {
+        let sql_get_sku = tx.exec::<Row, &str, Params>("SELECT id FROM products WHERE sku = :sku", params! {
+            "sku" => &product.sku
+        });
+
+        match sql_get_sku {
+            Ok(rows) => if !rows.is_empty() {
+                return HttpResponse::BadRequest().json(Response { error: Some(format!("Product with SKU '{}' already exists!", product.sku.as_ref().unwrap()))});
+            },
+            Err(err) => {
+                eprintln!("Unable to fetch products from the database: {:?}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    if product.name != current.name {
+        let sql_get_name = tx.exec::<Row, &str, Params>("SELECT id FROM products WHERE name = :name", params! {
+            "name" => &product.name
+        });
+
+        match sql_get_name {
+            Ok(rows) => if !rows.is_empty() {
+                return HttpResponse::BadRequest().json(Response { error: Some(format!("Product with name '{}' already exists!", &product.name))});
+            },
+            Err(err) => {
+                eprintln!("Unable to fetch products from the database: {:?}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    if let Err(err) = overwrite_product(&mut tx, &request.id, &product) {
+        eprintln!("Unable to update product in the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Unable to commit product update to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn left_out_fields_are_kept_and_null_clears_them() {
+        let request: Request = serde_json::from_str(r#"{"id": "A", "sku": null, "unit": "pcs", "vat_perc": "9"}"#).unwrap();
+
+        assert_eq!(request.sku, Some(None));
+        assert_eq!(request.unit, Some(Some("pcs".to_string())));
+        assert_eq!(request.vat_perc, Some(Some(Money::from(9))));
+        assert_eq!(request.category, None);
+        assert!(request.name.is_none() && request.price.is_none());
+    }
+}
@@ -0,0 +1,267 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Deserialize;
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, TxOpts, params};
+use std::collections::HashMap;
+use crate::AppData;
+use crate::apis::pdf::{PdfCommonPayload, ItemRow};
+use crate::endpoints::invoice::{InvoiceStatus, insert_invoice};
+use crate::endpoints::pdf::invoice::generate_and_finalize;
+use crate::endpoints::quote::{QuoteStatus, get_quote, get_invoiced_percentages, insert_conversion};
+use crate::sequences::{next_number, Sequence};
+use crate::money::{self, Money};
+use crate::endpoints::exchange_rates::snapshot_rate;
+use crate::endpoints::vat::apply_reverse_charge;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    template_name:  Option<String>,
+    language:       Option<String>,
+    reference:      Option<String>,
+    notes:          Option<String>,
+    expiry_date:    i64,
+    creation_date:  i64,
+    /// Product IDs of the quote rows to invoice, all rows are invoiced when omitted
+    rows:           Option<Vec<String>>,
+    /// Invoice only this percentage of every row's price, e.g. for a down-payment invoice
+    percentage:     Option<Money>
+}
+
+/**
+Check that invoicing `percentage` of the rows at `positions` does not invoice any of them for more than 100% in total
+*/
+fn check_remaining(invoiced: &HashMap<usize, Money>, positions: &[usize], percentage: Money) -> Result<(), String> {
+    for position in positions {
+        let done = invoiced.get(position).copied().unwrap_or_default();
+        if done >= Money::ONE_HUNDRED {
+            return Err(format!("Row {} has already been invoiced in full.", position + 1));
+        }
+
+        if done + percentage > Money::ONE_HUNDRED {
+            return Err(format!("Row {} has already been invoiced for {}%, only {}% is left to invoice.", position + 1, done.normalize(), (Money::ONE_HUNDRED - done).normalize()));
+        }
+    }
+
+    Ok(())
+}
+
+/**
+The rows of the invoice: the quote rows at `positions`, at `percentage` of their price when given
+*/
+fn invoice_rows(rows: &[ItemRow], positions: &[usize], percentage: Option<Money>) -> Vec<ItemRow> {
+    positions.iter()
+        .map(|position| {
+            let mut row = rows[*position].clone();
+            if let Some(percentage) = percentage {
+                row.price = money::percentage(row.price, percentage);
+            }
+
+            row
+        })
+        .collect()
+}
+
+#[post("/quotes/{id}/convert")]
+#[has_permissions("INVOICE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn convert_quote(data: web::Data<AppData>, web::Path(quote_id): web::Path<i64>, request: web::Json<Request>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err.to_string());
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    //The quote stays locked until the invoice is committed, so concurrent conversions can not invoice the same rows twice
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_get_quote = tx.exec_first::<Row, &str, Params>("SELECT revision, status FROM quotes WHERE id = :id ORDER BY revision DESC LIMIT 1 FOR UPDATE", params! {
+        "id" => quote_id
+    });
+
+    let (revision, status) = match sql_get_quote {
+        Ok(Some(row)) => (row.get::<i32, &str>("revision").unwrap(), row.get::<String, &str>("status").unwrap().parse::<QuoteStatus>().unwrap()),
+        Ok(None) => return HttpResponse::NotFound().body(format!("Quote with ID {} does not exist.", quote_id)),
+        Err(err) => {
+            eprintln!("Failed to query quote from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if !matches!(status, QuoteStatus::Open | QuoteStatus::Accepted) {
+        return HttpResponse::BadRequest().body(format!("Quote {} is {} and can not be converted.", quote_id, status.as_str()));
+    }
+
+    let quote = match get_quote(&mut tx, quote_id) {
+        Ok(Some(quote)) => quote,
+        Ok(None) => return HttpResponse::NotFound().body(format!("Quote with ID {} does not exist.", quote_id)),
+        Err(err) => {
+            eprintln!("Failed to query quote from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let percentage = request.percentage.unwrap_or(Money::ONE_HUNDRED);
+    if percentage <= Money::ZERO || percentage > Money::ONE_HUNDRED {
+        return HttpResponse::BadRequest().body("Percentage must be greater than 0 and at most 100.");
+    }
+
+    if let Some(selected) = &request.rows {
+        if let Some(unknown) = selected.iter().find(|id| !quote.common.rows.iter().any(|row| &row.id == *id)) {
+            return HttpResponse::BadRequest().body(format!("Quote {} has no row for product '{}'.", quote_id, unknown));
+        }
+    }
+
+    let positions = quote.common.rows.iter()
+        .enumerate()
+        .filter(|(_, row)| match &request.rows {
+            Some(selected) => selected.contains(&row.id),
+            None => true
+        })
+        .map(|(position, _)| position)
+        .collect::<Vec<_>>();
+
+    if positions.is_empty() {
+        return HttpResponse::BadRequest().body("No rows selected to invoice.");
+    }
+
+    let invoiced = match get_invoiced_percentages(&mut tx, quote_id) {
+        Ok(invoiced) => invoiced,
+        Err(err) => {
+            eprintln!("Failed to query quote conversions from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(err) = check_remaining(&invoiced, &positions, percentage) {
+        return HttpResponse::BadRequest().body(format!("Quote {} can not be converted: {}", quote_id, err));
+    }
+
+    let rows = invoice_rows(&quote.common.rows, &positions, request.percentage);
+
+    //The invoice is committed before its PDF is generated, so the number sequence is not locked while the PDF service is called.
+    //Its number is never handed out again, the invoice is pending until its PDF has been generated
+    let id = match next_number(&mut tx, &data.config, Sequence::Invoice) {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Failed to allocate invoice number: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut payload = PdfCommonPayload {
+        id,
+        template_name: request.template_name.clone().unwrap_or(quote.common.template_name),
+        language: request.language.clone().unwrap_or(quote.common.language),
+        reference: request.reference.clone().unwrap_or(quote.common.reference),
+        notes: request.notes.clone().or(quote.common.notes),
+        expiry_date: request.expiry_date,
+        creation_date: request.creation_date,
+        rows,
+        ..quote.common
+    };
+
+    //The invoice keeps the currency of the quote, at the rate of its own creation date
+    match snapshot_rate(&mut tx, &data.config, &mut payload) {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to query exchange rate from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    if let Err(err) = apply_reverse_charge(&data.config, &mut payload) {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    if let Err(err) = insert_invoice(&mut tx, &payload, Some(quote_id), InvoiceStatus::PendingGeneration) {
+        eprintln!("Failed to create new invoice in database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = insert_conversion(&mut tx, quote_id, id, &positions, percentage) {
+        eprintln!("Failed to link invoice to the quote in database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    //Invoicing a quote accepts it on behalf of the customer
+    if status == QuoteStatus::Open {
+        let sql_accept_quote = tx.exec_drop("UPDATE quotes SET status = 'accepted', decided_at = :decided_at WHERE id = :id AND revision = :revision", params! {
+            "id" => quote_id,
+            "revision" => revision,
+            "decided_at" => chrono::Utc::now().timestamp()
+        });
+
+        if let Err(err) = sql_accept_quote {
+            eprintln!("Failed to accept quote in database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit invoice to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    generate_and_finalize(&data, &mut conn, &payload).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn rows_are_invoiced_for_at_most_their_full_price() {
+        let mut invoiced = HashMap::new();
+        assert!(check_remaining(&invoiced, &[0, 1], Money::ONE_HUNDRED).is_ok());
+
+        invoiced.insert(0, money("30"));
+        assert!(check_remaining(&invoiced, &[0, 1], money("70")).is_ok());
+        assert!(check_remaining(&invoiced, &[1], Money::ONE_HUNDRED).is_ok());
+        assert!(check_remaining(&invoiced, &[0, 1], money("70.01")).is_err());
+        assert!(check_remaining(&invoiced, &[0, 1], Money::ONE_HUNDRED).is_err());
+
+        invoiced.insert(1, Money::ONE_HUNDRED);
+        assert_eq!(check_remaining(&invoiced, &[1], money("0.01")), Err("Row 2 has already been invoiced in full.".to_string()));
+    }
+
+    fn row(id: &str, price: &str) -> ItemRow {
+        ItemRow {
+            comment: None,
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            discount_perc: None,
+            vat_perc: money("21"),
+            price: money(price),
+            quantity: 2
+        }
+    }
+
+    #[test]
+    fn selected_rows_are_invoiced_at_the_percentage() {
+        let rows = vec![row("A", "100"), row("B", "33.33"), row("C", "10")];
+
+        let invoiced = invoice_rows(&rows, &[0, 2], None);
+        assert_eq!(invoiced.iter().map(|row| row.id.as_str()).collect::<Vec<_>>(), ["A", "C"]);
+        assert_eq!(invoiced[0].price, money("100"));
+
+        let invoiced = invoice_rows(&rows, &[1], Some(money("50")));
+        assert_eq!(invoiced[0].price, money("16.665"));
+        assert_eq!(invoiced[0].quantity, 2);
+    }
+}
@@ -0,0 +1,88 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use crate::AppData;
+use crate::apis::pdf::PdfQuotePayload;
+use crate::calculation::{calculate, Totals};
+use crate::endpoints::quote::{get_quote, get_quote_revision, get_quote_invoices};
+
+#[derive(Deserialize)]
+pub struct Query {
+    /// Defaults to the latest revision
+    revision: Option<i32>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    #[serde(flatten)]
+    quote:          PdfQuotePayload,
+    totals:         Totals,
+    /// The invoices the quote has been converted to
+    invoice_ids:    Vec<i64>
+}
+
+#[get("/quote/{id:\\d+}")]
+#[has_permissions("QUOTE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_quote_by_id(data: web::Data<AppData>, web::Path(quote_id): web::Path<i64>, query: web::Query<Query>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let quote = match query.revision {
+        Some(revision) => get_quote_revision(&mut conn, quote_id, revision),
+        None => get_quote(&mut conn, quote_id)
+    };
+
+    let quote = match quote {
+        Ok(Some(quote)) => quote,
+        Ok(None) => return match query.revision {
+            Some(revision) => HttpResponse::NotFound().body(format!("Quote {} has no revision {}.", quote_id, revision)),
+            None => HttpResponse::NotFound().body(format!("Quote with ID {} does not exist.", quote_id))
+        },
+        Err(err) => {
+            eprintln!("Failed to query quote from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match get_quote_invoices(&mut conn, quote_id) {
+        Ok(invoice_ids) => HttpResponse::Ok().json(Response { totals: calculate(&quote.common.rows, data.config.rounding), quote, invoice_ids }),
+        Err(err) => {
+            eprintln!("Failed to query quote conversions from the database: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::money::Rounding;
+
+    #[test]
+    fn quotes_are_returned_with_their_revision_totals_and_invoices() {
+        let mut quote: PdfQuotePayload = serde_json::from_value(json!({
+            "templateName": "default", "language": "en", "id": 20240001, "attentionOf": null, "receiver": "Acme GmbH", "reference": "Stickers", "notes": null,
+            "expiryDate": 0, "creationDate": 0, "rows": [
+                { "comment": null, "id": "P-1", "name": "Stickers", "description": "", "discountPerc": null, "vatPerc": "21", "price": "10", "quantity": 2 }
+            ], "address": { "city": "Berlin", "country": "Germany", "postalCode": "10115", "street": "Main 1" },
+            "quoteTopic": "Stickers", "quoteContactPerson": "John Doe", "debitId": "D-1"
+        })).unwrap();
+
+        quote.revision = 2;
+        let totals = calculate(&quote.common.rows, Rounding::Total);
+        let json = serde_json::to_value(Response { totals, quote, invoice_ids: vec![20240001] }).unwrap();
+
+        assert_eq!(json["revision"], json!(2));
+        assert_eq!(json["quoteTopic"], json!("Stickers"));
+        assert_eq!(json["receiver"], json!("Acme GmbH"));
+        assert_eq!(json["invoice_ids"], json!([20240001]));
+        assert_eq!(json["totals"]["total"], json!("24.20"));
+    }
+}
@@ -0,0 +1,134 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use mysql::prelude::Queryable;
+use mysql::{params, TxOpts};
+use serde::{Serialize, Deserialize};
+use crate::AppData;
+use crate::apis::pdf::PdfQuotePayload;
+use crate::calculation::Totals;
+use crate::endpoints::exchange_rates::snapshot_rate;
+use crate::endpoints::quote::{QuoteStatus, insert_quote};
+use crate::sequences::{next_number, Sequence};
+use crate::ubl::import::{prepare, Import, LineReport, UnmappedField};
+
+#[derive(Deserialize)]
+pub struct Query {
+    template_name:  String,
+    language:       String,
+    /// UBL has no subject for a quotation
+    quote_topic:    Option<String>,
+    dry_run:        Option<bool>
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    dry_run:        bool,
+    /// Not set for a dry run
+    quote_id:       Option<i64>,
+    source_id:      Option<String>,
+    quote:          PdfQuotePayload,
+    totals:         Totals,
+    lines:          Vec<LineReport>,
+    unmapped:       Vec<UnmappedField>,
+    warnings:       Vec<String>
+}
+
+/**
+Import a UBL Quotation, or an Invoice, as a draft quote. No PDF is generated, revising the draft sends it to the customer
+*/
+#[post("/quote/import")]
+#[has_permissions("QUOTE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn import_quote(data: web::Data<AppData>, query: web::Query<Query>, body: web::Bytes) -> HttpResponse {
+    let dry_run = query.dry_run.unwrap_or(false);
+
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let Import { mut document, lines, totals } = match prepare(&mut conn, &data.config, &body, &query.template_name, &query.language) {
+        Ok(Ok(import)) => import,
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to fetch products from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if query.quote_topic.is_none() {
+        document.warnings.push("No quote topic was given, set one when revising the quote.".to_string());
+    }
+
+    let mut quote = PdfQuotePayload {
+        common: document.payload,
+        quote_topic: query.quote_topic.clone().unwrap_or_default(),
+        quote_contact_person: document.seller_contact.unwrap_or_default(),
+        debit_id: document.buyer_id.unwrap_or_default(),
+        revision: 0
+    };
+
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match snapshot_rate(&mut tx, &data.config, &mut quote.common) {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to query exchange rate from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut quote_id = None;
+    if !dry_run {
+        quote.common.id = match next_number(&mut tx, &data.config, Sequence::Quote) {
+            Ok(id) => id,
+            Err(err) => {
+                eprintln!("Failed to allocate quote number: {:?}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+
+        if let Err(err) = insert_quote(&mut tx, &quote, QuoteStatus::Draft) {
+            eprintln!("Failed to create imported quote in database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        let sql_set_source = tx.exec_drop("UPDATE quotes SET source_id = :source_id WHERE id = :id AND revision = 0", params! {
+            "id" => quote.common.id,
+            "source_id" => &document.source_id
+        });
+
+        if let Err(err) = sql_set_source {
+            eprintln!("Failed to store the source ID of the imported quote: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        if let Err(err) = tx.commit() {
+            eprintln!("Failed to commit imported quote to the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        quote_id = Some(quote.common.id);
+    }
+
+    HttpResponse::Ok().json(Response {
+        dry_run,
+        quote_id,
+        source_id: document.source_id,
+        quote,
+        totals,
+        lines,
+        unmapped: document.unmapped,
+        warnings: document.warnings
+    })
+}
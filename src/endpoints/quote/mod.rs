@@ -0,0 +1,336 @@
+pub mod convert;
+pub mod token;
+pub mod respond;
+pub mod revise;
+pub mod revisions;
+pub mod get;
+pub mod import;
+
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, params};
+use hmac::{Hmac, NewMac, Mac};
+use sha2::{Sha256, Digest};
+use std::str::FromStr;
+use std::collections::HashMap;
+use crate::appdata::Config;
+use crate::money::Money;
+use crate::apis::pdf::{PdfQuotePayload, PdfCommonPayload};
+use crate::endpoints::pdf::{insert_itemrows, get_itemrows};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStatus {
+    /// Imported and not sent yet, revising the quote sends it
+    Draft,
+    Open,
+    Accepted,
+    Declined,
+    Expired,
+    /// A newer revision of the quote exists, this revision is read-only
+    Superseded
+}
+
+impl QuoteStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::Open => "open",
+            Self::Accepted => "accepted",
+            Self::Declined => "declined",
+            Self::Expired => "expired",
+            Self::Superseded => "superseded"
+        }
+    }
+}
+
+impl FromStr for QuoteStatus {
+    type Err = String;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "draft" => Ok(Self::Draft),
+            "open" => Ok(Self::Open),
+            "accepted" => Ok(Self::Accepted),
+            "declined" => Ok(Self::Declined),
+            "expired" => Ok(Self::Expired),
+            "superseded" => Ok(Self::Superseded),
+            _ => Err(format!("Unknown quote status '{}'", status))
+        }
+    }
+}
+
+/**
+Build a quote from a row of the quotes table, fetching its rows
+*/
+fn quote_from_row<Q: Queryable>(conn: &mut Q, row: &Row) -> mysql::Result<PdfQuotePayload> {
+    let id: i64 = row.get("id").unwrap();
+    let revision: i32 = row.get("revision").unwrap();
+
+    let itemrows = get_itemrows(conn, id, revision, "quotes")?;
+    Ok(PdfQuotePayload {
+        quote_topic: row.get("quote_topic").unwrap(),
+        quote_contact_person: row.get("quote_contact_person").unwrap(),
+        debit_id: row.get("debit_id").unwrap(),
+        revision,
+        common: PdfCommonPayload::from_row(row, itemrows)
+    })
+}
+
+/**
+Fetch the latest revision of a quote and its rows, `None` if the quote does not exist
+*/
+pub fn get_quote<Q: Queryable>(conn: &mut Q, id: i64) -> mysql::Result<Option<PdfQuotePayload>> {
+    match conn.exec_first::<Row, &str, Params>("SELECT * FROM quotes WHERE id = :id ORDER BY revision DESC LIMIT 1", params! { "id" => id })? {
+        Some(row) => Ok(Some(quote_from_row(conn, &row)?)),
+        None => Ok(None)
+    }
+}
+
+/**
+Fetch a specific revision of a quote and its rows, `None` if it does not exist
+*/
+pub fn get_quote_revision<Q: Queryable>(conn: &mut Q, id: i64, revision: i32) -> mysql::Result<Option<PdfQuotePayload>> {
+    match conn.exec_first::<Row, &str, Params>("SELECT * FROM quotes WHERE id = :id AND revision = :revision", params! { "id" => id, "revision" => revision })? {
+        Some(row) => Ok(Some(quote_from_row(conn, &row)?)),
+        None => Ok(None)
+    }
+}
+
+/**
+Fetch all revisions of a quote with their status, oldest first
+*/
+pub fn get_quote_revisions<Q: Queryable>(conn: &mut Q, id: i64) -> mysql::Result<Vec<(PdfQuotePayload, QuoteStatus)>> {
+    let rows = conn.exec::<Row, &str, Params>("SELECT * FROM quotes WHERE id = :id ORDER BY revision ASC", params! { "id" => id })?;
+
+    let mut revisions = Vec::with_capacity(rows.len());
+    for row in rows {
+        let status = row.get::<String, &str>("status").unwrap().parse::<QuoteStatus>().unwrap();
+        revisions.push((quote_from_row(conn, &row)?, status));
+    }
+
+    Ok(revisions)
+}
+
+/**
+Fetch the latest revision number and its status of a quote, `None` if the quote does not exist
+*/
+pub fn get_quote_status<Q: Queryable>(conn: &mut Q, id: i64) -> mysql::Result<Option<(i32, QuoteStatus)>> {
+    let row = conn.exec_first::<Row, &str, Params>("SELECT revision, status FROM quotes WHERE id = :id ORDER BY revision DESC LIMIT 1", params! { "id" => id })?;
+    Ok(row.map(|row| (row.get("revision").unwrap(), row.get::<String, &str>("status").unwrap().parse::<QuoteStatus>().unwrap())))
+}
+
+/**
+Format the number of a quote revision: the base number, with a revision suffix from the first revision on
+*/
+pub fn revision_number(id: i64, revision: i32) -> String {
+    match revision {
+        0 => id.to_string(),
+        _ => format!("{}-R{}", id, revision)
+    }
+}
+
+/**
+Insert a revision of a quote and its rows
+*/
+pub fn insert_quote<Q: Queryable>(conn: &mut Q, payload: &PdfQuotePayload, status: QuoteStatus) -> mysql::Result<()> {
+    conn.exec_drop("INSERT INTO quotes \
+        (id, revision, status, template_name, language, attention_of, receiver, reference, notes, expiry_date, creation_date, city, country, postal_code, street, currency, exchange_rate, electronic_address, vat_number, reverse_charge, quote_topic, quote_contact_person, debit_id) \
+        VALUES (:id, :revision, :status, :template_name, :language, :attention_of, :receiver, :reference, :notes, :expiry_date, :creation_date, :city, :country, :postal_code, :street, :currency, :exchange_rate, :electronic_address, :vat_number, :reverse_charge, :quote_topic, :quote_contact_person, :debit_id)", params! {
+
+        "id" => &payload.common.id,
+        "revision" => payload.revision,
+        "status" => status.as_str(),
+        "template_name" => &payload.common.template_name,
+        "language" => &payload.common.language,
+        "attention_of" => &payload.common.attention_of,
+        "receiver" => &payload.common.receiver,
+        "reference" => &payload.common.reference,
+        "notes" => &payload.common.notes,
+        "expiry_date" => &payload.common.expiry_date,
+        "creation_date" => &payload.common.creation_date,
+        "city" => &payload.common.address.city,
+        "country" => &payload.common.address.country,
+        "postal_code" => &payload.common.address.postal_code,
+        "street" => &payload.common.address.street,
+        "currency" => &payload.common.currency,
+        "exchange_rate" => payload.common.exchange_rate,
+        "electronic_address" => &payload.common.electronic_address,
+        "vat_number" => &payload.common.address.vat_number,
+        "reverse_charge" => payload.common.reverse_charge,
+        "quote_topic" => &payload.quote_topic,
+        "quote_contact_person" => &payload.quote_contact_person,
+        "debit_id" => &payload.debit_id
+    })?;
+
+    insert_itemrows(conn, payload.common.id, payload.revision, "quotes", &payload.common.rows)
+}
+
+/**
+Set the status of a single revision of a quote
+*/
+pub fn set_revision_status<Q: Queryable>(conn: &mut Q, id: i64, revision: i32, status: QuoteStatus) -> mysql::Result<()> {
+    conn.exec_drop("UPDATE quotes SET status = :status WHERE id = :id AND revision = :revision", params! {
+        "id" => id,
+        "revision" => revision,
+        "status" => status.as_str()
+    })
+}
+
+/**
+Remove a revision of a quote and its rows, for a revision whose PDF could not be generated
+*/
+pub fn delete_revision<Q: Queryable>(conn: &mut Q, id: i64, revision: i32) -> mysql::Result<()> {
+    conn.exec_drop("DELETE FROM itemrows WHERE parent_type = 'quotes' AND parent_id = :id AND parent_revision = :revision", params! {
+        "id" => id,
+        "revision" => revision
+    })?;
+
+    conn.exec_drop("DELETE FROM quotes WHERE id = :id AND revision = :revision", params! {
+        "id" => id,
+        "revision" => revision
+    })
+}
+
+/**
+Mark all open quotes whose expiry date has passed as expired. Returns the number of quotes that expired
+*/
+pub fn expire_quotes<Q: Queryable>(conn: &mut Q) -> mysql::Result<u64> {
+    let result = conn.exec_iter("UPDATE quotes SET status = 'expired' WHERE status = 'open' AND expiry_date < :now", params! {
+        "now" => chrono::Utc::now().timestamp()
+    })?;
+
+    Ok(result.affected_rows())
+}
+
+/**
+Record which rows of a quote an invoice was converted from, and for what percentage of their price
+*/
+pub fn insert_conversion<Q: Queryable>(conn: &mut Q, quote_id: i64, invoice_id: i64, positions: &[usize], percentage: Money) -> mysql::Result<()> {
+    conn.exec_batch("INSERT INTO quote_conversions (quote_id, invoice_id, position, percentage) VALUES (:quote_id, :invoice_id, :position, :percentage)",
+        positions.iter().map(|position| params! {
+            "quote_id" => quote_id,
+            "invoice_id" => invoice_id,
+            "position" => *position as u32,
+            "percentage" => percentage
+        }))
+}
+
+/**
+Fetch the percentage of every row of a quote that has been invoiced, by row position. Cancelled invoices do not count
+*/
+pub fn get_invoiced_percentages<Q: Queryable>(conn: &mut Q, quote_id: i64) -> mysql::Result<HashMap<usize, Money>> {
+    let rows = conn.exec::<(u32, Money), &str, Params>("SELECT quote_conversions.position, SUM(quote_conversions.percentage) FROM quote_conversions \
+        INNER JOIN invoices ON invoices.id = quote_conversions.invoice_id \
+        WHERE quote_conversions.quote_id = :quote_id AND invoices.status <> 'cancelled' GROUP BY quote_conversions.position", params! {
+        "quote_id" => quote_id
+    })?;
+
+    Ok(rows.into_iter().map(|(position, percentage)| (position as usize, percentage)).collect())
+}
+
+/**
+Fetch the IDs of the invoices a quote has been converted to, oldest first
+*/
+pub fn get_quote_invoices<Q: Queryable>(conn: &mut Q, quote_id: i64) -> mysql::Result<Vec<i64>> {
+    conn.exec("SELECT DISTINCT invoice_id FROM quote_conversions WHERE quote_id = :quote_id ORDER BY invoice_id", params! {
+        "quote_id" => quote_id
+    })
+}
+
+fn sign(config: &Config, payload: &str) -> crate::Result<HmacSha256> {
+    if config.quote_token_secret.is_empty() {
+        return Err("No quote token secret is configured".to_string());
+    }
+
+    let mut mac = match HmacSha256::new_from_slice(config.quote_token_secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(err) => return Err(err.to_string())
+    };
+
+    mac.update(payload.as_bytes());
+    Ok(mac)
+}
+
+/**
+Create a signed token for a quote, in the form `{quote_id}.{nonce}.{signature}`
+*/
+pub fn create_token(config: &Config, quote_id: i64, nonce: &str) -> crate::Result<String> {
+    let payload = format!("{}.{}", quote_id, nonce);
+    let signature = sign(config, &payload)?.finalize().into_bytes();
+    Ok(format!("{}.{}", payload, base64::encode_config(signature, base64::URL_SAFE_NO_PAD)))
+}
+
+/**
+Check the signature of a token, returning the quote ID and nonce it was issued with
+*/
+pub fn verify_token(config: &Config, token: &str) -> Option<(i64, String)> {
+    let mut parts = token.splitn(3, '.');
+    let quote_id = parts.next()?;
+    let nonce = parts.next()?;
+    let signature = base64::decode_config(parts.next()?, base64::URL_SAFE_NO_PAD).ok()?;
+
+    let mac = sign(config, &format!("{}.{}", quote_id, nonce)).ok()?;
+    mac.verify(&signature).ok()?;
+
+    Some((quote_id.parse().ok()?, nonce.to_string()))
+}
+
+/**
+Tokens are stored hashed, so a leaked database does not give out working accept links
+*/
+pub fn hash_nonce(nonce: &str) -> String {
+    format!("{:x}", Sha256::digest(nonce.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(secret: &str) -> Config {
+        Config { quote_token_secret: secret.to_string(), ..Config::default() }
+    }
+
+    #[test]
+    fn tokens_verify_with_the_secret_they_were_signed_with() {
+        let token = create_token(&config("secret"), 20240001, "nonce").unwrap();
+        assert!(token.starts_with("20240001.nonce."));
+        assert_eq!(verify_token(&config("secret"), &token), Some((20240001, "nonce".to_string())));
+        assert_eq!(verify_token(&config("other"), &token), None);
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let token = create_token(&config("secret"), 20240001, "nonce").unwrap();
+        let signature = token.rsplit('.').next().unwrap();
+
+        assert_eq!(verify_token(&config("secret"), &format!("20240002.nonce.{}", signature)), None);
+        assert_eq!(verify_token(&config("secret"), &format!("20240001.other.{}", signature)), None);
+        assert_eq!(verify_token(&config("secret"), "20240001.nonce"), None);
+        assert_eq!(verify_token(&config("secret"), "20240001.nonce.!!"), None);
+    }
+
+    #[test]
+    fn tokens_need_a_secret() {
+        assert!(create_token(&config(""), 20240001, "nonce").is_err());
+        assert_eq!(verify_token(&config(""), "20240001.nonce.c2lnbmF0dXJl"), None);
+    }
+
+    #[test]
+    fn nonces_are_stored_hashed() {
+        assert_eq!(hash_nonce("nonce").len(), 64);
+        assert_eq!(hash_nonce("nonce"), hash_nonce("nonce"));
+        assert_ne!(hash_nonce("nonce"), hash_nonce("other"));
+    }
+
+    #[test]
+    fn quote_statuses_round_trip_through_their_names() {
+        for status in [QuoteStatus::Draft, QuoteStatus::Open, QuoteStatus::Accepted, QuoteStatus::Declined, QuoteStatus::Expired, QuoteStatus::Superseded] {
+            assert_eq!(status.as_str().parse::<QuoteStatus>(), Ok(status));
+        }
+
+        assert!("paid".parse::<QuoteStatus>().is_err());
+    }
+}
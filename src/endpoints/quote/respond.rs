@@ -0,0 +1,170 @@
+use actix_web::{get, post, web, HttpResponse};
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Params, params, PooledConn};
+use crate::AppData;
+use crate::apis::pdf::PdfQuotePayload;
+use crate::endpoints::quote::{QuoteStatus, get_quote, get_quote_status, expire_quotes, verify_token, hash_nonce};
+
+#[derive(Deserialize)]
+pub struct Query {
+    token: String
+}
+
+#[derive(Serialize)]
+pub struct GetResponse {
+    quote:  PdfQuotePayload,
+    status: QuoteStatus
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Accept,
+    Decline
+}
+
+#[derive(Deserialize)]
+pub struct Request {
+    token:      String,
+    decision:   Decision,
+    /// The name the customer typed to confirm the decision
+    name:       String
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    status: Option<QuoteStatus>,
+    error:  Option<String>
+}
+
+/**
+Check a token and return the quote and revision it was issued for, if the token is valid, unused and that revision is still open
+*/
+fn check_token(data: &AppData, conn: &mut PooledConn, token: &str) -> Result<(i64, i32, String), HttpResponse> {
+    let (quote_id, nonce) = match verify_token(&data.config, token) {
+        Some(token) => token,
+        None => return Err(HttpResponse::Forbidden().json(Response { status: None, error: Some("Invalid token".to_string()) }))
+    };
+
+    let sql_get_token = conn.exec_first::<(i64, i32), &str, Params>("SELECT quote_id, revision FROM quote_tokens WHERE token_hash = :token_hash AND used_at IS NULL", params! {
+        "token_hash" => hash_nonce(&nonce)
+    });
+
+    let revision = match sql_get_token {
+        Ok(Some((id, revision))) if id == quote_id => revision,
+        Ok(_) => return Err(HttpResponse::Forbidden().json(Response { status: None, error: Some("This link has already been used".to_string()) })),
+        Err(err) => {
+            eprintln!("Failed to query quote token from the database: {:?}", err);
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+    };
+
+    if let Err(err) = expire_quotes(conn) {
+        eprintln!("Failed to expire quotes: {:?}", err);
+        return Err(HttpResponse::InternalServerError().finish());
+    }
+
+    match get_quote_status(conn, quote_id) {
+        Ok(Some((latest, _))) if latest != revision => Err(HttpResponse::BadRequest().json(Response { status: Some(QuoteStatus::Superseded), error: Some("A newer version of this quote has been sent to you".to_string()) })),
+        Ok(Some((_, QuoteStatus::Open))) => Ok((quote_id, revision, nonce)),
+        Ok(Some((_, status))) => Err(HttpResponse::BadRequest().json(Response { status: Some(status), error: Some(format!("This quote is {} and can no longer be responded to", status.as_str())) })),
+        Ok(None) => Err(HttpResponse::NotFound().finish()),
+        Err(err) => {
+            eprintln!("Failed to query quote from the database: {:?}", err);
+            Err(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+/**
+Public endpoint used by the customer-facing page to show the quote behind an accept/decline link
+*/
+#[get("/quote/respond")]
+pub async fn get_quote_response(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let (quote_id, _, _) = match check_token(&data, &mut conn, &query.token) {
+        Ok(token) => token,
+        Err(response) => return response
+    };
+
+    match get_quote(&mut conn, quote_id) {
+        Ok(Some(quote)) => HttpResponse::Ok().json(GetResponse { quote, status: QuoteStatus::Open }),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            eprintln!("Failed to query quote from the database: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/**
+Public endpoint recording the customer's decision on a quote. Every token can be used once
+*/
+#[post("/quote/respond")]
+pub async fn respond_to_quote(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if request.name.trim().is_empty() {
+        return HttpResponse::BadRequest().json(Response { status: None, error: Some("Please enter your name".to_string()) });
+    }
+
+    let (quote_id, revision, nonce) = match check_token(&data, &mut conn, &request.token) {
+        Ok(token) => token,
+        Err(response) => return response
+    };
+
+    let now = chrono::Utc::now().timestamp();
+
+    //Claiming the token is a single conditional update, so two concurrent requests can't both use it
+    let sql_use_token = conn.exec_drop("UPDATE quote_tokens SET used_at = :now WHERE token_hash = :token_hash AND used_at IS NULL", params! {
+        "now" => now,
+        "token_hash" => hash_nonce(&nonce)
+    });
+
+    if let Err(err) = sql_use_token {
+        eprintln!("Failed to mark quote token as used: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if conn.affected_rows() != 1 {
+        return HttpResponse::Forbidden().json(Response { status: None, error: Some("This link has already been used".to_string()) });
+    }
+
+    let status = match request.decision {
+        Decision::Accept => QuoteStatus::Accepted,
+        Decision::Decline => QuoteStatus::Declined
+    };
+
+    let sql_update_quote = conn.exec_drop("UPDATE quotes SET status = :status, decided_at = :decided_at, decided_by = :decided_by WHERE id = :id AND revision = :revision AND status = 'open'", params! {
+        "id" => quote_id,
+        "revision" => revision,
+        "status" => status.as_str(),
+        "decided_at" => now,
+        "decided_by" => request.name.trim()
+    });
+
+    if let Err(err) = sql_update_quote {
+        eprintln!("Failed to record quote decision in the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if conn.affected_rows() != 1 {
+        return HttpResponse::BadRequest().json(Response { status: None, error: Some("This quote can no longer be responded to".to_string()) });
+    }
+
+    HttpResponse::Ok().json(Response { status: Some(status), error: None })
+}
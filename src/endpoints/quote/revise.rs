@@ -0,0 +1,128 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Serialize;
+use mysql::prelude::Queryable;
+use mysql::params;
+use mysql::TxOpts;
+use crate::AppData;
+use crate::apis::pdf::{generate_quote, PdfGenerationResponse, PdfQuotePayload};
+use crate::endpoints::quote::{QuoteStatus, get_quote_status, insert_quote, set_revision_status, delete_revision, revision_number};
+use crate::calculation::{calculate, Totals};
+use crate::endpoints::exchange_rates::snapshot_rate;
+use crate::endpoints::vat::apply_reverse_charge;
+
+#[derive(Serialize)]
+pub struct Response {
+    #[serde(flatten)]
+    pdf:            PdfGenerationResponse,
+    quote_id:       i64,
+    revision:       i32,
+    number:         String,
+    totals:         Totals
+}
+
+/**
+Create a new revision of a quote. The quote keeps its number, earlier revisions become read-only
+*/
+#[post("/quotes/{id}/revise")]
+#[has_permissions("QUOTE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn revise_quote(data: web::Data<AppData>, web::Path(quote_id): web::Path<i64>, payload: web::Json<PdfQuotePayload>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err.to_string());
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let latest = match get_quote_status(&mut conn, quote_id) {
+        Ok(Some((_, QuoteStatus::Accepted))) => return HttpResponse::BadRequest().body(format!("Quote {} has been accepted and can no longer be revised.", quote_id)),
+        Ok(Some((revision, _))) => revision,
+        Ok(None) => return HttpResponse::NotFound().body(format!("Quote with ID {} does not exist.", quote_id)),
+        Err(err) => {
+            eprintln!("Failed to query quote from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut payload = payload.into_inner();
+    payload.common.id = quote_id;
+    payload.revision = latest + 1;
+
+    //The revision is committed as a draft before its PDF is generated, so no lock is held while the PDF service is called.
+    //It only supersedes the earlier revisions once its PDF exists, and is deleted again when generation fails
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match snapshot_rate(&mut tx, &data.config, &mut payload.common) {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(err),
+        Err(err) => {
+            eprintln!("Failed to query exchange rate from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    if let Err(err) = apply_reverse_charge(&data.config, &mut payload.common) {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    if let Err(err) = insert_quote(&mut tx, &payload, QuoteStatus::Draft) {
+        eprintln!("Failed to create new quote revision in database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit quote revision to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    //Generate the PDF
+    let id = match generate_quote(&data.config, &payload).await {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Failed to send Quote generation request: {:?}", err);
+            if let Err(err) = delete_revision(&mut conn, quote_id, payload.revision) {
+                eprintln!("Failed to remove quote revision {} after its PDF could not be generated: {:?}", revision_number(quote_id, payload.revision), err);
+            }
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut tx = match conn.start_transaction(TxOpts::default()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!("Failed to start database transaction: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    //Only the latest revision can be accepted
+    let sql_supersede = tx.exec_drop("UPDATE quotes SET status = 'superseded' WHERE id = :id AND revision < :revision AND status IN ('open', 'draft')", params! {
+        "id" => quote_id,
+        "revision" => payload.revision
+    });
+
+    if let Err(err) = sql_supersede {
+        eprintln!("Failed to mark earlier quote revisions as superseded: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = set_revision_status(&mut tx, quote_id, payload.revision, QuoteStatus::Open) {
+        eprintln!("Failed to set quote status in database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = tx.commit() {
+        eprintln!("Failed to commit quote revision to the database: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(Response { pdf: PdfGenerationResponse { id: Some(id), error: None }, quote_id, revision: payload.revision, number: revision_number(quote_id, payload.revision), totals: calculate(&payload.common.rows, data.config.rounding) })
+}
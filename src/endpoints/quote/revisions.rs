@@ -0,0 +1,246 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::AppData;
+use crate::apis::pdf::{PdfQuotePayload, ItemRow};
+use crate::endpoints::quote::{QuoteStatus, get_quote_revisions, revision_number};
+
+#[derive(Serialize)]
+pub struct Revision {
+    #[serde(flatten)]
+    quote:  PdfQuotePayload,
+    number: String,
+    status: QuoteStatus
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    revisions: Vec<Revision>
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    from:   i32,
+    to:     i32
+}
+
+#[derive(Serialize)]
+pub struct FieldChange {
+    field:  String,
+    from:   Value,
+    to:     Value
+}
+
+#[derive(Serialize)]
+pub struct RowChange {
+    product_id: String,
+    from:       ItemRow,
+    to:         ItemRow
+}
+
+#[derive(Serialize)]
+pub struct DiffResponse {
+    from:           i32,
+    to:             i32,
+    fields:         Vec<FieldChange>,
+    rows_added:     Vec<ItemRow>,
+    rows_removed:   Vec<ItemRow>,
+    rows_changed:   Vec<RowChange>
+}
+
+#[get("/quotes/{id}/revisions")]
+#[has_permissions("QUOTE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_revisions(data: web::Data<AppData>, web::Path(quote_id): web::Path<i64>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let revisions = match get_quote_revisions(&mut conn, quote_id) {
+        Ok(revisions) => revisions,
+        Err(err) => {
+            eprintln!("Failed to query quote revisions from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if revisions.is_empty() {
+        return HttpResponse::NotFound().body(format!("Quote with ID {} does not exist.", quote_id));
+    }
+
+    HttpResponse::Ok().json(Response { revisions: revisions.into_iter().map(|(quote, status)| Revision { number: revision_number(quote.common.id, quote.revision), quote, status }).collect() })
+}
+
+#[get("/quotes/{id}/diff")]
+#[has_permissions("QUOTE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_revision_diff(data: web::Data<AppData>, web::Path(quote_id): web::Path<i64>, query: web::Query<DiffQuery>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let revisions = match get_quote_revisions(&mut conn, quote_id) {
+        Ok(revisions) => revisions,
+        Err(err) => {
+            eprintln!("Failed to query quote revisions from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let find = |revision: i32| revisions.iter().find(|(quote, _)| quote.revision == revision).map(|(quote, _)| quote.clone());
+    let (from, to) = match (find(query.from), find(query.to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return HttpResponse::NotFound().body(format!("Quote {} has no revision {} or {}.", quote_id, query.from, query.to))
+    };
+
+    let (rows_added, rows_removed, rows_changed) = diff_rows(&from.common.rows, &to.common.rows);
+    HttpResponse::Ok().json(DiffResponse {
+        from: query.from,
+        to: query.to,
+        fields: diff_fields(&from, &to),
+        rows_added,
+        rows_removed,
+        rows_changed
+    })
+}
+
+/**
+Compare the header fields of two revisions
+*/
+fn diff_fields(from: &PdfQuotePayload, to: &PdfQuotePayload) -> Vec<FieldChange> {
+    let to_map = |quote: &PdfQuotePayload| match serde_json::to_value(quote) {
+        Ok(Value::Object(mut map)) => {
+            map.remove("rows");
+            map.remove("revision");
+            map
+        },
+        _ => serde_json::Map::new()
+    };
+
+    let from = to_map(from);
+    let mut to = to_map(to);
+
+    let mut changes = Vec::new();
+    for (field, from_value) in from {
+        let to_value = to.remove(&field).unwrap_or(Value::Null);
+        if from_value != to_value {
+            changes.push(FieldChange { field, from: from_value, to: to_value });
+        }
+    }
+
+    changes
+}
+
+/**
+Compare the rows of two revisions. Rows are matched on their product ID, in order of appearance
+*/
+fn diff_rows(from: &[ItemRow], to: &[ItemRow]) -> (Vec<ItemRow>, Vec<ItemRow>, Vec<RowChange>) {
+    let mut remaining: HashMap<&str, Vec<&ItemRow>> = HashMap::new();
+    for row in from.iter().rev() {
+        remaining.entry(row.id.as_str()).or_default().push(row);
+    }
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for row in to {
+        match remaining.get_mut(row.id.as_str()).and_then(|rows| rows.pop()) {
+            Some(previous) => {
+                if serde_json::to_value(previous).ok() != serde_json::to_value(row).ok() {
+                    changed.push(RowChange { product_id: row.id.clone(), from: previous.clone(), to: row.clone() });
+                }
+            },
+            None => added.push(row.clone())
+        }
+    }
+
+    let mut removed = Vec::new();
+    for row in from {
+        if let Some(rows) = remaining.get_mut(row.id.as_str()) {
+            if let Some(position) = rows.iter().position(|r| std::ptr::eq(*r, row)) {
+                removed.push((*rows.remove(position)).clone());
+            }
+        }
+    }
+
+    (added, removed, changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(id: &str, price: &str, quantity: i64) -> ItemRow {
+        serde_json::from_value(json!({
+            "comment": null, "id": id, "name": id, "description": "", "discountPerc": null, "vatPerc": 21, "price": price, "quantity": quantity
+        })).unwrap()
+    }
+
+    fn quote(reference: &str, rows: Vec<ItemRow>) -> PdfQuotePayload {
+        let mut quote: PdfQuotePayload = serde_json::from_value(json!({
+            "templateName": "default", "language": "en", "id": 20210001, "attentionOf": null, "receiver": "Acme", "reference": reference, "notes": null,
+            "expiryDate": 0, "creationDate": 0, "rows": [], "address": { "city": "Utrecht", "country": "Netherlands", "postalCode": "1234 AB", "street": "Main 1" },
+            "quoteTopic": "Website", "quoteContactPerson": "Jane", "debitId": "D1"
+        })).unwrap();
+        quote.common.rows = rows;
+        quote
+    }
+
+    #[test]
+    fn diff_fields_reports_changed_fields_only() {
+        let mut from = quote("First", vec![row("A", "10", 1)]);
+        let mut to = quote("Second", vec![row("B", "20", 2)]);
+        from.revision = 0;
+        to.revision = 1;
+
+        let changes = diff_fields(&from, &to);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "reference");
+        assert_eq!(changes[0].from, json!("First"));
+        assert_eq!(changes[0].to, json!("Second"));
+    }
+
+    #[test]
+    fn diff_rows_matches_rows_on_product() {
+        let from = [row("A", "10", 1), row("B", "5", 2)];
+        let to = [row("A", "12", 1), row("C", "7", 1)];
+
+        let (added, removed, changed) = diff_rows(&from, &to);
+        assert_eq!(added.iter().map(|row| row.id.as_str()).collect::<Vec<_>>(), ["C"]);
+        assert_eq!(removed.iter().map(|row| row.id.as_str()).collect::<Vec<_>>(), ["B"]);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].product_id, "A");
+        assert_eq!(changed[0].to.price, "12".parse().unwrap());
+    }
+
+    #[test]
+    fn diff_rows_pairs_repeated_products_in_order() {
+        let from = [row("A", "10", 1), row("A", "10", 2)];
+        let to = [row("A", "10", 1)];
+
+        let (added, removed, changed) = diff_rows(&from, &to);
+        assert!(added.is_empty());
+        assert!(changed.is_empty());
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].quantity, 2);
+    }
+
+    #[test]
+    fn identical_revisions_have_no_differences() {
+        let rows = vec![row("A", "10", 1)];
+        assert!(diff_fields(&quote("Same", rows.clone()), &quote("Same", rows.clone())).is_empty());
+
+        let (added, removed, changed) = diff_rows(&rows, &rows);
+        assert!(added.is_empty() && removed.is_empty() && changed.is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::{Serialize, Deserialize};
+use mysql::prelude::Queryable;
+use mysql::{Params, params};
+use rand::Rng;
+use crate::AppData;
+use crate::endpoints::quote::{QuoteStatus, get_quote_status, expire_quotes, create_token, hash_nonce};
+
+#[derive(Deserialize)]
+pub struct Request {
+    id: i64
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    token:  Option<String>,
+    url:    Option<String>,
+    error:  Option<String>
+}
+
+/**
+Issue a single-use token for the customer-facing accept/decline page of the latest revision of a quote
+*/
+#[post("/quote/token")]
+#[has_permissions("QUOTE_CREATE")]
+#[allow(clippy::async_yields_async)]
+pub async fn create_quote_token(data: web::Data<AppData>, request: web::Json<Request>) -> HttpResponse {
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(err) = expire_quotes(&mut conn) {
+        eprintln!("Failed to expire quotes: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let revision = match get_quote_status(&mut conn, request.id) {
+        Ok(Some((revision, QuoteStatus::Open))) => revision,
+        Ok(Some((_, status))) => return HttpResponse::BadRequest().json(Response { token: None, url: None, error: Some(format!("Quote {} is {} and can no longer be responded to", request.id, status.as_str())) }),
+        Ok(None) => return HttpResponse::NotFound().body(format!("Quote with ID {} does not exist.", request.id)),
+        Err(err) => {
+            eprintln!("Failed to query quote from the database: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let nonce: String = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect();
+    let token = match create_token(&data.config, request.id, &nonce) {
+        Ok(token) => token,
+        Err(err) => {
+            eprintln!("Failed to create quote token: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let sql_insert_token = conn.exec::<usize, &str, Params>("INSERT INTO quote_tokens (token_hash, quote_id, revision, created_at) VALUES (:token_hash, :quote_id, :revision, :created_at)", params! {
+        "token_hash" => hash_nonce(&nonce),
+        "quote_id" => request.id,
+        "revision" => revision,
+        "created_at" => chrono::Utc::now().timestamp()
+    });
+
+    if sql_insert_token.is_err() {
+        eprintln!("Failed to insert quote token into the database: {:?}", sql_insert_token.err().unwrap());
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let url = format!("{}?token={}", data.config.quote_response_url, token);
+    HttpResponse::Ok().json(Response { token: Some(token), url: Some(url), error: None })
+}
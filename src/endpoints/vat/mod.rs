@@ -0,0 +1,321 @@
+pub mod report;
+
+use chrono::NaiveDate;
+use mysql::prelude::Queryable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::apis::pdf::PdfCommonPayload;
+use crate::appdata::Config;
+use crate::calculation::calculate;
+use crate::countries;
+use crate::endpoints::invoice::{get_issued_invoices, get_credit_notes};
+use crate::money::{self, Money};
+use crate::vat_number;
+
+/// Boxes of the Dutch VAT return for sales, with whether VAT is reported in the box
+const BOXES: &[(&str, &str, bool)] = &[
+    ("1a", "Supplies and services taxed at the high rate", true),
+    ("1b", "Supplies and services taxed at the low rate", true),
+    ("1c", "Supplies and services taxed at other rates, except 0%", true),
+    ("1e", "Supplies and services taxed at 0% or not taxed in the Netherlands", false),
+    ("3a", "Supplies to countries outside the EU", false),
+    ("3b", "Supplies to or services in countries within the EU", false)
+];
+
+const HIGH_RATE: u32 = 21;
+/// 9% since 2019, 6% before
+const LOW_RATES: &[u32] = &[9, 6];
+
+/**
+A document's share in a box of the return, in the base currency
+*/
+#[derive(Serialize, Clone)]
+pub struct Entry {
+    #[serde(rename = "box")]
+    pub vat_box:        &'static str,
+    pub document_type:  &'static str,
+    pub document_id:    i64,
+    pub creation_date:  i64,
+    pub receiver:       String,
+    pub country:        String,
+    pub currency:       String,
+    pub base:           Money,
+    pub vat:            Money
+}
+
+#[derive(Serialize)]
+pub struct BoxTotal {
+    #[serde(rename = "box")]
+    pub vat_box:        &'static str,
+    pub description:    &'static str,
+    pub base:           Money,
+    /// Not set for boxes without VAT
+    pub vat:            Option<Money>,
+    /// The documents behind the amounts
+    pub documents:      Vec<Entry>
+}
+
+#[derive(Serialize)]
+pub struct VatReturn {
+    /// First and last day of the period, `YYYY-MM-DD`
+    pub from:           String,
+    pub to:             String,
+    pub boxes:          Vec<BoxTotal>,
+    /// Box 5a, VAT due
+    pub vat_due:        Money,
+    /// Box 5b, input VAT. Invoicr has no purchases, so this is always zero
+    pub input_vat:      Money,
+    /// Box 5c, 5a minus 5b
+    pub balance:        Money,
+    pub warnings:       Vec<String>
+}
+
+/**
+Parse a return period: a year (`2024`), a quarter (`2024-Q1`) or a month (`2024-03`). Returns the first and last day
+*/
+pub fn parse_period(period: &str) -> crate::Result<(NaiveDate, NaiveDate)> {
+    let invalid = || format!("Invalid period '{}', expected a year (2024), quarter (2024-Q1) or month (2024-03)", period);
+    let (year, rest) = match period.trim().split_once('-') {
+        Some((year, rest)) => (year, Some(rest)),
+        None => (period.trim(), None)
+    };
+
+    let year: i32 = year.parse().map_err(|_| invalid())?;
+    let (first_month, months) = match rest {
+        None => (1, 12),
+        Some(quarter) if quarter.starts_with('Q') || quarter.starts_with('q') => match quarter[1..].parse::<u32>() {
+            Ok(quarter @ 1..=4) => ((quarter - 1) * 3 + 1, 3),
+            _ => return Err(invalid())
+        },
+        Some(month) => match month.parse::<u32>() {
+            Ok(month @ 1..=12) => (month, 1),
+            _ => return Err(invalid())
+        }
+    };
+
+    let from = NaiveDate::from_ymd_opt(year, first_month, 1).ok_or_else(invalid)?;
+    let last_month = first_month + months - 1;
+    let to = match last_month {
+        12 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+        _ => NaiveDate::from_ymd_opt(year, last_month + 1, 1)
+    }.and_then(|next| next.pred_opt()).ok_or_else(invalid)?;
+
+    Ok((from, to))
+}
+
+/**
+Work out the box of a row from its VAT percentage and the country of the receiver.
+Rows without VAT to another country are exports, rows with VAT are always domestic supplies
+*/
+fn classify(vat_perc: Money, country: Option<&str>, domestic: &str) -> &'static str {
+    if vat_perc.is_zero() {
+        return match country {
+            Some(country) if country != domestic && countries::is_eu(country) => "3b",
+            Some(country) if country != domestic => "3a",
+            _ => "1e"
+        };
+    }
+
+    if vat_perc == Money::from(HIGH_RATE) {
+        "1a"
+    } else if LOW_RATES.iter().any(|rate| vat_perc == Money::from(*rate)) {
+        "1b"
+    } else {
+        "1c"
+    }
+}
+
+/**
+Validate the VAT number of the receiver and work out whether the VAT is reverse charged. That is the case for
+businesses in another EU member state than the seller, identified by their VAT number. All rows of a reverse
+charged document are set to 0%. Returns whether any row had another rate.
+Without a seller VAT number reverse charge can not be applied, so receivers with an EU VAT number are refused then
+*/
+pub fn apply_reverse_charge(config: &Config, payload: &mut PdfCommonPayload) -> crate::Result<bool> {
+    //VAT numbers from outside the EU can not be checked and are kept as given
+    payload.address.vat_number = match payload.address.vat_number.as_deref().map(vat_number::normalize) {
+        Some(number) if number.is_empty() => None,
+        Some(number) if vat_number::country(&number).is_some() => Some(vat_number::validate(&number).map_err(|err| format!("Invalid VAT number of the receiver: {}.", err))?),
+        number => number
+    };
+
+    let seller = vat_number::country(&vat_number::normalize(&config.seller.vat_number));
+    let receiver = payload.address.vat_number.as_deref().and_then(vat_number::country);
+    if receiver.is_some() && config.seller.vat_number.trim().is_empty() {
+        return Err("The receiver has an EU VAT number, but no seller VAT number is configured to decide on reverse charge.".to_string());
+    }
+
+    payload.reverse_charge = match (seller, receiver) {
+        (Some(seller), Some(receiver)) => seller != receiver,
+        _ => false
+    };
+
+    if !payload.reverse_charge {
+        return Ok(false);
+    }
+
+    let changed = payload.rows.iter().any(|row| !row.vat_perc.is_zero());
+    for row in payload.rows.iter_mut() {
+        row.vat_perc = Money::ZERO;
+    }
+
+    Ok(changed)
+}
+
+/**
+Split a document over the boxes. `sign` is -1 for credit notes, which lower the boxes they fall in
+*/
+fn entries(config: &Config, document: &PdfCommonPayload, document_type: &'static str, sign: Money, domestic: &str, warnings: &mut Vec<String>) -> Vec<Entry> {
+    //A reverse charged supply goes to the member state of the receiver's VAT number, wherever the address is
+    let country = match document.address.vat_number.as_deref().and_then(vat_number::country) {
+        Some(country) if document.reverse_charge => Some(country),
+        _ => countries::iso_code(&document.address.country)
+    };
+    if country.is_none() {
+        warnings.push(format!("The country '{}' of {} {} is not known, its rows are reported as domestic.", document.address.country, document_type.replace('_', " "), document.id));
+    }
+
+    let totals = calculate(&document.rows, config.rounding);
+    let mut per_box: BTreeMap<&'static str, (Money, Money)> = BTreeMap::new();
+    for line in &totals.lines {
+        let (base, vat) = per_box.entry(classify(line.vat_perc, country, domestic)).or_default();
+        *base += line.net;
+        *vat += line.vat;
+    }
+
+    per_box.into_iter()
+        .map(|(vat_box, (base, vat))| Entry {
+            vat_box,
+            document_type,
+            document_id: document.id,
+            creation_date: document.creation_date,
+            receiver: document.receiver.clone(),
+            country: country.unwrap_or(&document.address.country).to_string(),
+            currency: document.currency.clone().unwrap_or_else(|| config.base_currency.clone()),
+            base: sign * money::to_base(money::round(base), document.exchange_rate),
+            vat: sign * money::to_base(money::round(vat), document.exchange_rate)
+        })
+        .collect()
+}
+
+/**
+Build the VAT return for the inclusive date range `[from, to]` from the issued invoices and credit notes created in it
+*/
+pub fn build_return<Q: Queryable>(conn: &mut Q, config: &Config, from: NaiveDate, to: NaiveDate) -> mysql::Result<VatReturn> {
+    let start = from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    let end = to.succ_opt().unwrap_or(to).and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    let domestic = countries::iso_code(&config.seller.country).unwrap_or("NL");
+
+    let mut warnings = Vec::new();
+    let mut all = Vec::new();
+    for invoice in get_issued_invoices(conn, start, end)? {
+        all.extend(entries(config, &invoice, "invoice", Money::ONE, domestic, &mut warnings));
+    }
+
+    for credit_note in get_credit_notes(conn, start, end)? {
+        all.extend(entries(config, &credit_note.common, "credit_note", -Money::ONE, domestic, &mut warnings));
+    }
+
+    let boxes: Vec<BoxTotal> = BOXES.iter()
+        .map(|(vat_box, description, has_vat)| {
+            let documents: Vec<Entry> = all.iter().filter(|entry| entry.vat_box == *vat_box).cloned().collect();
+            BoxTotal {
+                vat_box,
+                description,
+                base: documents.iter().map(|entry| entry.base).sum(),
+                vat: if *has_vat { Some(documents.iter().map(|entry| entry.vat).sum()) } else { None },
+                documents
+            }
+        })
+        .collect();
+
+    let vat_due: Money = boxes.iter().filter_map(|total| total.vat).sum();
+    Ok(VatReturn {
+        from: from.format("%Y-%m-%d").to_string(),
+        to: to.format("%Y-%m-%d").to_string(),
+        boxes,
+        vat_due,
+        input_vat: Money::ZERO,
+        balance: vat_due,
+        warnings
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_period_covers_years_quarters_and_months() {
+        assert_eq!(parse_period("2024"), Ok((date(2024, 1, 1), date(2024, 12, 31))));
+        assert_eq!(parse_period("2024-Q1"), Ok((date(2024, 1, 1), date(2024, 3, 31))));
+        assert_eq!(parse_period("2024-q4"), Ok((date(2024, 10, 1), date(2024, 12, 31))));
+        assert_eq!(parse_period("2024-02"), Ok((date(2024, 2, 1), date(2024, 2, 29))));
+        assert_eq!(parse_period("2023-12"), Ok((date(2023, 12, 1), date(2023, 12, 31))));
+        assert_eq!(parse_period(" 2024-3 "), Ok((date(2024, 3, 1), date(2024, 3, 31))));
+    }
+
+    #[test]
+    fn parse_period_rejects_unknown_periods() {
+        for period in ["", "abc", "2024-", "2024-Q", "2024-Q0", "2024-Q5", "2024-00", "2024-13", "2024-03-01", "2024-Ä1", "-2024"] {
+            assert!(parse_period(period).is_err(), "{}", period);
+        }
+    }
+
+    #[test]
+    fn classify_zero_rated_rows_by_country() {
+        assert_eq!(classify(Money::ZERO, Some("NL"), "NL"), "1e");
+        assert_eq!(classify(Money::ZERO, None, "NL"), "1e");
+        assert_eq!(classify(Money::ZERO, Some("DE"), "NL"), "3b");
+        assert_eq!(classify(Money::ZERO, Some("US"), "NL"), "3a");
+    }
+
+    #[test]
+    fn classify_rows_with_vat_as_domestic() {
+        assert_eq!(classify(money("21.00"), Some("DE"), "NL"), "1a");
+        assert_eq!(classify(money("9"), Some("US"), "NL"), "1b");
+        assert_eq!(classify(money("6"), None, "NL"), "1b");
+        assert_eq!(classify(money("19"), Some("NL"), "NL"), "1c");
+    }
+
+    fn document(vat_number: Option<&str>) -> PdfCommonPayload {
+        let mut document = crate::ubl::tests::invoice();
+        document.address.vat_number = vat_number.map(str::to_string);
+        document
+    }
+
+    #[test]
+    fn reverse_charge_applies_to_other_member_states() {
+        let config = Config { seller: crate::ubl::tests::seller(), ..Config::default() };
+
+        let mut foreign = document(Some("de 123456788"));
+        assert_eq!(apply_reverse_charge(&config, &mut foreign), Ok(true));
+        assert!(foreign.reverse_charge);
+        assert!(foreign.rows.iter().all(|row| row.vat_perc.is_zero()));
+        assert_eq!(foreign.address.vat_number.as_deref(), Some("DE123456788"));
+
+        let mut domestic = document(Some("NL000099998B57"));
+        assert_eq!(apply_reverse_charge(&config, &mut domestic), Ok(false));
+        assert!(!domestic.reverse_charge);
+
+        assert!(apply_reverse_charge(&config, &mut document(Some("DE123456789"))).is_err());
+    }
+
+    #[test]
+    fn reverse_charge_requires_a_seller_vat_number() {
+        let mut seller = crate::ubl::tests::seller();
+        seller.vat_number = String::new();
+        let config = Config { seller, ..Config::default() };
+
+        assert!(apply_reverse_charge(&config, &mut document(Some("DE123456788"))).is_err());
+        assert_eq!(apply_reverse_charge(&config, &mut document(None)), Ok(false));
+    }
+}
@@ -0,0 +1,80 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web_grants::proc_macro::has_permissions;
+use serde::Deserialize;
+use crate::AppData;
+use crate::endpoints::products::Format;
+use crate::endpoints::vat::{build_return, parse_period};
+
+#[derive(Deserialize)]
+pub struct Query {
+    /// `2024`, `2024-Q1` or `2024-03`
+    period:     String,
+    format:     Option<Format>,
+    /// Only include this box, e.g. `1a`
+    #[serde(rename = "box")]
+    vat_box:    Option<String>
+}
+
+/**
+The VAT return for a period, grouped into the boxes of the Dutch return with the documents behind every box.
+As CSV, every line is the share of one document in one box
+*/
+#[get("/vat/return")]
+#[has_permissions("INVOICE_READ")]
+#[allow(clippy::async_yields_async)]
+pub async fn get_vat_return(data: web::Data<AppData>, query: web::Query<Query>) -> HttpResponse {
+    let (from, to) = match parse_period(&query.period) {
+        Ok(range) => range,
+        Err(err) => return HttpResponse::BadRequest().body(err)
+    };
+
+    let mut conn = match data.pool.get_conn() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Failed to create database connection: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut vat_return = match build_return(&mut conn, &data.config, from, to) {
+        Ok(vat_return) => vat_return,
+        Err(err) => {
+            eprintln!("Failed to query documents for the VAT return: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Some(vat_box) = &query.vat_box {
+        if !vat_return.boxes.iter().any(|total| total.vat_box == vat_box) {
+            return HttpResponse::BadRequest().body(format!("Unknown box '{}'.", vat_box));
+        }
+
+        vat_return.boxes.retain(|total| total.vat_box == vat_box);
+    }
+
+    match query.format.unwrap_or(Format::Json) {
+        Format::Json => HttpResponse::Ok().json(vat_return),
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for entry in vat_return.boxes.iter().flat_map(|total| total.documents.iter()) {
+                if let Err(err) = writer.serialize(entry) {
+                    eprintln!("Unable to serialize VAT return entry to CSV: {:?}", err);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+
+            let body = match writer.into_inner() {
+                Ok(body) => body,
+                Err(err) => {
+                    eprintln!("Unable to write CSV export: {:?}", err);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            };
+
+            HttpResponse::Ok()
+                .content_type("text/csv")
+                .header("Content-Disposition", format!("attachment; filename=\"vat-return-{}.csv\"", query.period.trim()))
+                .body(body)
+        }
+    }
+}
@@ -3,6 +3,15 @@ mod endpoints;
 mod apis;
 mod threads;
 mod authenticator;
+mod migrations;
+mod sequences;
+mod money;
+mod calculation;
+mod countries;
+mod ubl;
+mod xml;
+mod xaf;
+mod vat_number;
 
 use crate::appdata::{Config, AppData};
 use actix_web::{HttpServer, App};
@@ -13,13 +22,64 @@ type Result<T> = std::result::Result<T, String>;
 #[actix_web::main]
 pub async fn main() -> std::io::Result<()> {
     println!("Welcome to Invoicr by MrFriendly");
-    let config = Config::read();
+    let mut config = Config::read();
+    config.base_currency = match endpoints::exchange_rates::normalize_currency(&config.base_currency) {
+        Ok(currency) => currency,
+        Err(err) => {
+            eprintln!("Invalid base currency in configuration: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    for format in [&config.invoice_number_format, &config.quote_number_format, &config.credit_note_number_format] {
+        if let Err(err) = sequences::validate_format(format) {
+            eprintln!("Invalid number format in configuration: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if !config.seller.vat_number.trim().is_empty() {
+        config.seller.vat_number = match vat_number::validate(&config.seller.vat_number) {
+            Ok(vat_number) => vat_number,
+            Err(err) => {
+                eprintln!("Invalid seller VAT number in configuration: {}", err);
+                std::process::exit(1);
+            }
+        };
+    } else {
+        eprintln!("No seller VAT number configured, documents for receivers with an EU VAT number will be refused.");
+    }
+
     let appdata = AppData::new(&config);
-    if !appdata.check_db() {
-        println!("Database check failed, some tables are missing. Creating them now.");
-        appdata.init_db();
+    match migrations::run(&appdata.pool) {
+        Ok(0) => println!("Database schema is up to date."),
+        Ok(applied) => println!("Applied {} database migration(s).", applied),
+        Err(err) => {
+            eprintln!("Database migration failed: {}", err);
+            std::process::exit(1);
+        }
     }
 
+    if std::env::args().any(|arg| arg == "--migrate-only") {
+        std::process::exit(0);
+    }
+
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--export-xaf") {
+        match xaf::export_to_file(&appdata.pool, &config, &args[position + 1..]) {
+            Ok(path) => {
+                println!("Auditfile written to '{}'.", path);
+                std::process::exit(0);
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    crate::threads::quotes::start(appdata.pool.clone());
+
     println!("Starting on port 8090");
     HttpServer::new(move || {
         let _cors = actix_cors::Cors::default()
@@ -41,12 +101,50 @@ pub async fn main() -> std::io::Result<()> {
             .service(crate::endpoints::products::get::get_products)
             .service(crate::endpoints::products::add::add_product)
             .service(crate::endpoints::products::del::del_product)
+            .service(crate::endpoints::products::update::update_product)
+            .service(crate::endpoints::products::revisions::get_product_revisions)
+            .service(crate::endpoints::products::restore::restore_product)
+            .service(crate::endpoints::products::import::import_products)
+            .service(crate::endpoints::products::export::export_products)
+            .service(crate::endpoints::pricelists::get::get_price_lists)
+            .service(crate::endpoints::pricelists::add::add_price_list)
+            .service(crate::endpoints::pricelists::del::del_price_list)
+            .service(crate::endpoints::pricelists::entries::set_price_list_entries)
+            .service(crate::endpoints::pricelists::price::get_price)
             .service(crate::endpoints::persons::get::get_contacts)
             .service(crate::endpoints::pdf::invoice::create_invoice)
+            .service(crate::endpoints::pdf::invoice::regenerate_invoice)
             .service(crate::endpoints::pdf::quote::create_quote)
+            .service(crate::endpoints::pdf::credit_note::create_credit_note)
+            .service(crate::endpoints::pdf::credit_note::regenerate_credit_note)
+            .service(crate::endpoints::calculate::totals::calculate_totals)
+            .service(crate::endpoints::exchange_rates::get::get_exchange_rates)
+            .service(crate::endpoints::exchange_rates::add::add_exchange_rates)
+            .service(crate::endpoints::exchange_rates::import::import_exchange_rates)
+            .service(crate::endpoints::quote::convert::convert_quote)
+            .service(crate::endpoints::quote::get::get_quote_by_id)
+            .service(crate::endpoints::invoice::get::get_invoice_by_id)
+            .service(crate::endpoints::invoice::ubl::get_invoice_ubl)
+            .service(crate::endpoints::invoice::ubl::validate_invoice_ubl)
+            .service(crate::endpoints::invoice::import::import_invoice)
+            .service(crate::endpoints::quote::import::import_quote)
+            .service(crate::endpoints::auditfile::xaf::get_xaf)
+            .service(crate::endpoints::vat::report::get_vat_return)
+            .service(crate::endpoints::quote::revise::revise_quote)
+            .service(crate::endpoints::quote::revisions::get_revisions)
+            .service(crate::endpoints::quote::revisions::get_revision_diff)
+            .service(crate::endpoints::quote::token::create_quote_token)
+            .service(crate::endpoints::quote::respond::get_quote_response)
+            .service(crate::endpoints::quote::respond::respond_to_quote)
             .service(crate::endpoints::history::invoice::get_invoice_history)
+            .service(crate::endpoints::invoice::status::get_invoice_status)
+            .service(crate::endpoints::invoice::status::set_invoice_status)
+            .service(crate::endpoints::invoice::payments::get_payments)
+            .service(crate::endpoints::invoice::payments::add_payment)
             .service(crate::endpoints::history::quote::get_quote_history)
+            .service(crate::endpoints::history::credit_note::get_credit_note_history)
             .service(crate::endpoints::ids::quote::get_quite_id)
+            .service(crate::endpoints::ids::invoice::get_invoice_id)
     })
     .bind("0.0.0.0:8090")?
     .run()
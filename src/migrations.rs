@@ -0,0 +1,248 @@
+use mysql::prelude::Queryable;
+use mysql::{Params, params};
+
+/**
+A numbered schema change. Migrations are applied in order and each version is applied only once
+*/
+pub struct Migration {
+    pub version:        u32,
+    pub description:    &'static str,
+    pub statements:     &'static [&'static str]
+}
+
+/**
+All migrations, oldest first. Never change a migration that has been released, add a new one instead
+*/
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS `products` (`id` varchar(32) NOT NULL, `name` varchar(255) NOT NULL, `description` text NOT NULL, `price` double NOT NULL, PRIMARY KEY (`id`), KEY `name` (`name`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+            "CREATE TABLE IF NOT EXISTS `invoices` (`id` bigint(64) NOT NULL, `template_name` varchar(255) NOT NULL, `language` varchar(255) NOT NULL, `attention_of` varchar(255) DEFAULT NULL, `receiver` varchar(255) NOT NULL, `reference` text NOT NULL, `notes` text DEFAULT NULL, `expiry_date` bigint(20) NOT NULL, `creation_date` bigint(20) NOT NULL, `city` varchar(255) NOT NULL, `country` varchar(255) NOT NULL, `postal_code` varchar(255) NOT NULL, `street` varchar(255) NOT NULL, PRIMARY KEY (`id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+            "CREATE TABLE IF NOT EXISTS `quotes` (`id` bigint(64) NOT NULL, `template_name` varchar(255) NOT NULL, `language` varchar(255) NOT NULL, `attention_of` varchar(255) DEFAULT NULL, `receiver` varchar(255) NOT NULL, `reference` text NOT NULL, `notes` text DEFAULT NULL, `expiry_date` bigint(20) NOT NULL, `creation_date` bigint(20) NOT NULL, `city` varchar(255) NOT NULL, `country` varchar(255) NOT NULL, `postal_code` varchar(255) NOT NULL, `street` varchar(255) NOT NULL, `quote_topic` varchar(255) NOT NULL, `quote_contact_person` varchar(255) NOT NULL, `debit_id` varchar(255) NOT NULL, PRIMARY KEY (`id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+            "CREATE TABLE IF NOT EXISTS `itemrows` (`id` varchar(32) NOT NULL, `product_id` varchar(32) NOT NULL, `parent_id` bigint(64) NOT NULL, `parent_type` varchar(32) NOT NULL, `comment` text DEFAULT NULL, `name` varchar(255) NOT NULL, `description` text NOT NULL, `discount_perc` double DEFAULT NULL, `vat_perc` double NOT NULL, `price` double NOT NULL, `quantity` bigint(20) NOT NULL, PRIMARY KEY (`id`), KEY `parent` (`parent_type`, `parent_id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 2,
+        description: "Product revisions",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS `product_revisions` (`id` varchar(32) NOT NULL, `product_id` varchar(32) NOT NULL, `name` varchar(255) NOT NULL, `description` text NOT NULL, `price` double NOT NULL, `revision_date` bigint(20) NOT NULL, PRIMARY KEY (`id`), KEY `product_id` (`product_id`, `revision_date`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 3,
+        description: "Archived products",
+        statements: &[
+            "ALTER TABLE `products` ADD COLUMN `archived` tinyint(1) NOT NULL DEFAULT 0"
+        ]
+    },
+    Migration {
+        version: 4,
+        description: "Product catalogue fields",
+        statements: &[
+            "ALTER TABLE `products` ADD COLUMN `sku` varchar(64) DEFAULT NULL, ADD COLUMN `unit` varchar(32) DEFAULT NULL, ADD COLUMN `vat_perc` double DEFAULT NULL, ADD COLUMN `category` varchar(255) DEFAULT NULL, ADD UNIQUE KEY `sku` (`sku`), ADD KEY `category` (`category`)",
+            "ALTER TABLE `product_revisions` ADD COLUMN `sku` varchar(64) DEFAULT NULL AFTER `price`, ADD COLUMN `unit` varchar(32) DEFAULT NULL AFTER `sku`, ADD COLUMN `vat_perc` double DEFAULT NULL AFTER `unit`, ADD COLUMN `category` varchar(255) DEFAULT NULL AFTER `vat_perc`"
+        ]
+    },
+    Migration {
+        version: 5,
+        description: "Price lists",
+        statements: &[
+            //A unique key allows any number of NULLs, so only the price list without an account takes part in `is_default`
+            "CREATE TABLE IF NOT EXISTS `price_lists` (`id` varchar(32) NOT NULL, `name` varchar(255) NOT NULL, `account_id` varchar(255) DEFAULT NULL, `is_default` tinyint(1) AS (IF(`account_id` IS NULL, 1, NULL)) STORED, PRIMARY KEY (`id`), UNIQUE KEY `account_id` (`account_id`), UNIQUE KEY `is_default` (`is_default`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+            "CREATE TABLE IF NOT EXISTS `price_list_entries` (`id` varchar(32) NOT NULL, `price_list_id` varchar(32) NOT NULL, `product_id` varchar(32) NOT NULL, `min_quantity` bigint(20) NOT NULL DEFAULT 1, `price` double NOT NULL, PRIMARY KEY (`id`), UNIQUE KEY `entry` (`price_list_id`, `product_id`, `min_quantity`), KEY `product_id` (`product_id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 6,
+        description: "Invoice status",
+        statements: &[
+            "ALTER TABLE `invoices` ADD COLUMN `status` varchar(32) NOT NULL DEFAULT 'draft'",
+            //Invoices from before the status existed have had their PDF generated and been handed out, only new invoices start as draft
+            "UPDATE `invoices` SET `status` = 'sent'",
+            "CREATE TABLE IF NOT EXISTS `invoice_status_history` (`id` varchar(32) NOT NULL, `invoice_id` bigint(64) NOT NULL, `status` varchar(32) NOT NULL, `changed_at` bigint(20) NOT NULL, PRIMARY KEY (`id`), KEY `invoice_id` (`invoice_id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 7,
+        description: "Payments",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS `payments` (`id` varchar(32) NOT NULL, `invoice_id` bigint(64) NOT NULL, `payment_date` bigint(20) NOT NULL, `amount` double NOT NULL, `method` varchar(64) NOT NULL, `reference` varchar(255) DEFAULT NULL, PRIMARY KEY (`id`), KEY `invoice_id` (`invoice_id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 8,
+        description: "Credit notes",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS `credit_notes` (`id` bigint(64) NOT NULL, `invoice_id` bigint(64) NOT NULL, `template_name` varchar(255) NOT NULL, `language` varchar(255) NOT NULL, `attention_of` varchar(255) DEFAULT NULL, `receiver` varchar(255) NOT NULL, `reference` text NOT NULL, `notes` text DEFAULT NULL, `expiry_date` bigint(20) NOT NULL, `creation_date` bigint(20) NOT NULL, `city` varchar(255) NOT NULL, `country` varchar(255) NOT NULL, `postal_code` varchar(255) NOT NULL, `street` varchar(255) NOT NULL, PRIMARY KEY (`id`), KEY `invoice_id` (`invoice_id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+            //Rows keep the order they were entered in, so a credit note can select them by position
+            "ALTER TABLE `itemrows` ADD COLUMN `position` int(11) NOT NULL DEFAULT 0 AFTER `parent_type`"
+        ]
+    },
+    Migration {
+        version: 9,
+        description: "Document number sequences",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS `sequences` (`name` varchar(64) NOT NULL, `period` int(11) NOT NULL, `value` bigint(64) NOT NULL, PRIMARY KEY (`name`, `period`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 10,
+        description: "Quote conversions",
+        statements: &[
+            "ALTER TABLE `invoices` ADD COLUMN `quote_id` bigint(64) DEFAULT NULL, ADD KEY `quote_id` (`quote_id`)",
+            //Which rows of a quote every invoice was converted from, so no row is invoiced for more than its full price
+            "CREATE TABLE IF NOT EXISTS `quote_conversions` (`quote_id` bigint(64) NOT NULL, `invoice_id` bigint(64) NOT NULL, `position` int(11) NOT NULL, `percentage` decimal(9,6) NOT NULL, PRIMARY KEY (`invoice_id`, `position`), KEY `quote_id` (`quote_id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 11,
+        description: "Quote responses",
+        statements: &[
+            "ALTER TABLE `quotes` ADD COLUMN `status` varchar(32) NOT NULL DEFAULT 'open', ADD COLUMN `decided_at` bigint(20) DEFAULT NULL, ADD COLUMN `decided_by` varchar(255) DEFAULT NULL",
+            "CREATE TABLE IF NOT EXISTS `quote_tokens` (`token_hash` varchar(64) NOT NULL, `quote_id` bigint(64) NOT NULL, `created_at` bigint(20) NOT NULL, `used_at` bigint(20) DEFAULT NULL, PRIMARY KEY (`token_hash`), KEY `quote_id` (`quote_id`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 12,
+        description: "Quote revisions",
+        statements: &[
+            "ALTER TABLE `quotes` ADD COLUMN `revision` int(11) NOT NULL DEFAULT 0 AFTER `id`, DROP PRIMARY KEY, ADD PRIMARY KEY (`id`, `revision`)",
+            "ALTER TABLE `itemrows` ADD COLUMN `parent_revision` int(11) NOT NULL DEFAULT 0 AFTER `parent_id`",
+            "ALTER TABLE `quote_tokens` ADD COLUMN `revision` int(11) NOT NULL DEFAULT 0 AFTER `quote_id`"
+        ]
+    },
+    Migration {
+        version: 13,
+        description: "Credit note status",
+        statements: &[
+            "ALTER TABLE `credit_notes` ADD COLUMN `status` varchar(32) NOT NULL DEFAULT 'finalized'"
+        ]
+    },
+    Migration {
+        version: 14,
+        description: "Store amounts and percentages as exact decimals",
+        //Six decimals keep every value a double could meaningfully hold for prices and percentages
+        statements: &[
+            "ALTER TABLE `products` MODIFY `price` decimal(19,6) NOT NULL, MODIFY `vat_perc` decimal(9,6) DEFAULT NULL",
+            "ALTER TABLE `product_revisions` MODIFY `price` decimal(19,6) NOT NULL, MODIFY `vat_perc` decimal(9,6) DEFAULT NULL",
+            "ALTER TABLE `price_list_entries` MODIFY `price` decimal(19,6) NOT NULL",
+            "ALTER TABLE `itemrows` MODIFY `price` decimal(19,6) NOT NULL, MODIFY `discount_perc` decimal(9,6) DEFAULT NULL, MODIFY `vat_perc` decimal(9,6) NOT NULL",
+            "ALTER TABLE `payments` MODIFY `amount` decimal(19,6) NOT NULL"
+        ]
+    },
+    Migration {
+        version: 15,
+        description: "Document currency and exchange rates",
+        //Existing documents were all in euros
+        statements: &[
+            "ALTER TABLE `invoices` ADD COLUMN `currency` char(3) NOT NULL DEFAULT 'EUR', ADD COLUMN `exchange_rate` decimal(19,10) NOT NULL DEFAULT 1",
+            "ALTER TABLE `quotes` ADD COLUMN `currency` char(3) NOT NULL DEFAULT 'EUR', ADD COLUMN `exchange_rate` decimal(19,10) NOT NULL DEFAULT 1",
+            "ALTER TABLE `credit_notes` ADD COLUMN `currency` char(3) NOT NULL DEFAULT 'EUR', ADD COLUMN `exchange_rate` decimal(19,10) NOT NULL DEFAULT 1",
+            "CREATE TABLE IF NOT EXISTS `exchange_rates` (`currency` char(3) NOT NULL, `rate_date` bigint(20) NOT NULL, `rate` decimal(19,10) NOT NULL, PRIMARY KEY (`currency`, `rate_date`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+        ]
+    },
+    Migration {
+        version: 16,
+        description: "Electronic address of the receiver for e-invoicing",
+        statements: &[
+            "ALTER TABLE `invoices` ADD COLUMN `electronic_address` varchar(255) DEFAULT NULL",
+            "ALTER TABLE `quotes` ADD COLUMN `electronic_address` varchar(255) DEFAULT NULL",
+            "ALTER TABLE `credit_notes` ADD COLUMN `electronic_address` varchar(255) DEFAULT NULL"
+        ]
+    },
+    Migration {
+        version: 17,
+        description: "Number of imported documents in the system they came from",
+        statements: &[
+            "ALTER TABLE `invoices` ADD COLUMN `source_id` varchar(255) DEFAULT NULL",
+            "ALTER TABLE `quotes` ADD COLUMN `source_id` varchar(255) DEFAULT NULL"
+        ]
+    },
+    Migration {
+        version: 18,
+        description: "VAT number of the receiver and intra-EU reverse charge",
+        statements: &[
+            "ALTER TABLE `invoices` ADD COLUMN `vat_number` varchar(32) DEFAULT NULL, ADD COLUMN `reverse_charge` tinyint(1) NOT NULL DEFAULT 0",
+            "ALTER TABLE `quotes` ADD COLUMN `vat_number` varchar(32) DEFAULT NULL, ADD COLUMN `reverse_charge` tinyint(1) NOT NULL DEFAULT 0",
+            "ALTER TABLE `credit_notes` ADD COLUMN `vat_number` varchar(32) DEFAULT NULL, ADD COLUMN `reverse_charge` tinyint(1) NOT NULL DEFAULT 0"
+        ]
+    }
+];
+
+/**
+Bring the database schema up to date by applying every migration that has not been applied yet.
+Returns the number of migrations applied
+*/
+pub fn run(pool: &mysql::Pool) -> crate::Result<usize> {
+    let mut conn = pool.get_conn().map_err(|e| format!("Unable to create database connection: {:?}", e))?;
+
+    conn.query_drop("CREATE TABLE IF NOT EXISTS `schema_migrations` (`version` int(11) NOT NULL, `description` varchar(255) NOT NULL, `applied_at` bigint(20) NOT NULL, PRIMARY KEY (`version`)) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4")
+        .map_err(|e| format!("Unable to create table 'schema_migrations': {:?}", e))?;
+
+    let current = conn.query_first::<Option<u32>, &str>("SELECT MAX(version) FROM schema_migrations")
+        .map_err(|e| format!("Unable to fetch the schema version: {:?}", e))?
+        .flatten()
+        .unwrap_or(0);
+
+    let latest = MIGRATIONS.last().map(|migration| migration.version).unwrap_or(0);
+    if current > latest {
+        return Err(format!("Database schema version {} is newer than the latest known version {}", current, latest));
+    }
+
+    let mut applied = 0;
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        println!("Applying migration {}: {}", migration.version, migration.description);
+
+        //MySQL commits implicitly after every DDL statement, so a failed migration has to be fixed by hand
+        for statement in migration.statements {
+            conn.query_drop(statement).map_err(|e| format!("Migration {} failed: {:?}", migration.version, e))?;
+        }
+
+        conn.exec_drop::<&str, Params>("INSERT INTO schema_migrations (version, description, applied_at) VALUES (:version, :description, :applied_at)", params! {
+            "version" => migration.version,
+            "description" => migration.description,
+            "applied_at" => chrono::Utc::now().timestamp()
+        }).map_err(|e| format!("Unable to record migration {}: {:?}", migration.version, e))?;
+
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_count_up_from_one() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, index + 1, "{}", migration.description);
+        }
+    }
+
+    #[test]
+    fn migrations_are_not_empty() {
+        for migration in MIGRATIONS {
+            assert!(!migration.description.is_empty());
+            assert!(!migration.statements.is_empty(), "Migration {} has no statements", migration.version);
+            assert!(migration.statements.iter().all(|statement| !statement.trim().is_empty() && !statement.trim_end().ends_with(';')));
+        }
+    }
+
+    #[test]
+    fn tables_are_created_before_they_are_altered() {
+        let mut created = Vec::new();
+        for statement in MIGRATIONS.iter().flat_map(|migration| migration.statements.iter()) {
+            if let Some(rest) = statement.strip_prefix("CREATE TABLE IF NOT EXISTS `") {
+                created.push(rest.split('`').next().unwrap());
+            } else if let Some(rest) = statement.strip_prefix("ALTER TABLE `") {
+                let table = rest.split('`').next().unwrap();
+                assert!(created.contains(&table), "Table '{}' is altered before it is created", table);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Serialize, Deserialize};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::str::FromStr;
+
+/// Monetary amounts and percentages are exact decimals, never floats. They are serialized as strings, e.g. `"12.50"`,
+/// and accept both strings and numbers when deserialized
+pub type Money = Decimal;
+
+/// Number of decimals amounts are rounded to
+const CENTS: u32 = 2;
+
+/**
+Where amounts are rounded to cents: every line total before summing, or only the document total
+*/
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    #[default]
+    Line,
+    Total
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(Self::Line),
+            "total" => Ok(Self::Total),
+            _ => Err(format!("Unknown rounding '{}', expected 'line' or 'total'", s))
+        }
+    }
+}
+
+/**
+Round an amount to cents, halves away from zero
+*/
+pub fn round(amount: Money) -> Money {
+    amount.round_dp_with_strategy(CENTS, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/**
+Convert an amount in a document's currency to the base currency, using the exchange rate stored on the document
+*/
+pub fn to_base(amount: Money, exchange_rate: Option<Money>) -> Money {
+    match exchange_rate {
+        Some(rate) if !rate.is_zero() => round(amount / rate),
+        _ => amount
+    }
+}
+
+/**
+Apply a percentage to an amount, e.g. `percentage(200, 21) == 42`
+*/
+pub fn percentage(amount: Money, percentage: Money) -> Money {
+    amount * percentage / Decimal::ONE_HUNDRED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn round_takes_halves_away_from_zero() {
+        assert_eq!(round(money("1.005")), money("1.01"));
+        assert_eq!(round(money("1.004")), money("1.00"));
+        assert_eq!(round(money("-1.005")), money("-1.01"));
+    }
+
+    #[test]
+    fn to_base_divides_by_the_rate() {
+        assert_eq!(to_base(money("110"), Some(money("1.1"))), money("100"));
+        assert_eq!(to_base(money("10"), Some(money("3"))), money("3.33"));
+        assert_eq!(to_base(money("10"), None), money("10"));
+    }
+
+    #[test]
+    fn percentage_is_exact() {
+        assert_eq!(percentage(money("200"), money("21")), money("42"));
+        assert_eq!(percentage(money("0.1"), money("9")), money("0.009"));
+    }
+
+    #[test]
+    fn money_serializes_without_losing_precision() {
+        assert_eq!(serde_json::to_string(&money("1234567890123.456789")).unwrap(), "\"1234567890123.456789\"");
+        assert_eq!(serde_json::to_string(&money("12345678901234567.89")).unwrap(), "\"12345678901234567.89\"");
+        assert_eq!(serde_json::from_str::<Money>("\"0.1\"").unwrap(), money("0.1"));
+        assert_eq!(serde_json::from_str::<Money>("19.99").unwrap(), money("19.99"));
+    }
+
+    #[test]
+    fn rounding_parses_from_config() {
+        assert_eq!("line".parse::<Rounding>(), Ok(Rounding::Line));
+        assert_eq!("total".parse::<Rounding>(), Ok(Rounding::Total));
+        assert!("cents".parse::<Rounding>().is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use mysql::prelude::Queryable;
+use mysql::params;
+use chrono::Datelike;
+use crate::appdata::Config;
+
+/**
+The document types which get their numbers from a sequence
+*/
+#[derive(Clone, Copy)]
+pub enum Sequence {
+    Invoice,
+    Quote,
+    CreditNote
+}
+
+impl Sequence {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Invoice => "invoices",
+            Self::Quote => "quotes",
+            Self::CreditNote => "credit_notes"
+        }
+    }
+
+    /// The table holding the documents numbered by this sequence
+    fn table(&self) -> &'static str {
+        self.name()
+    }
+
+    fn format<'a>(&self, config: &'a Config) -> &'a str {
+        match self {
+            Self::Invoice => &config.invoice_number_format,
+            Self::Quote => &config.quote_number_format,
+            Self::CreditNote => &config.credit_note_number_format
+        }
+    }
+}
+
+/**
+Render a number format such as `{year}{seq:04}`. Supported placeholders are `{year}`, and `{seq}` optionally followed by a minimum width.
+The result must be numeric, as document IDs are stored as integers
+*/
+pub fn format_number(format: &str, year: i32, seq: i64) -> crate::Result<i64> {
+    let mut result = String::new();
+    let mut rest = format;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(format!("Unclosed placeholder in number format '{}'", format))
+        };
+
+        let placeholder = &rest[start + 1..end];
+        match placeholder.split_once(':') {
+            None if placeholder == "year" => result.push_str(&year.to_string()),
+            None if placeholder == "seq" => result.push_str(&seq.to_string()),
+            Some(("seq", width)) => {
+                let width = match width.parse::<usize>() {
+                    Ok(width) => width,
+                    Err(_) => return Err(format!("Invalid width '{}' in number format '{}'", width, format))
+                };
+                result.push_str(&format!("{:0width$}", seq, width = width));
+            },
+            _ => return Err(format!("Unknown placeholder '{{{}}}' in number format '{}'", placeholder, format))
+        }
+
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+
+    result.parse::<i64>().map_err(|_| format!("Number format '{}' does not produce a numeric ID ('{}')", format, result))
+}
+
+/**
+Check that a number format is usable, so a misconfiguration is caught on startup rather than when creating a document
+*/
+pub fn validate_format(format: &str) -> crate::Result<()> {
+    if !format.contains("{seq") {
+        return Err(format!("Number format '{}' does not contain a {{seq}} placeholder", format));
+    }
+
+    format_number(format, 2021, 1).map(|_| ())
+}
+
+/**
+Increment a counter in the sequences table and return its new value, starting at 1
+*/
+fn increment<Q: Queryable>(conn: &mut Q, name: &str, period: i32) -> crate::Result<i64> {
+    conn.exec_drop("INSERT INTO sequences (name, period, value) VALUES (:name, :period, LAST_INSERT_ID(1)) \
+        ON DUPLICATE KEY UPDATE value = LAST_INSERT_ID(value + 1)", params! {
+        "name" => name,
+        "period" => period
+    }).map_err(|err| err.to_string())?;
+
+    match conn.query_first::<i64, &str>("SELECT LAST_INSERT_ID()") {
+        Ok(Some(seq)) => Ok(seq),
+        Ok(None) => Err("LAST_INSERT_ID() returned no value".to_string()),
+        Err(err) => Err(err.to_string())
+    }
+}
+
+/**
+Allocate a provisional ID for an invoice draft. Drafts do not take a number from the gapless invoice sequence,
+they count down from -1 in a counter of their own and get their invoice number when they are finalized
+*/
+pub fn next_draft_id<Q: Queryable>(conn: &mut Q) -> crate::Result<i64> {
+    increment(conn, "invoice_drafts", 0).map(|seq| -seq)
+}
+
+/**
+Allocate the next number of a sequence. Sequences whose format contains `{year}` restart at 1 every year.
+
+The allocation is a single upsert, so concurrent requests never receive the same number. When called inside a transaction
+the sequence row stays locked until it is committed, and a rollback returns the number, keeping the sequence gapless.
+Numbers that are already taken by documents created before server-side numbering are skipped
+*/
+pub fn next_number<Q: Queryable>(conn: &mut Q, config: &Config, sequence: Sequence) -> crate::Result<i64> {
+    let format = sequence.format(config);
+    let year = chrono::Utc::now().year();
+    let period = if format.contains("{year}") { year } else { 0 };
+
+    loop {
+        let seq = increment(conn, sequence.name(), period)?;
+        let number = format_number(format, year, seq)?;
+        let taken = conn.exec_first::<i64, String, _>(format!("SELECT id FROM {} WHERE id = :id", sequence.table()), params! {
+            "id" => number
+        }).map_err(|err| err.to_string())?;
+
+        if taken.is_none() {
+            return Ok(number);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_number_renders_placeholders() {
+        assert_eq!(format_number("{year}{seq:04}", 2021, 7), Ok(20210007));
+        assert_eq!(format_number("9{year}{seq:04}", 2021, 12), Ok(920210012));
+        assert_eq!(format_number("{seq}", 2021, 42), Ok(42));
+        assert_eq!(format_number("1{seq:06}", 2021, 3), Ok(1000003));
+    }
+
+    #[test]
+    fn format_number_grows_beyond_its_width() {
+        assert_eq!(format_number("{year}{seq:02}", 2021, 123), Ok(2021123));
+    }
+
+    #[test]
+    fn format_number_rejects_invalid_formats() {
+        assert!(format_number("{year}{seq", 2021, 1).is_err());
+        assert!(format_number("{seq:xx}", 2021, 1).is_err());
+        assert!(format_number("{month}{seq}", 2021, 1).is_err());
+        assert!(format_number("INV-{seq}", 2021, 1).is_err());
+    }
+
+    #[test]
+    fn validate_format_requires_a_sequence() {
+        assert!(validate_format("{year}{seq:04}").is_ok());
+        assert!(validate_format("9{year}{seq:04}").is_ok());
+        assert!(validate_format("{year}").is_err());
+        assert!(validate_format("INV{seq}").is_err());
+    }
+}
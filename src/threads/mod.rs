@@ -1 +1,2 @@
-pub mod espocrm;
\ No newline at end of file
+pub mod espocrm;
+pub mod quotes;
\ No newline at end of file
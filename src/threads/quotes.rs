@@ -0,0 +1,25 @@
+use std::thread::{spawn, sleep};
+use std::time::Duration;
+use crate::endpoints::quote::expire_quotes;
+
+const EXPIRY_INTERVAL_SECONDS: u64 = 3600;
+
+/**
+Start a thread which periodically marks open quotes past their expiry date as expired
+*/
+pub fn start(pool: mysql::Pool) {
+    spawn(move || {
+        loop {
+            match pool.get_conn() {
+                Ok(mut conn) => match expire_quotes(&mut conn) {
+                    Ok(0) => {},
+                    Ok(count) => println!("Marked {} quotes as expired.", count),
+                    Err(err) => eprintln!("Failed to expire quotes. Retrying in {} seconds: {:?}", EXPIRY_INTERVAL_SECONDS, err)
+                },
+                Err(err) => eprintln!("Failed to create database connection to expire quotes. Retrying in {} seconds: {:?}", EXPIRY_INTERVAL_SECONDS, err)
+            }
+
+            sleep(Duration::from_secs(EXPIRY_INTERVAL_SECONDS));
+        }
+    });
+}
@@ -0,0 +1,184 @@
+use crate::money::Money;
+use crate::xml::{self, Writer};
+use crate::ubl::{UblInvoice, Party, CUSTOMIZATION_ID, PROFILE_ID, EXEMPTION_REVERSE_CHARGE};
+
+/// UNCL 1001 code for a commercial invoice
+const INVOICE_TYPE_CODE: &str = "380";
+/// UN/ECE Recommendation 20 code for "one", used for every line as Invoicr has no units
+const UNIT_CODE: &str = "C62";
+
+/// Amounts carry their currency as an attribute
+fn write_amount(w: &mut Writer, tag: &str, currency: &str, value: Money) {
+    w.leaf(tag, &[("currencyID", currency)], &xml::amount(value));
+}
+
+fn write_party(w: &mut Writer, tag: &str, party: &Party) {
+    w.open(tag, &[]);
+    w.open("cac:Party", &[]);
+
+    if let Some((scheme, id)) = &party.endpoint {
+        w.leaf("cbc:EndpointID", &[("schemeID", scheme)], id);
+    }
+
+    w.open("cac:PartyName", &[]);
+    w.text("cbc:Name", &party.name);
+    w.close("cac:PartyName");
+
+    w.open("cac:PostalAddress", &[]);
+    w.text("cbc:StreetName", &party.street);
+    w.text("cbc:CityName", &party.city);
+    w.text("cbc:PostalZone", &party.postal_code);
+    w.open("cac:Country", &[]);
+    w.text("cbc:IdentificationCode", party.country_code.unwrap_or(&party.country));
+    w.close("cac:Country");
+    w.close("cac:PostalAddress");
+
+    if let Some(vat_number) = &party.vat_number {
+        w.open("cac:PartyTaxScheme", &[]);
+        w.text("cbc:CompanyID", vat_number);
+        w.open("cac:TaxScheme", &[]);
+        w.text("cbc:ID", "VAT");
+        w.close("cac:TaxScheme");
+        w.close("cac:PartyTaxScheme");
+    }
+
+    w.open("cac:PartyLegalEntity", &[]);
+    w.text("cbc:RegistrationName", &party.name);
+    w.optional("cbc:CompanyID", &party.company_id);
+    w.close("cac:PartyLegalEntity");
+
+    if party.contact.is_some() || party.email.is_some() {
+        w.open("cac:Contact", &[]);
+        w.optional("cbc:Name", &party.contact);
+        w.optional("cbc:ElectronicMail", &party.email);
+        w.close("cac:Contact");
+    }
+
+    w.close("cac:Party");
+    w.close(tag);
+}
+
+/// The exemption reason is only written in the VAT breakdown, not on the lines
+fn write_tax_category(w: &mut Writer, tag: &str, category: &str, vat_perc: Money, breakdown: bool) {
+    w.open(tag, &[]);
+    w.text("cbc:ID", category);
+    w.text("cbc:Percent", &vat_perc.normalize().to_string());
+    if breakdown && category == "AE" {
+        w.text("cbc:TaxExemptionReasonCode", EXEMPTION_REVERSE_CHARGE);
+    }
+    w.open("cac:TaxScheme", &[]);
+    w.text("cbc:ID", "VAT");
+    w.close("cac:TaxScheme");
+    w.close(tag);
+}
+
+/**
+Write an invoice as a UBL 2.1 Invoice document following Peppol BIS Billing 3.0
+*/
+pub fn to_xml(invoice: &UblInvoice) -> String {
+    let currency = invoice.currency.as_str();
+    let mut w = Writer::new();
+
+    w.open("Invoice", &[
+        ("xmlns", "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2"),
+        ("xmlns:cac", "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2"),
+        ("xmlns:cbc", "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2")
+    ]);
+
+    w.text("cbc:CustomizationID", CUSTOMIZATION_ID);
+    w.text("cbc:ProfileID", PROFILE_ID);
+    w.text("cbc:ID", &invoice.id);
+    w.text("cbc:IssueDate", &invoice.issue_date.format("%Y-%m-%d").to_string());
+    w.text("cbc:DueDate", &invoice.due_date.format("%Y-%m-%d").to_string());
+    w.text("cbc:InvoiceTypeCode", INVOICE_TYPE_CODE);
+    w.optional("cbc:Note", &invoice.note);
+    w.text("cbc:DocumentCurrencyCode", currency);
+    if !invoice.buyer_reference.is_empty() {
+        w.text("cbc:BuyerReference", &invoice.buyer_reference);
+    }
+
+    write_party(&mut w, "cac:AccountingSupplierParty", &invoice.seller);
+    write_party(&mut w, "cac:AccountingCustomerParty", &invoice.buyer);
+
+    if let Some((code, iban)) = &invoice.payment_means {
+        w.open("cac:PaymentMeans", &[]);
+        w.text("cbc:PaymentMeansCode", code);
+        w.text("cbc:PaymentID", &invoice.id);
+        w.open("cac:PayeeFinancialAccount", &[]);
+        w.text("cbc:ID", iban);
+        w.close("cac:PayeeFinancialAccount");
+        w.close("cac:PaymentMeans");
+    }
+
+    for subtotal in invoice.subtotals.iter().filter(|subtotal| !subtotal.rounding.is_zero()) {
+        w.open("cac:AllowanceCharge", &[]);
+        w.text("cbc:ChargeIndicator", if subtotal.rounding > Money::ZERO { "true" } else { "false" });
+        w.text("cbc:AllowanceChargeReason", "Rounding");
+        write_amount(&mut w, "cbc:Amount", currency, subtotal.rounding.abs());
+        write_tax_category(&mut w, "cac:TaxCategory", subtotal.vat_category, subtotal.vat_perc, false);
+        w.close("cac:AllowanceCharge");
+    }
+
+    w.open("cac:TaxTotal", &[]);
+    write_amount(&mut w, "cbc:TaxAmount", currency, invoice.tax_total);
+    for subtotal in &invoice.subtotals {
+        w.open("cac:TaxSubtotal", &[]);
+        write_amount(&mut w, "cbc:TaxableAmount", currency, subtotal.taxable);
+        write_amount(&mut w, "cbc:TaxAmount", currency, subtotal.tax);
+        write_tax_category(&mut w, "cac:TaxCategory", subtotal.vat_category, subtotal.vat_perc, true);
+        w.close("cac:TaxSubtotal");
+    }
+    w.close("cac:TaxTotal");
+
+    w.open("cac:LegalMonetaryTotal", &[]);
+    write_amount(&mut w, "cbc:LineExtensionAmount", currency, invoice.line_total);
+    write_amount(&mut w, "cbc:TaxExclusiveAmount", currency, invoice.tax_exclusive);
+    write_amount(&mut w, "cbc:TaxInclusiveAmount", currency, invoice.payable);
+    if !invoice.allowance_total.is_zero() {
+        write_amount(&mut w, "cbc:AllowanceTotalAmount", currency, invoice.allowance_total);
+    }
+    if !invoice.charge_total.is_zero() {
+        write_amount(&mut w, "cbc:ChargeTotalAmount", currency, invoice.charge_total);
+    }
+    write_amount(&mut w, "cbc:PayableAmount", currency, invoice.payable);
+    w.close("cac:LegalMonetaryTotal");
+
+    for line in &invoice.lines {
+        w.open("cac:InvoiceLine", &[]);
+        w.text("cbc:ID", &line.id.to_string());
+        w.leaf("cbc:InvoicedQuantity", &[("unitCode", UNIT_CODE)], &line.quantity.to_string());
+        write_amount(&mut w, "cbc:LineExtensionAmount", currency, line.net);
+
+        if !line.discount.is_zero() {
+            w.open("cac:AllowanceCharge", &[]);
+            w.text("cbc:ChargeIndicator", "false");
+            w.text("cbc:AllowanceChargeReason", "Discount");
+            w.text("cbc:MultiplierFactorNumeric", &line.discount_perc.to_string());
+            write_amount(&mut w, "cbc:Amount", currency, line.discount);
+            write_amount(&mut w, "cbc:BaseAmount", currency, line.net + line.discount);
+            w.close("cac:AllowanceCharge");
+        }
+
+        w.open("cac:Item", &[]);
+        if !line.description.is_empty() {
+            w.text("cbc:Description", &line.description);
+        }
+        w.text("cbc:Name", &line.name);
+        if !line.product_id.trim().is_empty() {
+            w.open("cac:SellersItemIdentification", &[]);
+            w.text("cbc:ID", &line.product_id);
+            w.close("cac:SellersItemIdentification");
+        }
+        write_tax_category(&mut w, "cac:ClassifiedTaxCategory", line.vat_category, line.vat_perc, false);
+        w.close("cac:Item");
+
+        w.open("cac:Price", &[]);
+        w.leaf("cbc:PriceAmount", &[("currencyID", currency)], &line.price.to_string());
+        w.close("cac:Price");
+
+        w.close("cac:InvoiceLine");
+    }
+
+    w.close("Invoice");
+    w.finish()
+}
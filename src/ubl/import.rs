@@ -0,0 +1,487 @@
+use mysql::prelude::Queryable;
+use roxmltree::{Document, Node, NodeId};
+use rust_decimal::prelude::ToPrimitive;
+use serde::Serialize;
+use std::collections::HashSet;
+use crate::apis::pdf::{PdfCommonPayload, ItemRow, Address};
+use crate::appdata::Config;
+use crate::calculation::{calculate, Totals};
+use crate::countries;
+use crate::endpoints::exchange_rates::normalize_currency;
+use crate::endpoints::products::{ProductLookup, MatchedBy};
+use crate::endpoints::vat::apply_reverse_charge;
+use crate::money::Money;
+
+/**
+A field of the UBL document that has no place in an Invoicr document
+*/
+#[derive(Serialize)]
+pub struct UnmappedField {
+    /// Element path from the root, e.g. `Invoice/InvoiceLine[2]/Item/CommodityClassification/ItemClassificationCode`
+    pub path:               String,
+    pub value:              String
+}
+
+/**
+How a line of the document was linked to the product catalogue
+*/
+#[derive(Serialize)]
+pub struct LineReport {
+    pub line:               usize,
+    pub name:               String,
+    pub product_id:         Option<String>,
+    pub matched_by:         Option<MatchedBy>
+}
+
+/**
+A UBL Invoice or Quotation read into an Invoicr document. The template, language and number are not part of UBL and have to be set by the caller
+*/
+pub struct ImportedDocument {
+    /// The number the document had in the system it came from
+    pub source_id:          Option<String>,
+    pub payload:            PdfCommonPayload,
+    /// Seller item identifier (SKU) and name of every line, used to find the product
+    pub items:              Vec<(Option<String>, String)>,
+    pub seller_contact:     Option<String>,
+    /// Identifier the seller assigned to the buyer
+    pub buyer_id:           Option<String>,
+    /// The amount due as stated in the document
+    pub payable:            Option<Money>,
+    pub unmapped:           Vec<UnmappedField>,
+    pub warnings:           Vec<String>
+}
+
+impl ImportedDocument {
+    /**
+    Link the rows to products from the catalogue, by SKU first and by name otherwise. Rows without a match keep an empty product ID
+    */
+    pub fn link_products(&mut self, lookup: &ProductLookup) -> Vec<LineReport> {
+        self.payload.rows.iter_mut().zip(self.items.iter()).enumerate()
+            .map(|(index, (row, (sku, name)))| {
+                let found = lookup.find(sku.as_deref(), name);
+                if let Some((id, _)) = &found {
+                    row.id = id.clone();
+                }
+
+                LineReport {
+                    line: index + 1,
+                    name: name.clone(),
+                    product_id: found.as_ref().map(|(id, _)| id.clone()),
+                    matched_by: found.map(|(_, matched_by)| matched_by)
+                }
+            })
+            .collect()
+    }
+}
+
+/**
+Keeps track of the elements that have been mapped, everything else is reported as unmapped
+*/
+struct Reader {
+    used:       HashSet<NodeId>,
+    warnings:   Vec<String>
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn find<'a, 'input>(node: Node<'a, 'input>, path: &[&str]) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, name| child(node, name))
+}
+
+fn timestamp(date: &str) -> Option<i64> {
+    chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+}
+
+/**
+Path of an element for reporting, siblings with the same name are numbered from 1
+*/
+fn path(node: Node) -> String {
+    let mut parts: Vec<String> = node.ancestors()
+        .filter(|node| node.is_element())
+        .map(|node| {
+            let name = node.tag_name().name();
+            match node.parent() {
+                Some(parent) if children(parent, name).count() > 1 => {
+                    let position = children(parent, name).position(|sibling| sibling == node).unwrap_or_default();
+                    format!("{}[{}]", name, position + 1)
+                },
+                _ => name.to_string()
+            }
+        })
+        .collect();
+
+    parts.reverse();
+    parts.join("/")
+}
+
+impl Reader {
+    /// Mark an element and everything in it as mapped
+    fn consume(&mut self, node: Option<Node>) {
+        if let Some(node) = node {
+            self.used.insert(node.id());
+        }
+    }
+
+    fn text(&mut self, node: Node, path: &[&str]) -> Option<String> {
+        let node = find(node, path)?;
+        self.used.insert(node.id());
+        node.text().map(|text| text.trim().to_string()).filter(|text| !text.is_empty())
+    }
+
+    fn amount(&mut self, node: Node, path: &[&str]) -> Option<Money> {
+        let text = self.text(node, path)?;
+        match text.parse::<Money>() {
+            Ok(amount) => Some(amount),
+            Err(_) => {
+                self.warnings.push(format!("'{}' in {} is not a valid number and was ignored.", text, path.join("/")));
+                None
+            }
+        }
+    }
+
+    fn date(&mut self, node: Node, path: &[&str]) -> Option<i64> {
+        let text = self.text(node, path)?;
+        let date = timestamp(&text);
+        if date.is_none() {
+            self.warnings.push(format!("'{}' in {} is not a valid date and was ignored.", text, path.join("/")));
+        }
+
+        date
+    }
+
+    fn party(&mut self, party: Node, payload: &mut PdfCommonPayload) -> Option<String> {
+        if let Some(endpoint) = child(party, "EndpointID") {
+            self.used.insert(endpoint.id());
+            payload.electronic_address = match (endpoint.attribute("schemeID"), endpoint.text()) {
+                (Some(scheme), Some(id)) => Some(format!("{}:{}", scheme.trim(), id.trim())),
+                _ => None
+            };
+        }
+
+        let name = self.text(party, &["PartyName", "Name"]);
+        let legal_name = find(party, &["PartyLegalEntity", "RegistrationName"]).and_then(|node| node.text()).map(|text| text.trim().to_string());
+        payload.receiver = match (name, legal_name) {
+            (Some(name), Some(legal_name)) if name != legal_name => name,
+            (None, Some(_)) | (Some(_), Some(_)) => self.text(party, &["PartyLegalEntity", "RegistrationName"]).unwrap_or_default(),
+            (name, None) => name.unwrap_or_default()
+        };
+
+        payload.address = Address {
+            street: self.text(party, &["PostalAddress", "StreetName"]).unwrap_or_default(),
+            city: self.text(party, &["PostalAddress", "CityName"]).unwrap_or_default(),
+            postal_code: self.text(party, &["PostalAddress", "PostalZone"]).unwrap_or_default(),
+            country: self.text(party, &["PostalAddress", "Country", "IdentificationCode"]).unwrap_or_default(),
+            vat_number: self.text(party, &["PartyTaxScheme", "CompanyID"])
+        };
+
+        //The scheme of the buyer's tax registration is always VAT in Peppol
+        self.consume(child(party, "PartyTaxScheme"));
+
+        if !payload.address.country.is_empty() && countries::iso_code(&payload.address.country).is_none() {
+            self.warnings.push(format!("The country '{}' of the buyer is not known.", payload.address.country));
+        }
+
+        payload.attention_of = self.text(party, &["Contact", "Name"]);
+        self.text(party, &["PartyIdentification", "ID"])
+    }
+
+    fn line(&mut self, line: Node, number: usize) -> (ItemRow, Option<String>) {
+        self.consume(child(line, "ID"));
+        //Recalculated from the price, quantity and discount
+        self.consume(child(line, "LineExtensionAmount"));
+
+        let quantity = self.amount(line, &["InvoicedQuantity"]).or_else(|| self.amount(line, &["Quantity"])).unwrap_or(Money::ONE);
+        let base_quantity = self.amount(line, &["Price", "BaseQuantity"]).filter(|quantity| !quantity.is_zero()).unwrap_or(Money::ONE);
+        let mut price = self.amount(line, &["Price", "PriceAmount"]).unwrap_or_default() / base_quantity;
+
+        let mut discount_perc = None;
+        for allowance in children(line, "AllowanceCharge") {
+            if find(allowance, &["ChargeIndicator"]).and_then(|node| node.text()).map(str::trim) != Some("false") {
+                continue;
+            }
+
+            let perc = match self.amount(allowance, &["MultiplierFactorNumeric"]) {
+                Some(perc) => Some(perc),
+                None => match (self.amount(allowance, &["Amount"]), self.amount(allowance, &["BaseAmount"])) {
+                    (Some(amount), Some(base)) if !base.is_zero() => Some(amount / base * Money::ONE_HUNDRED),
+                    _ => None
+                }
+            };
+
+            //Invoicr has one discount per row, further allowances stay unmapped
+            if discount_perc.is_none() && perc.is_some() {
+                discount_perc = perc.map(|perc| perc.round_dp(6));
+                self.consume(Some(allowance));
+            }
+        }
+
+        let item = child(line, "Item");
+        let name = item.and_then(|item| self.text(item, &["Name"])).unwrap_or_default();
+        if name.is_empty() {
+            self.warnings.push(format!("Line {} has no item name.", number));
+        }
+
+        let vat_perc = match item.and_then(|item| self.amount(item, &["ClassifiedTaxCategory", "Percent"])) {
+            Some(vat_perc) => vat_perc,
+            None => {
+                self.warnings.push(format!("Line {} has no VAT percentage, 0% was used.", number));
+                Money::ZERO
+            }
+        };
+        self.consume(item.and_then(|item| child(item, "ClassifiedTaxCategory")));
+
+        let quantity = if quantity.fract().is_zero() {
+            quantity.to_i64().unwrap_or(1)
+        } else {
+            self.warnings.push(format!("Line {} has a fractional quantity of {}, it was imported as quantity 1 at the price of the whole line.", number, quantity.normalize()));
+            price *= quantity;
+            1
+        };
+
+        let row = ItemRow {
+            comment: self.text(line, &["Note"]),
+            id: String::new(),
+            name,
+            description: item.and_then(|item| self.text(item, &["Description"])).unwrap_or_default(),
+            discount_perc,
+            vat_perc: vat_perc.normalize(),
+            price: price.round_dp(6),
+            quantity
+        };
+
+        (row, item.and_then(|item| self.text(item, &["SellersItemIdentification", "ID"])))
+    }
+}
+
+/**
+Read a UBL 2.1 Invoice or Quotation. The buyer becomes the receiver, the seller is always taken from the configuration.
+Totals are recalculated from the lines
+*/
+pub fn parse(xml: &str) -> crate::Result<ImportedDocument> {
+    let document = Document::parse(xml).map_err(|e| format!("Invalid XML: {}", e))?;
+    let root = document.root_element();
+
+    let (buyer_path, seller_path, line_path, total_path): (&[&str], &[&str], &[&str], &str) = match root.tag_name().name() {
+        "Invoice" => (&["AccountingCustomerParty", "Party"], &["AccountingSupplierParty", "Party"], &["InvoiceLine"], "LegalMonetaryTotal"),
+        "Quotation" => (&["BuyerCustomerParty", "Party"], &["SellerSupplierParty", "Party"], &["QuotationLine", "LineItem"], "QuotedMonetaryTotal"),
+        other => return Err(format!("A UBL Invoice or Quotation was expected, the document is a '{}'", other))
+    };
+
+    let mut reader = Reader { used: HashSet::new(), warnings: Vec::new() };
+    for name in ["UBLVersionID", "CustomizationID", "ProfileID", "InvoiceTypeCode", "TaxTotal"] {
+        for node in children(root, name) {
+            reader.consume(Some(node));
+        }
+    }
+
+    //Rounding charges follow from the totals, which are recalculated
+    for node in children(root, "AllowanceCharge") {
+        if child(node, "AllowanceChargeReason").and_then(|reason| reason.text()).map(str::trim) == Some("Rounding") {
+            reader.consume(Some(node));
+        }
+    }
+
+    let source_id = reader.text(root, &["ID"]);
+    let creation_date = reader.date(root, &["IssueDate"]).ok_or("The document has no valid IssueDate")?;
+    let expiry_date = match reader.date(root, &["DueDate"])
+        .or_else(|| reader.date(root, &["PaymentMeans", "PaymentDueDate"]))
+        .or_else(|| reader.date(root, &["ValidityPeriod", "EndDate"])) {
+        Some(date) => date,
+        None => {
+            reader.warnings.push("The document has no due date or end of validity, the issue date was used.".to_string());
+            creation_date
+        }
+    };
+
+    let currency = match reader.text(root, &["DocumentCurrencyCode"]).or_else(|| reader.text(root, &["PricingCurrencyCode"])) {
+        Some(currency) => Some(normalize_currency(&currency)?),
+        None => None
+    };
+
+    let notes: Vec<String> = children(root, "Note").filter_map(|note| {
+        reader.used.insert(note.id());
+        note.text().map(|text| text.trim().to_string()).filter(|text| !text.is_empty())
+    }).collect();
+
+    let reference = reader.text(root, &["BuyerReference"])
+        .or_else(|| reader.text(root, &["OrderReference", "ID"]))
+        .or_else(|| reader.text(root, &["RequestForQuotationDocumentReference", "ID"]))
+        .unwrap_or_default();
+
+    let mut payload = PdfCommonPayload {
+        template_name: String::new(),
+        language: String::new(),
+        id: 0,
+        attention_of: None,
+        receiver: String::new(),
+        reference,
+        notes: if notes.is_empty() { None } else { Some(notes.join("\n")) },
+        expiry_date,
+        creation_date,
+        rows: Vec::new(),
+        address: Address { city: String::new(), country: String::new(), postal_code: String::new(), street: String::new(), vat_number: None },
+        currency,
+        exchange_rate: None,
+        electronic_address: None,
+        reverse_charge: false
+    };
+
+    let buyer_id = match find(root, buyer_path) {
+        Some(party) => reader.party(party, &mut payload),
+        None => return Err(format!("The document has no {}", buyer_path[0]))
+    };
+
+    let seller = find(root, seller_path);
+    let seller_contact = seller.and_then(|seller| find(seller, &["Contact", "Name"])).and_then(|node| node.text()).map(|text| text.trim().to_string());
+    reader.consume(seller.and_then(|seller| seller.parent()));
+
+    let mut items = Vec::new();
+    for (index, node) in children(root, line_path[0]).enumerate() {
+        reader.consume(child(node, "ID"));
+        let line = match line_path.get(1) {
+            Some(name) => match child(node, name) {
+                Some(line) => line,
+                None => continue
+            },
+            None => node
+        };
+
+        let (row, sku) = reader.line(line, index + 1);
+        items.push((sku, row.name.clone()));
+        payload.rows.push(row);
+    }
+
+    if payload.rows.is_empty() {
+        reader.warnings.push("The document has no lines.".to_string());
+    }
+
+    let payable = find(root, &[total_path]).and_then(|total| reader.amount(total, &["PayableAmount"]));
+    reader.consume(child(root, total_path));
+
+    let unmapped = root.descendants()
+        .filter(|node| node.is_element() && !node.children().any(|child| child.is_element()))
+        .filter(|node| !node.ancestors().any(|ancestor| reader.used.contains(&ancestor.id())))
+        .filter_map(|node| {
+            let value = node.text().map(str::trim).unwrap_or_default();
+            if value.is_empty() {
+                return None;
+            }
+
+            Some(UnmappedField { path: path(node), value: value.to_string() })
+        })
+        .collect();
+
+    Ok(ImportedDocument {
+        source_id,
+        payload,
+        items,
+        seller_contact,
+        buyer_id,
+        payable,
+        unmapped,
+        warnings: reader.warnings
+    })
+}
+
+/**
+An imported document linked to the product catalogue, with its totals recalculated
+*/
+pub struct Import {
+    pub document:           ImportedDocument,
+    pub lines:              Vec<LineReport>,
+    pub totals:             Totals
+}
+
+/**
+The steps shared by the invoice and quote imports: read the UBL document, set the template and language, link the rows to
+products and apply reverse charge. A receiver VAT number that is not valid rejects the document, without it the VAT of
+the rows can not be decided
+*/
+pub fn prepare<Q: Queryable>(conn: &mut Q, config: &Config, body: &[u8], template_name: &str, language: &str) -> mysql::Result<crate::Result<Import>> {
+    let xml = match std::str::from_utf8(body) {
+        Ok(xml) => xml,
+        Err(_) => return Ok(Err("File is not valid UTF-8".to_string()))
+    };
+
+    let mut document = match parse(xml) {
+        Ok(document) => document,
+        Err(err) => return Ok(Err(err))
+    };
+
+    document.payload.template_name = template_name.to_string();
+    document.payload.language = language.to_string();
+
+    let lines = document.link_products(&ProductLookup::load(conn)?);
+
+    match apply_reverse_charge(config, &mut document.payload) {
+        Ok(true) => document.warnings.push("The VAT is reverse charged, all rows are set to 0%.".to_string()),
+        Ok(false) => {},
+        Err(err) => return Ok(Err(format!("{} Correct it in the document and import it again.", err)))
+    }
+
+    let totals = calculate(&document.payload.rows, config.rounding);
+    if let Some(payable) = document.payable {
+        if payable != totals.total {
+            document.warnings.push(format!("The document states {} as payable amount, the recalculated total is {}.", payable.normalize(), totals.total));
+        }
+    }
+
+    Ok(Ok(Import { document, lines, totals }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculation::calculate;
+    use crate::money::Rounding;
+    use crate::ubl::{export, UblInvoice};
+    use crate::ubl::tests::{invoice, seller, money};
+
+    #[test]
+    fn an_exported_invoice_imports_unchanged() {
+        let original = invoice();
+        let ubl = UblInvoice::from_invoice(&original, &seller(), "EUR", Rounding::Total);
+        let imported = parse(&export::to_xml(&ubl)).unwrap();
+
+        assert_eq!(imported.source_id, Some(original.id.to_string()));
+        assert_eq!(imported.payload.receiver, original.receiver);
+        assert_eq!(imported.payload.reference, original.reference);
+        assert_eq!(imported.payload.currency, original.currency);
+        assert_eq!(imported.payload.creation_date, original.creation_date);
+        assert_eq!(imported.payload.expiry_date, original.expiry_date);
+        assert_eq!(imported.payload.address.street, original.address.street);
+        assert_eq!(imported.payload.address.postal_code, original.address.postal_code);
+        assert_eq!(imported.payload.address.country, "DE");
+        assert_eq!(imported.payload.address.vat_number, original.address.vat_number);
+        assert_eq!(imported.payload.electronic_address, original.electronic_address);
+
+        assert_eq!(imported.payload.rows.len(), original.rows.len());
+        for (row, expected) in imported.payload.rows.iter().zip(original.rows.iter()) {
+            assert_eq!(row.name, expected.name);
+            assert_eq!(row.price, expected.price);
+            assert_eq!(row.quantity, expected.quantity);
+            assert_eq!(row.discount_perc, expected.discount_perc);
+            assert_eq!(row.vat_perc, expected.vat_perc);
+        }
+        assert_eq!(imported.items[0], (Some("P-1".to_string()), "Consulting".to_string()));
+        assert_eq!(imported.items[1], (None, "Sticker".to_string()));
+
+        assert_eq!(imported.payable, Some(money("197.41")));
+        assert_eq!(calculate(&imported.payload.rows, Rounding::Total).total, money("197.41"));
+        assert!(imported.unmapped.iter().all(|field| !field.path.contains("AllowanceCharge")), "{:?}", imported.unmapped.iter().map(|field| &field.path).collect::<Vec<_>>());
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+    }
+
+    #[test]
+    fn other_documents_are_rejected() {
+        assert!(parse("<CreditNote/>").is_err());
+        assert!(parse("not xml").is_err());
+    }
+}
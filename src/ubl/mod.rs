@@ -0,0 +1,347 @@
+pub mod export;
+pub mod import;
+pub mod validation;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use std::collections::BTreeMap;
+use crate::apis::pdf::PdfCommonPayload;
+use crate::appdata::Seller;
+use crate::calculation::calculate;
+use crate::countries;
+use crate::money::{Money, Rounding};
+
+pub const CUSTOMIZATION_ID: &str = "urn:cen.eu:en16931:2017#compliant#urn:fdc:peppol.eu:2017:poacc:billing:3.0";
+pub const PROFILE_ID: &str = "urn:fdc:peppol.eu:2017:poacc:billing:01:1.0";
+
+/// UNCL 4461 payment means code for SEPA credit transfer
+const PAYMENT_MEANS_SEPA: &str = "58";
+
+/**
+A party on an e-invoice
+*/
+pub struct Party {
+    pub name:               String,
+    pub street:             String,
+    pub city:               String,
+    pub postal_code:        String,
+    /// The country as written in the address
+    pub country:            String,
+    /// ISO 3166-1 alpha-2 code, `None` if the country could not be recognized
+    pub country_code:       Option<&'static str>,
+    pub vat_number:         Option<String>,
+    pub company_id:         Option<String>,
+    /// Peppol participant identifier, split into scheme and identifier
+    pub endpoint:           Option<(String, String)>,
+    pub contact:            Option<String>,
+    pub email:              Option<String>
+}
+
+/**
+An invoice line with amounts rounded to cents
+*/
+pub struct Line {
+    pub id:                 usize,
+    pub product_id:         String,
+    pub name:               String,
+    pub description:        String,
+    pub quantity:           i64,
+    pub price:              Money,
+    pub discount_perc:      Money,
+    pub discount:           Money,
+    pub net:                Money,
+    pub vat_category:       &'static str,
+    pub vat_perc:           Money
+}
+
+/**
+Taxable amount and VAT per VAT category and rate
+*/
+pub struct TaxSubtotal {
+    pub vat_category:       &'static str,
+    pub vat_perc:           Money,
+    pub taxable:            Money,
+    pub tax:                Money,
+    /// Taxable amount minus the sum of the rounded line amounts, written as a document level charge or allowance
+    pub rounding:           Money
+}
+
+/**
+An invoice in the terms of EN 16931, ready to be written as UBL
+*/
+pub struct UblInvoice {
+    pub id:                 String,
+    pub issue_date:         NaiveDate,
+    pub due_date:           NaiveDate,
+    pub currency:           String,
+    pub note:               Option<String>,
+    pub buyer_reference:    String,
+    pub seller:             Party,
+    pub buyer:              Party,
+    pub payment_means:      Option<(&'static str, String)>,
+    pub lines:              Vec<Line>,
+    pub subtotals:          Vec<TaxSubtotal>,
+    pub line_total:         Money,
+    /// Sum of the negative rounding differences, as a positive amount
+    pub allowance_total:    Money,
+    /// Sum of the positive rounding differences
+    pub charge_total:       Money,
+    pub tax_exclusive:      Money,
+    pub tax_total:          Money,
+    pub payable:            Money
+}
+
+/// VATEX code for intra-Community reverse charge, required with category AE
+pub const EXEMPTION_REVERSE_CHARGE: &str = "VATEX-EU-AE";
+
+/**
+Work out the VAT category code (UNCL 5305) of a row from its VAT percentage and whether the VAT is reverse charged
+*/
+pub fn vat_category(vat_perc: Money, reverse_charge: bool) -> &'static str {
+    if reverse_charge {
+        "AE"
+    } else if vat_perc.is_zero() {
+        "Z"
+    } else {
+        "S"
+    }
+}
+
+fn date(timestamp: i64) -> NaiveDate {
+    Utc.timestamp_opt(timestamp, 0).single().map(|date| date.date_naive()).unwrap_or_default()
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        value => Some(value.to_string())
+    }
+}
+
+/**
+Split a Peppol participant identifier `scheme:identifier` into its parts
+*/
+fn endpoint(address: &str) -> Option<(String, String)> {
+    let (scheme, id) = address.trim().split_once(':')?;
+    match (scheme.trim(), id.trim()) {
+        ("", _) | (_, "") => None,
+        (scheme, id) => Some((scheme.to_string(), id.to_string()))
+    }
+}
+
+impl UblInvoice {
+    /**
+    Build an e-invoice from a stored invoice. Line amounts are rounded to cents as UBL requires. The VAT breakdown and totals are
+    those of the PDF, calculated with `rounding`. With `Rounding::Total` the rounded lines may not add up to the taxable amount
+    of their rate, the difference is written as a document level charge or allowance
+    */
+    pub fn from_invoice(invoice: &PdfCommonPayload, seller: &Seller, base_currency: &str, rounding: Rounding) -> Self {
+        let rounded = calculate(&invoice.rows, Rounding::Line);
+        let totals = calculate(&invoice.rows, rounding);
+        let lines: Vec<Line> = invoice.rows.iter().zip(rounded.lines.iter()).enumerate()
+            .map(|(index, (row, line))| Line {
+                id: index + 1,
+                product_id: row.id.clone(),
+                name: row.name.clone(),
+                description: row.description.clone(),
+                quantity: row.quantity,
+                price: row.price.normalize(),
+                discount_perc: row.discount_perc.unwrap_or_default().normalize(),
+                discount: line.discount,
+                net: line.net,
+                vat_category: vat_category(row.vat_perc, invoice.reverse_charge),
+                vat_perc: line.vat_perc
+            })
+            .collect();
+
+        let mut per_rate: BTreeMap<Money, (&'static str, Money)> = BTreeMap::new();
+        for line in &lines {
+            per_rate.entry(line.vat_perc).or_insert((line.vat_category, Money::ZERO)).1 += line.net;
+        }
+
+        let subtotals: Vec<TaxSubtotal> = totals.vat_rates.iter()
+            .map(|rate| {
+                let (vat_category, lines_net) = per_rate.get(&rate.vat_perc).copied().unwrap_or((vat_category(rate.vat_perc, invoice.reverse_charge), Money::ZERO));
+                TaxSubtotal {
+                    vat_category,
+                    vat_perc: rate.vat_perc,
+                    taxable: rate.base,
+                    tax: rate.vat,
+                    rounding: rate.base - lines_net
+                }
+            })
+            .collect();
+
+        let line_total = lines.iter().map(|line| line.net).sum();
+        let allowance_total = subtotals.iter().filter(|subtotal| subtotal.rounding < Money::ZERO).map(|subtotal| -subtotal.rounding).sum();
+        let charge_total = subtotals.iter().filter(|subtotal| subtotal.rounding > Money::ZERO).map(|subtotal| subtotal.rounding).sum();
+
+        Self {
+            id: invoice.id.to_string(),
+            issue_date: date(invoice.creation_date),
+            due_date: date(invoice.expiry_date),
+            currency: invoice.currency.clone().unwrap_or_else(|| base_currency.to_string()),
+            note: invoice.notes.as_deref().and_then(non_empty),
+            buyer_reference: invoice.reference.trim().to_string(),
+            seller: Party {
+                name: seller.name.clone(),
+                street: seller.street.clone(),
+                city: seller.city.clone(),
+                postal_code: seller.postal_code.clone(),
+                country: seller.country.clone(),
+                country_code: countries::iso_code(&seller.country),
+                vat_number: non_empty(&seller.vat_number),
+                company_id: non_empty(&seller.company_id),
+                endpoint: endpoint(&seller.electronic_address),
+                contact: None,
+                email: non_empty(&seller.email)
+            },
+            buyer: Party {
+                name: invoice.receiver.clone(),
+                street: invoice.address.street.clone(),
+                city: invoice.address.city.clone(),
+                postal_code: invoice.address.postal_code.clone(),
+                country: invoice.address.country.clone(),
+                country_code: countries::iso_code(&invoice.address.country),
+                vat_number: invoice.address.vat_number.clone(),
+                company_id: None,
+                endpoint: invoice.electronic_address.as_deref().and_then(endpoint),
+                contact: invoice.attention_of.as_deref().and_then(non_empty),
+                email: None
+            },
+            payment_means: non_empty(&seller.iban).map(|iban| (PAYMENT_MEANS_SEPA, iban.replace(' ', ""))),
+            lines,
+            subtotals,
+            line_total,
+            allowance_total,
+            charge_total,
+            tax_exclusive: totals.net,
+            tax_total: totals.vat,
+            payable: totals.total
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::apis::pdf::{Address, ItemRow};
+
+    pub fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    fn row(id: &str, name: &str, price: &str, quantity: i64, discount_perc: Option<&str>, vat_perc: &str) -> ItemRow {
+        ItemRow {
+            comment: None,
+            id: id.to_string(),
+            name: name.to_string(),
+            description: String::new(),
+            discount_perc: discount_perc.map(money),
+            vat_perc: money(vat_perc),
+            price: money(price),
+            quantity
+        }
+    }
+
+    pub fn seller() -> Seller {
+        Seller {
+            name: "Invoicr B.V.".to_string(),
+            street: "Stationsplein 1".to_string(),
+            city: "Utrecht".to_string(),
+            postal_code: "3511 ED".to_string(),
+            country: "NL".to_string(),
+            vat_number: "NL000099998B57".to_string(),
+            company_id: "12345678".to_string(),
+            iban: "NL91 ABNA 0417 1643 00".to_string(),
+            email: "billing@example.com".to_string(),
+            electronic_address: "0106:12345678".to_string()
+        }
+    }
+
+    /// A consulting row at 9% and three sticker rows at 21% that round differently per line than in total
+    pub fn invoice() -> PdfCommonPayload {
+        PdfCommonPayload {
+            template_name: "default".to_string(),
+            language: "en".to_string(),
+            id: 20240001,
+            attention_of: Some("Jane Doe".to_string()),
+            receiver: "Acme GmbH".to_string(),
+            reference: "PO-42".to_string(),
+            notes: Some("Thank you".to_string()),
+            expiry_date: 1711929600,
+            creation_date: 1709251200,
+            rows: vec![
+                row("P-1", "Consulting", "100", 2, Some("10"), "9"),
+                row("", "Sticker", "0.333", 1, None, "21"),
+                row("", "Sticker", "0.333", 1, None, "21"),
+                row("", "Sticker", "0.333", 1, None, "21")
+            ],
+            address: Address {
+                city: "Berlin".to_string(),
+                country: "Germany".to_string(),
+                postal_code: "10115".to_string(),
+                street: "Invalidenstraße 1".to_string(),
+                vat_number: Some("DE123456788".to_string())
+            },
+            currency: Some("EUR".to_string()),
+            exchange_rate: Some(Money::ONE),
+            electronic_address: Some("9930:DE123456788".to_string()),
+            reverse_charge: false
+        }
+    }
+
+    #[test]
+    fn line_rounding_needs_no_rounding_charge() {
+        let ubl = UblInvoice::from_invoice(&invoice(), &seller(), "EUR", Rounding::Line);
+
+        assert!(ubl.subtotals.iter().all(|subtotal| subtotal.rounding.is_zero()));
+        assert_eq!(ubl.line_total, money("180.99"));
+        assert_eq!(ubl.tax_exclusive, money("180.99"));
+        assert_eq!(ubl.tax_total, money("16.41"));
+        assert_eq!(ubl.payable, money("197.40"));
+    }
+
+    #[test]
+    fn total_rounding_is_balanced_with_a_charge() {
+        let ubl = UblInvoice::from_invoice(&invoice(), &seller(), "EUR", Rounding::Total);
+        let standard = ubl.subtotals.iter().find(|subtotal| subtotal.vat_category == "S" && subtotal.vat_perc == money("21")).unwrap();
+
+        assert_eq!(standard.taxable, money("1.00"));
+        assert_eq!(standard.rounding, money("0.01"));
+        assert_eq!(ubl.line_total, money("180.99"));
+        assert_eq!(ubl.charge_total, money("0.01"));
+        assert_eq!(ubl.allowance_total, Money::ZERO);
+        assert_eq!(ubl.tax_exclusive, ubl.line_total + ubl.charge_total - ubl.allowance_total);
+        assert_eq!(ubl.payable, calculate(&invoice().rows, Rounding::Total).total);
+    }
+
+    #[test]
+    fn reverse_charge_uses_category_ae() {
+        let mut invoice = invoice();
+        invoice.reverse_charge = true;
+        for row in invoice.rows.iter_mut() {
+            row.vat_perc = Money::ZERO;
+        }
+
+        let ubl = UblInvoice::from_invoice(&invoice, &seller(), "EUR", Rounding::Line);
+        assert!(ubl.lines.iter().all(|line| line.vat_category == "AE"));
+        assert_eq!(ubl.tax_total, Money::ZERO);
+        assert!(export::to_xml(&ubl).contains(EXEMPTION_REVERSE_CHARGE));
+    }
+
+    #[test]
+    fn a_complete_invoice_passes_validation() {
+        let ubl = UblInvoice::from_invoice(&invoice(), &seller(), "EUR", Rounding::Total);
+        let violations: Vec<_> = validation::validate(&ubl).into_iter().map(|violation| violation.rule).collect();
+
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+
+    #[test]
+    fn rows_without_product_have_no_seller_item_identification() {
+        let xml = export::to_xml(&UblInvoice::from_invoice(&invoice(), &seller(), "EUR", Rounding::Line));
+
+        assert_eq!(xml.matches("<cac:SellersItemIdentification>").count(), 1);
+        assert!(xml.contains("<cbc:ID>P-1</cbc:ID>"));
+    }
+}
@@ -0,0 +1,71 @@
+use serde::Serialize;
+use crate::money::Money;
+use crate::ubl::{UblInvoice, Party};
+
+/**
+A business rule of EN 16931 or Peppol BIS Billing 3.0 the invoice does not satisfy
+*/
+#[derive(Serialize)]
+pub struct RuleViolation {
+    /// Identifier of the rule as used in the specification, e.g. `BR-06`
+    pub rule:       &'static str,
+    pub message:    String
+}
+
+struct Violations(Vec<RuleViolation>);
+
+impl Violations {
+    fn check(&mut self, ok: bool, rule: &'static str, message: impl Into<String>) {
+        if !ok {
+            self.0.push(RuleViolation { rule, message: message.into() });
+        }
+    }
+}
+
+fn check_address(v: &mut Violations, party: &Party, rule: &'static str, who: &str) {
+    v.check(party.country_code.is_some(), rule, format!("The country of the {} ('{}') is not a known ISO 3166-1 country.", who, party.country));
+}
+
+/**
+Check the invoice against the mandatory business rules of EN 16931 and Peppol BIS Billing 3.0 that
+can fail for an invoice stored in Invoicr. An empty result means the invoice may be sent over Peppol
+*/
+pub fn validate(invoice: &UblInvoice) -> Vec<RuleViolation> {
+    let mut v = Violations(Vec::new());
+
+    v.check(!invoice.id.is_empty(), "BR-02", "An invoice shall have an invoice number.");
+    v.check(invoice.due_date >= invoice.issue_date, "BR-CO-25", "The payment due date shall not be before the issue date.");
+    v.check(invoice.currency.len() == 3, "BR-05", format!("The invoice currency code '{}' is not a valid ISO 4217 code.", invoice.currency));
+
+    v.check(!invoice.seller.name.trim().is_empty(), "BR-06", "An invoice shall contain the seller name. Configure the seller details.");
+    v.check(!invoice.buyer.name.trim().is_empty(), "BR-07", "An invoice shall contain the buyer name.");
+    v.check(!invoice.seller.street.trim().is_empty() || !invoice.seller.city.trim().is_empty(), "BR-08", "An invoice shall contain the seller postal address.");
+    v.check(!invoice.seller.country.trim().is_empty(), "BR-09", "The seller postal address shall contain a country code.");
+    check_address(&mut v, &invoice.seller, "BR-09", "seller");
+    v.check(!invoice.buyer.street.trim().is_empty() || !invoice.buyer.city.trim().is_empty(), "BR-10", "An invoice shall contain the buyer postal address.");
+    v.check(!invoice.buyer.country.trim().is_empty(), "BR-11", "The buyer postal address shall contain a country code.");
+    check_address(&mut v, &invoice.buyer, "BR-11", "buyer");
+
+    v.check(!invoice.lines.is_empty(), "BR-16", "An invoice shall have at least one invoice line.");
+    for line in &invoice.lines {
+        v.check(!line.name.trim().is_empty(), "BR-25", format!("Invoice line {} shall contain the item name.", line.id));
+        v.check(line.price >= Money::ZERO, "BR-27", format!("The item net price of invoice line {} shall not be negative.", line.id));
+    }
+
+    let has_standard_rate = invoice.lines.iter().any(|line| line.vat_category == "S");
+    v.check(!has_standard_rate || invoice.seller.vat_number.is_some(), "BR-S-02",
+        "An invoice with standard rated VAT shall contain the seller VAT identifier. Configure the seller VAT number.");
+
+    let reverse_charged = invoice.lines.iter().any(|line| line.vat_category == "AE");
+    v.check(!reverse_charged || (invoice.seller.vat_number.is_some() && invoice.buyer.vat_number.is_some()), "BR-AE-02",
+        "A reverse charged invoice shall contain both the seller and the buyer VAT identifier.");
+    for line in invoice.lines.iter().filter(|line| line.vat_category == "AE") {
+        v.check(line.vat_perc.is_zero(), "BR-AE-05", format!("The VAT rate of reverse charged invoice line {} shall be 0.", line.id));
+    }
+
+    v.check(!invoice.buyer_reference.is_empty(), "PEPPOL-EN16931-R003", "A buyer reference or purchase order reference shall be provided. Set the reference of the invoice.");
+    v.check(invoice.buyer.endpoint.is_some(), "PEPPOL-EN16931-R010", "The buyer electronic address shall be provided as 'scheme:identifier'.");
+    v.check(invoice.seller.endpoint.is_some(), "PEPPOL-EN16931-R020", "The seller electronic address shall be provided as 'scheme:identifier'. Configure the seller electronic address.");
+
+    v.0
+}
@@ -0,0 +1,474 @@
+/**
+Remove the separators people write VAT numbers with and convert to upper case
+*/
+pub fn normalize(vat_number: &str) -> String {
+    vat_number.chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '.' | '-' | '/'))
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/**
+The ISO 3166-1 country code of a VAT number. Greece uses the prefix EL instead of its ISO code GR
+*/
+pub fn country(vat_number: &str) -> Option<&'static str> {
+    let prefix = vat_number.get(..2)?;
+    let code = if prefix == "EL" { "GR" } else { prefix };
+    crate::countries::iso_code(code).filter(|code| crate::countries::is_eu(code))
+}
+
+fn digits(number: &str) -> Option<Vec<u32>> {
+    number.chars().map(|c| c.to_digit(10)).collect()
+}
+
+fn is_digits(number: &str, lengths: &[usize]) -> bool {
+    lengths.contains(&number.len()) && number.chars().all(|c| c.is_ascii_digit())
+}
+
+fn weighted(digits: &[u32], weights: &[u32]) -> u32 {
+    digits.iter().zip(weights.iter()).map(|(d, w)| d * w).sum()
+}
+
+/// Luhn checksum, zero for a valid number
+fn luhn(digits: &[u32]) -> u32 {
+    digits.iter().rev().enumerate()
+        .map(|(i, d)| if i % 2 == 1 { let d = d * 2; d / 10 + d % 10 } else { *d })
+        .sum::<u32>() % 10
+}
+
+/// ISO 7064 Mod 11, 10, valid when the number including its check digit gives 1
+fn mod_11_10(digits: &[u32]) -> u32 {
+    digits.iter().fold(5, |check, d| ((if check == 0 { 10 } else { check }) * 2 % 11 + d) % 10)
+}
+
+/// ISO 7064 Mod 97, 10 over a number with letters, A = 10 to Z = 35
+fn mod_97_10(number: &str) -> Option<u32> {
+    number.chars().try_fold(0u32, |rest, c| {
+        let value = c.to_digit(36)?;
+        Some(if value < 10 { (rest * 10 + value) % 97 } else { (rest * 100 + value) % 97 })
+    })
+}
+
+fn check_at(number: &str) -> bool {
+    match number.strip_prefix('U').filter(|n| is_digits(n, &[8])).and_then(digits) {
+        Some(d) => (6 + 10 - luhn(&d[..7])) % 10 == d[7],
+        None => false
+    }
+}
+
+fn check_be(number: &str) -> bool {
+    let number = if number.len() == 9 { format!("0{}", number) } else { number.to_string() };
+    if !is_digits(&number, &[10]) || !number.starts_with(['0', '1']) {
+        return false;
+    }
+
+    let base: u64 = number[..8].parse().unwrap();
+    let check: u64 = number[8..].parse().unwrap();
+    97 - base % 97 == check
+}
+
+fn check_bg(number: &str) -> bool {
+    let d = match digits(number) {
+        Some(d) if d.len() == 9 || d.len() == 10 => d,
+        _ => return false
+    };
+
+    if d.len() == 9 {
+        let mut check = weighted(&d[..8], &[1, 2, 3, 4, 5, 6, 7, 8]) % 11;
+        if check == 10 {
+            check = weighted(&d[..8], &[3, 4, 5, 6, 7, 8, 9, 10]) % 11;
+        }
+        return check % 10 == d[8];
+    }
+
+    //Ten digits is a personal number, a foreigner's number or another kind of organisation
+    let personal = weighted(&d[..9], &[2, 4, 8, 5, 10, 9, 7, 3, 6]) % 11 % 10 == d[9];
+    let foreigner = weighted(&d[..9], &[21, 19, 17, 13, 11, 9, 7, 3, 1]) % 10 == d[9];
+    let other = match 11 - weighted(&d[..9], &[4, 3, 2, 7, 6, 5, 4, 3, 2]) % 11 {
+        11 => d[9] == 0,
+        10 => false,
+        check => check == d[9]
+    };
+
+    personal || foreigner || other
+}
+
+fn check_cy(number: &str) -> bool {
+    let (body, letter) = match (number.get(..8), number.get(8..)) {
+        (Some(body), Some(letter)) if is_digits(body, &[8]) && letter.len() == 1 => (body, letter),
+        _ => return false
+    };
+
+    if body.starts_with("12") {
+        return false;
+    }
+
+    const ODD: [u32; 10] = [1, 0, 5, 7, 9, 13, 15, 17, 19, 21];
+    let sum: u32 = digits(body).unwrap().iter().enumerate()
+        .map(|(i, d)| if i % 2 == 0 { ODD[*d as usize] } else { *d })
+        .sum();
+
+    letter.chars().next() == char::from_u32('A' as u32 + sum % 26)
+}
+
+fn check_cz(number: &str) -> bool {
+    let d = match digits(number) {
+        Some(d) => d,
+        None => return false
+    };
+
+    match d.len() {
+        //Legal entities
+        8 => d[0] != 9 && match weighted(&d[..7], &[8, 7, 6, 5, 4, 3, 2]) % 11 {
+            0 => 1,
+            1 => 0,
+            rest => 11 - rest
+        } == d[7],
+        //Birth numbers from before 1954 have no check digit
+        9 => true,
+        10 => {
+            let value: u64 = number.parse().unwrap();
+            value.is_multiple_of(11) || (value / 10 % 11 == 10 && d[9] == 0)
+        },
+        _ => false
+    }
+}
+
+fn check_de(number: &str) -> bool {
+    is_digits(number, &[9]) && !number.starts_with('0') && mod_11_10(&digits(number).unwrap()) == 1
+}
+
+fn check_dk(number: &str) -> bool {
+    is_digits(number, &[8]) && !number.starts_with('0') && weighted(&digits(number).unwrap(), &[2, 7, 6, 5, 4, 3, 2, 1]).is_multiple_of(11)
+}
+
+fn check_ee(number: &str) -> bool {
+    is_digits(number, &[9]) && number.starts_with("10") && weighted(&digits(number).unwrap(), &[3, 7, 1, 3, 7, 1, 3, 7, 1]).is_multiple_of(10)
+}
+
+fn check_el(number: &str) -> bool {
+    let number = if number.len() == 8 { format!("0{}", number) } else { number.to_string() };
+    if !is_digits(&number, &[9]) {
+        return false;
+    }
+
+    let d = digits(&number).unwrap();
+    d[..8].iter().fold(0, |check, d| (check + d) * 2) % 11 % 10 == d[8]
+}
+
+fn check_es(number: &str) -> bool {
+    const LETTERS: &[u8] = b"TRWAGMYFPDXBNJZSQVHLCKE";
+    if number.len() != 9 || !number[1..8].chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let first = number.chars().next().unwrap();
+    let last = number.chars().last().unwrap();
+    let dni = |digits: &str| digits.parse::<usize>().map(|value| LETTERS[value % 23] as char == last).unwrap_or(false);
+
+    match first {
+        //Spanish citizens
+        '0'..='9' => dni(&number[..8]),
+        //Foreigners, X, Y and Z stand for 0, 1 and 2
+        'X' | 'Y' | 'Z' => dni(&format!("{}{}", first as u32 - 'X' as u32, &number[1..8])),
+        'K' | 'L' | 'M' => dni(&number[1..8]),
+        //Legal entities
+        'A'..='H' | 'J' | 'N' | 'P'..='S' | 'U' | 'V' | 'W' => {
+            let d = digits(&number[1..8]).unwrap();
+            let sum: u32 = d.iter().enumerate()
+                .map(|(i, d)| if i % 2 == 0 { let d = d * 2; d / 10 + d % 10 } else { *d })
+                .sum();
+            let check = (10 - sum % 10) % 10;
+            last.to_digit(10) == Some(check) || last == (b"JABCDEFGHI"[check as usize] as char)
+        },
+        _ => false
+    }
+}
+
+fn check_fi(number: &str) -> bool {
+    if !is_digits(number, &[8]) {
+        return false;
+    }
+
+    let d = digits(number).unwrap();
+    let check = (11 - weighted(&d[..7], &[7, 9, 10, 5, 8, 4, 2]) % 11) % 11;
+    check != 10 && check == d[7]
+}
+
+fn check_fr(number: &str) -> bool {
+    let (key, siren) = match (number.get(..2), number.get(2..)) {
+        (Some(key), Some(siren)) if number.len() == 11 && is_digits(siren, &[9]) => (key, siren),
+        _ => return false
+    };
+
+    if !siren.starts_with("000") && luhn(&digits(siren).unwrap()) != 0 {
+        return false;
+    }
+
+    match key.parse::<u64>() {
+        Ok(key) => key == (12 + 3 * (siren.parse::<u64>().unwrap() % 97)) % 97,
+        //Newer numbers have an alphanumeric key that can not be checked
+        Err(_) => key.chars().all(|c| c.is_ascii_alphanumeric() && c != 'I' && c != 'O')
+    }
+}
+
+fn check_hr(number: &str) -> bool {
+    is_digits(number, &[11]) && mod_11_10(&digits(number).unwrap()) == 1
+}
+
+fn check_hu(number: &str) -> bool {
+    is_digits(number, &[8]) && weighted(&digits(number).unwrap(), &[9, 7, 3, 1, 9, 7, 3, 1]).is_multiple_of(10)
+}
+
+fn check_ie(number: &str) -> bool {
+    const ALPHABET: &str = "WABCDEFGHIJKLMNOPQRSTUV";
+    let chars: Vec<char> = number.chars().collect();
+
+    //Old style numbers have a letter, + or * as second character, they are rewritten to the new style
+    let number = if chars.len() == 8 && chars[0].is_ascii_digit() && (chars[1].is_ascii_uppercase() || chars[1] == '+' || chars[1] == '*')
+        && chars[2..7].iter().all(char::is_ascii_digit) && chars[7].is_ascii_uppercase() {
+        format!("0{}{}{}", chars[2..7].iter().collect::<String>(), chars[0], chars[7])
+    } else {
+        number.to_string()
+    };
+
+    if !(number.len() == 8 || number.len() == 9) || !is_digits(&number[..7], &[7]) {
+        return false;
+    }
+
+    let extra = match number.get(8..9) {
+        Some(letter) => match ALPHABET.find(letter) {
+            Some(index) => index as u32,
+            None => return false
+        },
+        None => 0
+    };
+
+    let sum = weighted(&digits(&number[..7]).unwrap(), &[8, 7, 6, 5, 4, 3, 2]) + 9 * extra;
+    ALPHABET.chars().nth((sum % 23) as usize) == number.chars().nth(7)
+}
+
+fn check_it(number: &str) -> bool {
+    if !is_digits(number, &[11]) || number[..7] == *"0000000" {
+        return false;
+    }
+
+    let office: u32 = number[7..10].parse().unwrap();
+    ((1..=100).contains(&office) || [120, 121, 888, 999].contains(&office)) && luhn(&digits(number).unwrap()) == 0
+}
+
+fn check_lt(number: &str) -> bool {
+    let d = match digits(number) {
+        Some(d) if (d.len() == 9 && d[7] == 1) || (d.len() == 12 && d[10] == 1) => d,
+        _ => return false
+    };
+
+    let body = &d[..d.len() - 1];
+    let mut check: u32 = body.iter().enumerate().map(|(i, d)| (1 + i as u32 % 9) * d).sum::<u32>() % 11;
+    if check == 10 {
+        check = body.iter().enumerate().map(|(i, d)| (1 + (i as u32 + 2) % 9) * d).sum::<u32>() % 11;
+    }
+
+    check % 10 == d[d.len() - 1]
+}
+
+fn check_lu(number: &str) -> bool {
+    is_digits(number, &[8]) && number[..6].parse::<u32>().unwrap() % 89 == number[6..].parse::<u32>().unwrap()
+}
+
+fn check_lv(number: &str) -> bool {
+    if !is_digits(number, &[11]) {
+        return false;
+    }
+
+    let d = digits(number).unwrap();
+    if d[0] > 3 {
+        //Legal entities
+        weighted(&d, &[9, 1, 4, 8, 3, 10, 2, 5, 7, 6, 1]) % 11 == 3
+    } else {
+        //Personal codes
+        (1101 - weighted(&d[..10], &[1, 6, 3, 7, 9, 10, 5, 8, 4, 2])) % 11 % 10 == d[10]
+    }
+}
+
+fn check_mt(number: &str) -> bool {
+    is_digits(number, &[8]) && !number.starts_with('0') && weighted(&digits(number).unwrap(), &[3, 4, 6, 7, 8, 9, 10, 1]).is_multiple_of(37)
+}
+
+fn check_nl(number: &str) -> bool {
+    let (body, suffix) = match (number.get(..9), number.get(9..)) {
+        (Some(body), Some(suffix)) if is_digits(body, &[9]) && suffix.len() == 3 && suffix.starts_with('B') && is_digits(&suffix[1..], &[2]) => (body, suffix),
+        _ => return false
+    };
+
+    //Numbers issued before 2020 pass the eleven test, numbers of sole proprietors issued since pass Mod 97, 10
+    let d = digits(body).unwrap();
+    let eleven = (weighted(&d[..8], &[9, 8, 7, 6, 5, 4, 3, 2]) + 11 - d[8]).is_multiple_of(11);
+    eleven || mod_97_10(&format!("NL{}{}", body, suffix)) == Some(1)
+}
+
+fn check_pl(number: &str) -> bool {
+    if !is_digits(number, &[10]) {
+        return false;
+    }
+
+    let d = digits(number).unwrap();
+    weighted(&d[..9], &[6, 5, 7, 2, 3, 4, 5, 6, 7]) % 11 == d[9]
+}
+
+fn check_pt(number: &str) -> bool {
+    if !is_digits(number, &[9]) || number.starts_with('0') {
+        return false;
+    }
+
+    let d = digits(number).unwrap();
+    (11 - weighted(&d[..8], &[9, 8, 7, 6, 5, 4, 3, 2]) % 11) % 11 % 10 == d[8]
+}
+
+fn check_ro(number: &str) -> bool {
+    if !is_digits(number, &[2, 3, 4, 5, 6, 7, 8, 9, 10]) || number.starts_with('0') {
+        return false;
+    }
+
+    let d = digits(&format!("{:0>10}", number)).unwrap();
+    10 * weighted(&d[..9], &[7, 5, 3, 2, 1, 7, 5, 3, 2]) % 11 % 10 == d[9]
+}
+
+fn check_se(number: &str) -> bool {
+    is_digits(number, &[12]) && number.ends_with("01") && luhn(&digits(&number[..10]).unwrap()) == 0
+}
+
+fn check_si(number: &str) -> bool {
+    if !is_digits(number, &[8]) || number.starts_with('0') {
+        return false;
+    }
+
+    let d = digits(number).unwrap();
+    match 11 - weighted(&d[..7], &[8, 7, 6, 5, 4, 3, 2]) % 11 {
+        10 => d[7] == 0,
+        11 => false,
+        check => check == d[7]
+    }
+}
+
+fn check_sk(number: &str) -> bool {
+    is_digits(number, &[10]) && !number.starts_with('0') && matches!(number.as_bytes()[2], b'2' | b'3' | b'4' | b'7' | b'8' | b'9')
+        && number.parse::<u64>().unwrap() % 11 == 0
+}
+
+/**
+Check the format and check digits of an EU VAT number, written with its country prefix.
+Returns the number without separators
+*/
+pub fn validate(vat_number: &str) -> crate::Result<String> {
+    let vat_number = normalize(vat_number);
+
+    //The checks below index by byte, which is only safe for ASCII
+    if !vat_number.is_ascii() {
+        return Err(format!("'{}' contains characters that can not be part of a VAT number", vat_number));
+    }
+
+    let number = vat_number.get(2..).unwrap_or_default();
+
+    let valid = match vat_number.get(..2).unwrap_or_default() {
+        "AT" => check_at(number),
+        "BE" => check_be(number),
+        "BG" => check_bg(number),
+        "CY" => check_cy(number),
+        "CZ" => check_cz(number),
+        "DE" => check_de(number),
+        "DK" => check_dk(number),
+        "EE" => check_ee(number),
+        "EL" => check_el(number),
+        "ES" => check_es(number),
+        "FI" => check_fi(number),
+        "FR" => check_fr(number),
+        "HR" => check_hr(number),
+        "HU" => check_hu(number),
+        "IE" => check_ie(number),
+        "IT" => check_it(number),
+        "LT" => check_lt(number),
+        "LU" => check_lu(number),
+        "LV" => check_lv(number),
+        "MT" => check_mt(number),
+        "NL" => check_nl(number),
+        "PL" => check_pl(number),
+        "PT" => check_pt(number),
+        "RO" => check_ro(number),
+        "SE" => check_se(number),
+        "SI" => check_si(number),
+        "SK" => check_sk(number),
+        _ => return Err(format!("'{}' does not start with the prefix of an EU member state", vat_number))
+    };
+
+    if valid {
+        Ok(vat_number)
+    } else {
+        Err(format!("'{}' is not a valid VAT number", vat_number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Published example numbers, at least one per member state and one per variant where a country has several
+    const VALID: &[&str] = &[
+        "ATU13585627", "BE0403019261", "BE403019261", "BG175074752", "BG7523169263", "CY10259033P", "CZ25123891", "CZ7103192745",
+        "CZ640903926", "DE136695976", "DK13585628", "EE100931558", "EL094259216", "ESA13585625", "ESX2482300W", "ES54362315K",
+        "FI20774740", "FR40303265045", "FRK7399859412", "HR33392005961", "HU12892312", "IE6433435F", "IE8D79739I", "IT00743110157",
+        "LT119511515", "LT100001919017", "LU15027442", "LV40003521600", "LV16117519997", "MT11679112", "NL004495445B01",
+        "NL002455799B11", "PL8567346215", "PT501964843", "RO18547290", "SE123456789701", "SI50223054", "SK2022749619"
+    ];
+
+    /// The numbers above with a wrong check digit
+    const INVALID: &[&str] = &[
+        "ATU13585626", "BE0403019262", "BG175074753", "CY10259033A", "CZ25123892", "CZ7103192746", "DE136695977", "DK13585627",
+        "EE100931559", "EL094259217", "ESA13585626", "ESX2482300A", "ES54362315A", "FI20774741", "FR41303265045", "HR33392005962",
+        "HU12892313", "IE6433435A", "IT00743110158", "LT119511516", "LT100001919018", "LU15027443", "LV40003521601",
+        "MT11679113", "NL004495446B01", "PL8567346216", "PT501964844", "RO18547291", "SE123456789801", "SI50223055", "SK2022749618"
+    ];
+
+    #[test]
+    fn valid_numbers_pass() {
+        for number in VALID {
+            assert_eq!(validate(number).as_deref(), Ok(*number));
+        }
+    }
+
+    #[test]
+    fn wrong_check_digits_fail() {
+        for number in INVALID {
+            assert!(validate(number).is_err(), "{}", number);
+        }
+    }
+
+    #[test]
+    fn numbers_are_normalized() {
+        assert_eq!(validate("nl 0044.95.445-b01"), Ok("NL004495445B01".to_string()));
+        assert_eq!(normalize(" de 136/695/976 "), "DE136695976");
+    }
+
+    #[test]
+    fn wrong_lengths_and_prefixes_fail() {
+        for number in ["", "N", "NL", "NL004495445", "NL004495445B0", "DE13669597", "DE1366959760", "US123456789", "GB980780684", "GR094259216"] {
+            assert!(validate(number).is_err(), "{}", number);
+        }
+    }
+
+    #[test]
+    fn non_ascii_input_fails_without_panicking() {
+        for number in ["NL00449544５B01", "NLé04495445B01", "ÉS54362315K", "DE١٣٦٦٩٥٩٧٦", "IE643343ÄF", "CY1025903ß", "ES5436231ÅK", "FR€0303265045"] {
+            assert!(validate(number).is_err(), "{}", number);
+        }
+    }
+
+    #[test]
+    fn country_maps_the_prefix() {
+        assert_eq!(country("NL004495445B01"), Some("NL"));
+        assert_eq!(country("EL094259216"), Some("GR"));
+        assert_eq!(country("US123456789"), None);
+        assert_eq!(country("N"), None);
+        assert_eq!(country("Ñ1"), None);
+    }
+}
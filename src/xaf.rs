@@ -0,0 +1,512 @@
+use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+use mysql::prelude::Queryable;
+use mysql::{Row, Params, params};
+use sha2::{Sha256, Digest};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::apis::pdf::PdfCommonPayload;
+use crate::appdata::Config;
+use crate::calculation::calculate;
+use crate::countries;
+use crate::endpoints::invoice::{get_issued_invoices, get_credit_notes};
+use crate::money::{self, Money};
+use crate::xml::{self, Writer};
+
+const NAMESPACE: &str = "http://www.auditfiles.nl/XAF/3.2";
+
+/**
+Parse an inclusive date range given as two `YYYY-MM-DD` dates. An auditfile covers a single fiscal year,
+so both dates have to be in the same calendar year
+*/
+pub fn parse_range(from: &str, to: &str) -> crate::Result<(NaiveDate, NaiveDate)> {
+    let from = NaiveDate::parse_from_str(from.trim(), "%Y-%m-%d").map_err(|_| format!("Invalid start date '{}', expected YYYY-MM-DD", from))?;
+    let to = NaiveDate::parse_from_str(to.trim(), "%Y-%m-%d").map_err(|_| format!("Invalid end date '{}', expected YYYY-MM-DD", to))?;
+    if to < from {
+        return Err("The end date is before the start date".to_string());
+    }
+
+    if to.year() != from.year() {
+        return Err(format!("The range spans {} to {}, export one auditfile per year", from.year(), to.year()));
+    }
+
+    Ok((from, to))
+}
+
+fn timestamp(date: NaiveDate) -> i64 {
+    date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()
+}
+
+fn date(timestamp: i64) -> NaiveDate {
+    Utc.timestamp_opt(timestamp, 0).single().map(|date| date.date_naive()).unwrap_or_default()
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+/**
+Invoicr has no customer records, customers are identified by their receiver name.
+The ID is derived from the name so it stays the same in every auditfile
+*/
+fn customer_id(receiver: &str) -> String {
+    let hash = format!("{:X}", Sha256::digest(receiver.trim().to_lowercase().as_bytes()));
+    format!("C{}", &hash[..10])
+}
+
+fn vat_id(vat_perc: Money) -> String {
+    format!("V{}", vat_perc.normalize())
+}
+
+struct Customer {
+    name:           String,
+    street:         String,
+    city:           String,
+    postal_code:    String,
+    country:        String,
+    vat_number:     Option<String>
+}
+
+/**
+A line of a journal transaction. Positive amounts are debit, negative amounts credit, VAT follows the sign of its line
+*/
+struct TransactionLine {
+    account:        String,
+    doc_ref:        String,
+    date:           NaiveDate,
+    description:    String,
+    amount:         Money,
+    customer_id:    Option<String>,
+    product:        Option<(String, i64)>,
+    vat:            Option<(Money, Money)>,
+    currency:       Option<(String, Money)>
+}
+
+struct Transaction {
+    number:         String,
+    description:    String,
+    date:           NaiveDate,
+    lines:          Vec<TransactionLine>
+}
+
+struct Journal {
+    id:             &'static str,
+    description:    &'static str,
+    kind:           &'static str,
+    transactions:   Vec<Transaction>
+}
+
+fn debit_credit(amount: Money) -> (Money, &'static str) {
+    if amount < Money::ZERO {
+        (-amount, "C")
+    } else {
+        (amount, "D")
+    }
+}
+
+/**
+Book a sales document: revenue and VAT per rate on the credit side, the total on receivables.
+Every amount is taken from the rounded totals and converted on its own, a difference from converting them separately
+is booked on the largest revenue line, so the receivable is the converted total that the bank journal clears.
+Credit notes are booked with `sign` -1, which reverses every line
+*/
+fn book_document(config: &Config, document: &PdfCommonPayload, sign: Money, description: String, vat_rates: &mut BTreeSet<Money>) -> Transaction {
+    let totals = calculate(&document.rows, config.rounding);
+    let accounts = &config.ledger_accounts;
+    let doc_ref = document.id.to_string();
+    let date = date(document.creation_date);
+    let to_base = |amount: Money| money::to_base(amount, document.exchange_rate);
+
+    let mut revenue = Vec::new();
+    for rate in &totals.vat_rates {
+        vat_rates.insert(rate.vat_perc);
+        revenue.push(TransactionLine {
+            account: accounts.revenue.clone(),
+            doc_ref: doc_ref.clone(),
+            date,
+            description: format!("Revenue VAT {}%", rate.vat_perc),
+            amount: -sign * to_base(rate.base),
+            customer_id: None,
+            product: None,
+            vat: Some((rate.vat_perc, -sign * to_base(rate.vat))),
+            currency: None
+        });
+    }
+
+    let mut vat = Vec::new();
+    for rate in totals.vat_rates.iter().filter(|rate| !rate.vat.is_zero()) {
+        vat.push(TransactionLine {
+            account: accounts.vat_payable.clone(),
+            doc_ref: doc_ref.clone(),
+            date,
+            description: format!("VAT {}%", rate.vat_perc),
+            amount: -sign * to_base(rate.vat),
+            customer_id: None,
+            product: None,
+            vat: None,
+            currency: None
+        });
+    }
+
+    let receivable = sign * to_base(totals.total);
+    let difference = -receivable - revenue.iter().chain(vat.iter()).map(|line| line.amount).sum::<Money>();
+    if let Some(line) = revenue.iter_mut().max_by_key(|line| line.amount.abs()) {
+        line.amount += difference;
+    }
+
+    let currency = document.currency.clone().filter(|currency| currency != &config.base_currency);
+    let mut lines = vec![TransactionLine {
+        account: accounts.receivables.clone(),
+        doc_ref: doc_ref.clone(),
+        date,
+        description: description.clone(),
+        amount: receivable,
+        customer_id: Some(customer_id(&document.receiver)),
+        product: None,
+        vat: None,
+        currency: currency.map(|currency| (currency, sign * totals.total))
+    }];
+    lines.append(&mut revenue);
+    lines.append(&mut vat);
+
+    Transaction { number: doc_ref, description, date, lines }
+}
+
+fn customer(document: &PdfCommonPayload) -> Customer {
+    Customer {
+        name: document.receiver.clone(),
+        street: document.address.street.clone(),
+        city: document.address.city.clone(),
+        postal_code: document.address.postal_code.clone(),
+        country: document.address.country.clone(),
+        vat_number: document.address.vat_number.clone()
+    }
+}
+
+fn write_address(w: &mut Writer, street: &str, city: &str, postal_code: &str, country: &str) {
+    w.open("streetAddress", &[]);
+    w.text("streetname", street);
+    w.text("city", city);
+    w.text("postalCode", postal_code);
+    if let Some(code) = countries::iso_code(country) {
+        w.text("country", code);
+    }
+    w.close("streetAddress");
+}
+
+/**
+Build an XAF 3.2 auditfile for the inclusive date range `[from, to]` within one year. It contains the issued invoices and
+credit notes in the sales journal and the recorded payments in the bank journal, in the base currency
+*/
+pub fn build<Q: Queryable>(conn: &mut Q, config: &Config, from: NaiveDate, to: NaiveDate) -> mysql::Result<String> {
+    let start = timestamp(from);
+    let end = timestamp(to.succ_opt().unwrap_or(to));
+
+    let invoices = get_issued_invoices(conn, start, end)?;
+    let credit_notes = get_credit_notes(conn, start, end)?;
+    let payments = conn.exec::<Row, &str, Params>("SELECT payments.*, invoices.receiver, invoices.city, invoices.country, invoices.postal_code, invoices.street, invoices.vat_number, invoices.exchange_rate \
+        FROM payments INNER JOIN invoices ON invoices.id = payments.invoice_id \
+        WHERE payments.payment_date >= :from AND payments.payment_date < :to ORDER BY payments.payment_date, payments.id", params! {
+        "from" => start,
+        "to" => end
+    })?;
+
+    let mut customers: BTreeMap<String, Customer> = BTreeMap::new();
+    let mut vat_rates = BTreeSet::new();
+
+    let mut sales = Journal { id: "VK", description: "Sales", kind: "S", transactions: Vec::new() };
+    for invoice in &invoices {
+        customers.insert(customer_id(&invoice.receiver), customer(invoice));
+        sales.transactions.push(book_document(config, invoice, Money::ONE, format!("Invoice {} {}", invoice.id, invoice.receiver), &mut vat_rates));
+    }
+
+    for credit_note in &credit_notes {
+        customers.insert(customer_id(&credit_note.common.receiver), customer(&credit_note.common));
+        let description = format!("Credit note {} on invoice {}", credit_note.common.id, credit_note.invoice_id);
+        sales.transactions.push(book_document(config, &credit_note.common, -Money::ONE, description, &mut vat_rates));
+    }
+
+    sales.transactions.sort_by_key(|transaction| transaction.date);
+
+    let mut bank = Journal { id: "BNK", description: "Bank", kind: "B", transactions: Vec::new() };
+    for payment in &payments {
+        let receiver: String = payment.get("receiver").unwrap();
+        let id = customer_id(&receiver);
+        customers.entry(id.clone()).or_insert_with(|| Customer {
+            name: receiver,
+            street: payment.get("street").unwrap(),
+            city: payment.get("city").unwrap(),
+            postal_code: payment.get("postal_code").unwrap(),
+            country: payment.get("country").unwrap(),
+            vat_number: payment.get::<Option<String>, &str>("vat_number").unwrap()
+        });
+
+        let invoice_id: i64 = payment.get("invoice_id").unwrap();
+        let date = date(payment.get("payment_date").unwrap());
+        let amount = money::to_base(payment.get("amount").unwrap(), payment.get("exchange_rate").unwrap());
+        let description = match payment.get::<Option<String>, &str>("reference").unwrap() {
+            Some(reference) => format!("Payment on invoice {} ({})", invoice_id, reference),
+            None => format!("Payment on invoice {}", invoice_id)
+        };
+
+        let line = |account: &str, amount: Money, customer_id: Option<String>| TransactionLine {
+            account: account.to_string(),
+            doc_ref: invoice_id.to_string(),
+            date,
+            description: description.clone(),
+            amount,
+            customer_id,
+            product: None,
+            vat: None,
+            currency: None
+        };
+
+        //Payment IDs are random, the bank journal numbers its transactions in order of payment instead
+        bank.transactions.push(Transaction {
+            number: (bank.transactions.len() + 1).to_string(),
+            description: description.clone(),
+            date,
+            lines: vec![
+                line(&config.ledger_accounts.bank, amount, None),
+                line(&config.ledger_accounts.receivables, -amount, Some(id))
+            ]
+        });
+    }
+
+    //One period per calendar month in the range
+    let mut periods = Vec::new();
+    let mut month = NaiveDate::from_ymd_opt(from.year(), from.month(), 1).unwrap();
+    while month <= to {
+        let next = if month.month() == 12 {
+            NaiveDate::from_ymd_opt(month.year() + 1, 1, 1).unwrap()
+        } else {
+            NaiveDate::from_ymd_opt(month.year(), month.month() + 1, 1).unwrap()
+        };
+
+        periods.push((month.max(from), next.pred_opt().unwrap().min(to)));
+        month = next;
+    }
+
+    let period_number = |date: NaiveDate| periods.iter().position(|(start, end)| date >= *start && date <= *end).map(|index| index + 1).unwrap_or(1);
+
+    let journals = [sales, bank];
+    let all_lines = journals.iter().flat_map(|journal| journal.transactions.iter()).flat_map(|transaction| transaction.lines.iter());
+    let (lines_count, total_debit, total_credit) = all_lines.fold((0, Money::ZERO, Money::ZERO), |(count, debit, credit), line| {
+        match debit_credit(line.amount) {
+            (amount, "D") => (count + 1, debit + amount, credit),
+            (amount, _) => (count + 1, debit, credit + amount)
+        }
+    });
+
+    let seller = &config.seller;
+    let accounts = &config.ledger_accounts;
+    let mut w = Writer::new();
+    w.open("auditfile", &[("xmlns", NAMESPACE)]);
+
+    w.open("header", &[]);
+    w.text("fiscalYear", &from.year().to_string());
+    w.text("startDate", &format_date(from));
+    w.text("endDate", &format_date(to));
+    w.text("curCode", &config.base_currency);
+    w.text("dateCreated", &format_date(Utc::now().date_naive()));
+    w.text("softwareDesc", "Invoicr");
+    w.text("softwareVersion", env!("CARGO_PKG_VERSION"));
+    w.close("header");
+
+    w.open("company", &[]);
+    w.text("companyIdent", &seller.company_id);
+    w.text("companyName", &seller.name);
+    w.text("taxRegistrationCountry", countries::iso_code(&seller.country).unwrap_or(&seller.country));
+    w.text("taxRegIdent", &seller.vat_number);
+    write_address(&mut w, &seller.street, &seller.city, &seller.postal_code, &seller.country);
+
+    w.open("customersSuppliers", &[]);
+    for (id, customer) in &customers {
+        w.open("customerSupplier", &[]);
+        w.text("custSupID", id);
+        w.text("custSupName", &customer.name);
+        w.optional("taxRegIdent", &customer.vat_number);
+        w.text("custSupTp", "C");
+        write_address(&mut w, &customer.street, &customer.city, &customer.postal_code, &customer.country);
+        w.close("customerSupplier");
+    }
+    w.close("customersSuppliers");
+
+    w.open("generalLedger", &[]);
+    for (account, description, kind) in [
+        (&accounts.bank, "Bank", "B"),
+        (&accounts.receivables, "Accounts receivable", "B"),
+        (&accounts.vat_payable, "VAT payable", "B"),
+        (&accounts.revenue, "Revenue", "P")
+    ] {
+        w.open("ledgerAccount", &[]);
+        w.text("accID", account);
+        w.text("accDesc", description);
+        w.text("accTp", kind);
+        w.close("ledgerAccount");
+    }
+    w.close("generalLedger");
+
+    w.open("vatCodes", &[]);
+    for vat_perc in &vat_rates {
+        w.open("vatCode", &[]);
+        w.text("vatID", &vat_id(*vat_perc));
+        w.text("vatDesc", &format!("VAT {}%", vat_perc));
+        w.text("vatToPayAccID", &accounts.vat_payable);
+        w.close("vatCode");
+    }
+    w.close("vatCodes");
+
+    w.open("periods", &[]);
+    for (index, (start, end)) in periods.iter().enumerate() {
+        w.open("period", &[]);
+        w.text("periodNumber", &(index + 1).to_string());
+        w.text("periodDesc", &start.format("%B %Y").to_string());
+        w.text("startDatePeriod", &format_date(*start));
+        w.text("endDatePeriod", &format_date(*end));
+        w.close("period");
+    }
+    w.close("periods");
+
+    w.open("transactions", &[]);
+    w.text("linesCount", &lines_count.to_string());
+    w.text("totalDebit", &xml::amount(total_debit));
+    w.text("totalCredit", &xml::amount(total_credit));
+
+    for journal in &journals {
+        w.open("journal", &[]);
+        w.text("jrnID", journal.id);
+        w.text("desc", journal.description);
+        w.text("jrnTp", journal.kind);
+
+        for transaction in &journal.transactions {
+            let debit: Money = transaction.lines.iter().map(|line| line.amount).filter(|amount| *amount > Money::ZERO).sum();
+
+            w.open("transaction", &[]);
+            w.text("nr", &transaction.number);
+            w.text("desc", &transaction.description);
+            w.text("periodNumber", &period_number(transaction.date).to_string());
+            w.text("trDt", &format_date(transaction.date));
+            w.text("amnt", &xml::amount(debit));
+            w.text("amntTp", "D");
+
+            for (index, line) in transaction.lines.iter().enumerate() {
+                let (amount, amount_type) = debit_credit(line.amount);
+
+                w.open("trLine", &[]);
+                w.text("nr", &(index + 1).to_string());
+                w.text("accID", &line.account);
+                w.text("docRef", &line.doc_ref);
+                w.text("effDate", &format_date(line.date));
+                w.text("desc", &line.description);
+                w.text("amnt", &xml::amount(amount));
+                w.text("amntTp", amount_type);
+                w.optional("custSupID", &line.customer_id);
+                if let Some((product_id, quantity)) = &line.product {
+                    if !product_id.is_empty() {
+                        w.text("prodID", product_id);
+                    }
+                    w.text("qntity", &quantity.to_string());
+                }
+
+                if let Some((vat_perc, vat_amount)) = line.vat {
+                    let (vat_amount, vat_type) = debit_credit(vat_amount);
+                    w.open("vat", &[]);
+                    w.text("vatID", &vat_id(vat_perc));
+                    w.text("vatPerc", &vat_perc.to_string());
+                    w.text("vatAmnt", &xml::amount(vat_amount));
+                    w.text("vatAmntTp", vat_type);
+                    w.close("vat");
+                }
+
+                if let Some((currency, currency_amount)) = &line.currency {
+                    w.open("currency", &[]);
+                    w.text("curCode", currency);
+                    w.text("curAmnt", &xml::amount(currency_amount.abs()));
+                    w.close("currency");
+                }
+
+                w.close("trLine");
+            }
+
+            w.close("transaction");
+        }
+
+        w.close("journal");
+    }
+
+    w.close("transactions");
+    w.close("company");
+    w.close("auditfile");
+
+    Ok(w.finish())
+}
+
+/**
+Command line export: `--export-xaf FROM TO [FILE]`. Writes the auditfile to FILE, or to `auditfile-FROM-TO.xaf`, and returns the path
+*/
+pub fn export_to_file(pool: &mysql::Pool, config: &Config, args: &[String]) -> crate::Result<String> {
+    let (from, to) = match args {
+        [from, to, ..] => parse_range(from, to)?,
+        _ => return Err("Usage: --export-xaf FROM TO [FILE], dates as YYYY-MM-DD".to_string())
+    };
+
+    let path = args.get(2).cloned().unwrap_or_else(|| format!("auditfile-{}-{}.xaf", from, to));
+    let mut conn = pool.get_conn().map_err(|e| format!("Failed to create database connection: {:?}", e))?;
+    let auditfile = build(&mut conn, config, from, to).map_err(|e| format!("Failed to build auditfile: {:?}", e))?;
+    std::fs::write(&path, auditfile).map_err(|e| format!("Failed to write '{}': {}", path, e))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Rounding;
+
+    #[test]
+    fn parse_range_accepts_dates_within_a_year() {
+        assert_eq!(parse_range("2024-01-01", "2024-12-31"), Ok((NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), NaiveDate::from_ymd_opt(2024, 12, 31).unwrap())));
+        assert_eq!(parse_range(" 2024-02-29 ", "2024-02-29").map(|(from, _)| from), Ok(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()));
+    }
+
+    #[test]
+    fn parse_range_rejects_invalid_ranges() {
+        assert!(parse_range("2024-03-01", "2024-02-01").is_err());
+        assert!(parse_range("2023-12-01", "2024-01-31").is_err());
+        assert!(parse_range("2023-02-29", "2023-03-01").is_err());
+        assert!(parse_range("01-01-2024", "2024-12-31").is_err());
+        assert!(parse_range("2024-01-01", "").is_err());
+    }
+
+    #[test]
+    fn debit_credit_splits_on_sign() {
+        assert_eq!(debit_credit("12.50".parse().unwrap()), ("12.50".parse().unwrap(), "D"));
+        assert_eq!(debit_credit("-12.50".parse().unwrap()), ("12.50".parse().unwrap(), "C"));
+    }
+
+    #[test]
+    fn customer_ids_ignore_case_and_whitespace() {
+        assert_eq!(customer_id("Acme GmbH"), customer_id(" acme gmbh "));
+        assert_ne!(customer_id("Acme GmbH"), customer_id("Acme B.V."));
+        assert_eq!(customer_id("Acme GmbH").len(), 11);
+    }
+
+    #[test]
+    fn documents_are_booked_on_the_converted_total() {
+        let mut document = crate::ubl::tests::invoice();
+        document.currency = Some("USD".to_string());
+        document.exchange_rate = Some("1.1".parse().unwrap());
+
+        for rounding in [Rounding::Line, Rounding::Total] {
+            let config = Config { rounding, ..Config::default() };
+            let totals = calculate(&document.rows, rounding);
+
+            for sign in [Money::ONE, -Money::ONE] {
+                let transaction = book_document(&config, &document, sign, String::new(), &mut BTreeSet::new());
+
+                assert_eq!(transaction.lines[0].amount, sign * money::to_base(totals.total, document.exchange_rate));
+                assert_eq!(transaction.lines.iter().map(|line| line.amount).sum::<Money>(), Money::ZERO);
+                assert!(transaction.lines.iter().all(|line| money::round(line.amount) == line.amount));
+            }
+        }
+    }
+}
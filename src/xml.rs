@@ -0,0 +1,75 @@
+use crate::money::Money;
+
+/**
+Minimal XML writer for the documents Invoicr exports, indenting by element depth
+*/
+pub struct Writer {
+    out:    String,
+    depth:  usize
+}
+
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// Amounts are always written with two decimals
+pub fn amount(value: Money) -> String {
+    format!("{:.2}", value)
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self { out: String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"), depth: 0 }
+    }
+
+    fn start_tag(&mut self, tag: &str, attrs: &[(&str, &str)]) {
+        self.out.push_str(&"  ".repeat(self.depth));
+        self.out.push('<');
+        self.out.push_str(tag);
+        for (name, value) in attrs {
+            self.out.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+        self.out.push('>');
+    }
+
+    pub fn open(&mut self, tag: &str, attrs: &[(&str, &str)]) {
+        self.start_tag(tag, attrs);
+        self.out.push('\n');
+        self.depth += 1;
+    }
+
+    pub fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.out.push_str(&format!("{}</{}>\n", "  ".repeat(self.depth), tag));
+    }
+
+    pub fn leaf(&mut self, tag: &str, attrs: &[(&str, &str)], value: &str) {
+        self.start_tag(tag, attrs);
+        self.out.push_str(&format!("{}</{}>\n", escape(value), tag));
+    }
+
+    pub fn text(&mut self, tag: &str, value: &str) {
+        self.leaf(tag, &[], value);
+    }
+
+    pub fn optional(&mut self, tag: &str, value: &Option<String>) {
+        if let Some(value) = value {
+            self.text(tag, value);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}